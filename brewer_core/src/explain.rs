@@ -0,0 +1,70 @@
+use std::fmt::{Display, Formatter};
+use std::process::Command;
+
+use serde::Serialize;
+
+/// Mutating operations which brew commands can be explained without running them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Install,
+    Uninstall,
+    Upgrade,
+    Cleanup,
+}
+
+/// A brew command as it would be run, with the environment overrides applied on top
+/// of the inherited environment
+#[derive(Clone, Debug, Serialize)]
+pub struct Invocation {
+    pub env: Vec<(String, String)>,
+    pub argv: Vec<String>,
+}
+
+impl From<&Command> for Invocation {
+    fn from(command: &Command) -> Self {
+        let env = command
+            .get_envs()
+            .filter_map(|(key, value)| {
+                Some((
+                    key.to_string_lossy().to_string(),
+                    value?.to_string_lossy().to_string(),
+                ))
+            })
+            .collect();
+
+        let argv = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|s| s.to_string_lossy().to_string())
+            .collect();
+
+        Invocation { env, argv }
+    }
+}
+
+impl Display for Invocation {
+    /// Shell-ready form, e.g. `HOMEBREW_NO_AUTO_UPDATE=1 brew install --formulae jq`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let env = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("{key}={}", quote(value)));
+
+        let argv = self.argv.iter().map(|arg| quote(arg));
+
+        write!(f, "{}", env.chain(argv).collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Single quotes the argument unless it consists of characters safe for the shell
+fn quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c));
+
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
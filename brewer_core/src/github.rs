@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use log::{info, warn};
+use reqwest::blocking::Response;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::models::{github, keg};
+
+const GITHUB_GRAPHQL_URL: &str = "https://api.github.com/graphql";
+
+/// GitHub limits how many nodes a single query may touch
+const BATCH_SIZE: usize = 100;
+
+/// Longest wait for a rate limit to reset, longer limits stop fetching
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Fetched repositories, incomplete when GitHub rate limited the requests
+pub struct Fetched {
+    pub repos: keg::Store<github::Repository>,
+    pub complete: bool,
+}
+
+/// Fetches stars and archived flags of the given "owner/repo" repositories
+/// using batched GraphQL queries. Missing or inaccessible repositories are skipped.
+///
+/// Rate limited requests are retried after the wait GitHub asks for, unless it is too long
+pub fn repositories(token: &str, repos: &[String]) -> anyhow::Result<Fetched> {
+    let client = reqwest::blocking::Client::new();
+
+    let mut store = keg::Store::with_capacity(repos.len());
+
+    let repos: Vec<_> = repos.iter().filter(|r| is_valid(r)).collect();
+
+    for (i, batch) in repos.chunks(BATCH_SIZE).enumerate() {
+        info!("fetching GitHub metadata, batch {}", i + 1);
+
+        let fields: Vec<_> = batch
+            .iter()
+            .enumerate()
+            .filter_map(|(j, repo)| {
+                let (owner, name) = repo.split_once('/')?;
+
+                Some(format!(
+                    r#"r{j}: repository(owner: "{owner}", name: "{name}") {{ stargazerCount isArchived }}"#
+                ))
+            })
+            .collect();
+
+        let query = format!("query {{ {} }}", fields.join(" "));
+        let body = serde_json::to_vec(&json!({ "query": query }))?;
+
+        let mut attempt = 0;
+
+        let response = loop {
+            attempt += 1;
+
+            let response = client
+                .post(GITHUB_GRAPHQL_URL)
+                .bearer_auth(token)
+                .header("User-Agent", "brewer")
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+                .send()?;
+
+            if !rate_limited(&response) {
+                break response;
+            }
+
+            let wait = backoff(response.headers(), attempt);
+
+            if attempt >= MAX_ATTEMPTS || wait > MAX_BACKOFF {
+                warn!("GitHub rate limit reached, stopping at batch {}", i + 1);
+
+                return Ok(Fetched {
+                    repos: store,
+                    complete: false,
+                });
+            }
+
+            info!("GitHub rate limit reached, retrying in {}s", wait.as_secs());
+
+            std::thread::sleep(wait);
+        };
+
+        if !response.status().is_success() {
+            return Err(anyhow!("GitHub responded with {}", response.status()));
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Node {
+            stargazer_count: u64,
+            is_archived: bool,
+        }
+
+        #[derive(Deserialize)]
+        struct Error {
+            #[serde(default, rename = "type")]
+            kind: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Body {
+            #[serde(default)]
+            data: Option<HashMap<String, Option<Node>>>,
+
+            #[serde(default)]
+            errors: Vec<Error>,
+        }
+
+        let response: Body = serde_json::from_slice(&response.bytes()?)?;
+
+        // the GraphQL limit on query cost is reported in the body
+        if response
+            .errors
+            .iter()
+            .any(|e| e.kind.as_deref() == Some("RATE_LIMITED"))
+        {
+            warn!("GitHub rate limit reached, stopping at batch {}", i + 1);
+
+            return Ok(Fetched {
+                repos: store,
+                complete: false,
+            });
+        }
+
+        let Some(data) = response.data else {
+            warn!("GitHub returned no data for batch {}", i + 1);
+            continue;
+        };
+
+        for (j, repo) in batch.iter().enumerate() {
+            if let Some(Some(node)) = data.get(&format!("r{j}")) {
+                store.insert(
+                    repo.to_string(),
+                    github::Repository {
+                        stars: node.stargazer_count,
+                        archived: node.is_archived,
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(Fetched {
+        repos: store,
+        complete: true,
+    })
+}
+
+/// Primary limits exhaust the remaining requests, secondary ones ask to retry later
+fn rate_limited(response: &Response) -> bool {
+    let headers = response.headers();
+
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::FORBIDDEN => {
+            header(headers, "x-ratelimit-remaining") == Some(0)
+                || header(headers, "retry-after").is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Wait before the next attempt, as asked by GitHub or growing exponentially
+fn backoff(headers: &HeaderMap, attempt: u32) -> Duration {
+    if let Some(seconds) = header(headers, "retry-after") {
+        return Duration::from_secs(seconds);
+    }
+
+    if let Some(reset) = header(headers, "x-ratelimit-reset") {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        return Duration::from_secs(reset.saturating_sub(now));
+    }
+
+    Duration::from_secs(2u64.pow(attempt))
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Keeps names safe to embed into the query
+fn is_valid(repo: &str) -> bool {
+    repo.split_once('/').is_some_and(|(owner, name)| {
+        let valid = |s: &str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+
+        valid(owner) && valid(name)
+    })
+}
@@ -1,21 +1,37 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::anyhow;
 use derive_builder::Builder;
-use log::info;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 
 use crate::models::*;
+use crate::progress::{Phase, Progress};
+use crate::transcript::Transcript;
+use crate::watchdog::{Watchdog, Watched};
 
+pub mod explain;
+pub mod github;
 pub mod models;
+pub mod progress;
+pub mod requirements;
+pub mod size;
+pub mod transcript;
+pub mod watchdog;
+pub mod xref;
 
 const DEFAULT_BREW_PATH: &str = "brew";
 
+const DEFAULT_JSON_VERSION: &str = "v2";
+
 const BREW_PREFIX_ENV_KEY: &str = "HOMEBREW_PREFIX";
 
 #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
@@ -27,15 +43,158 @@ const DEFAULT_BREW_PREFIX: &str = "/usr/local";
 #[cfg(target_os = "linux")]
 const DEFAULT_BREW_PREFIX: &str = "/home/linuxbrew/.linuxbrew";
 
+/// Linuxbrew inside WSL, the prefix is a path within the distribution
+#[cfg(target_os = "windows")]
+const DEFAULT_BREW_PREFIX: &str = "/home/linuxbrew/.linuxbrew";
+
+const BREW_CACHE_ENV_KEY: &str = "HOMEBREW_CACHE";
+
+const BREW_TEMP_ENV_KEY: &str = "HOMEBREW_TEMP";
+
+const BREW_MAKE_JOBS_ENV_KEY: &str = "HOMEBREW_MAKE_JOBS";
+
+#[cfg(target_os = "macos")]
+const DEFAULT_BREW_TEMP: &str = "/private/tmp";
+
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+const DEFAULT_BREW_TEMP: &str = "/tmp";
+
+const WSL_EXE: &str = "wsl.exe";
+
+/// Generous enough for source builds, brew stalled on a lock or network is the target
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 30);
+
+const DEFAULT_APP_DIR: &str = "/Applications";
+
 const BREW_BIN_REGISTRY_URL: &str =
     "https://raw.githubusercontent.com/Homebrew/homebrew-command-not-found/master/executables.txt";
 
 const BREW_ANALYTICS_URL: &str = "https://formulae.brew.sh/api/analytics/install/30d.json";
 
+const BREW_CASK_ANALYTICS_URL: &str =
+    "https://formulae.brew.sh/api/analytics/cask-install/30d.json";
+
+pub const OFFICIAL_TAPS: [&str; 2] = ["homebrew/core", "homebrew/cask"];
+
+const BREW_NO_ANALYTICS_ENV_KEY: &str = "HOMEBREW_NO_ANALYTICS";
+
+/// A brew command exited unsuccessfully
+#[derive(Debug)]
+pub struct BrewFailure(String);
+
+impl BrewFailure {
+    pub fn new(message: impl Into<String>) -> BrewFailure {
+        BrewFailure(message.into())
+    }
+}
+
+impl std::fmt::Display for BrewFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BrewFailure {}
+
+/// Installed keg a file belongs to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Owner {
+    Formula(String),
+    Cask(String),
+}
+
+/// Parts of the index refreshed by an update, the rest is kept from the previous index
+#[derive(Clone, Copy)]
+pub struct Sections {
+    pub formulae: bool,
+    pub casks: bool,
+    pub executables: bool,
+}
+
+impl Sections {
+    pub const ALL: Sections = Sections {
+        formulae: true,
+        casks: true,
+        executables: true,
+    };
+
+    pub fn is_all(&self) -> bool {
+        self.formulae && self.casks && self.executables
+    }
+
+    /// Names of the refreshed sections
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.formulae, "formulae"),
+            (self.casks, "casks"),
+            (self.executables, "executables"),
+        ]
+        .into_iter()
+        .filter(|(refreshed, _)| *refreshed)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
 #[derive(Builder, Clone)]
 pub struct Brew {
     pub path: PathBuf,
     pub prefix: PathBuf,
+
+    /// Version of the `brew info` JSON output to request
+    #[builder(default = "DEFAULT_JSON_VERSION.into()")]
+    pub json_version: String,
+
+    /// Whether analytics should be fetched from formulae.brew.sh
+    #[builder(default = "analytics_enabled()")]
+    pub analytics: bool,
+
+    /// Let brew run its own auto-update before installing
+    #[builder(default)]
+    pub auto_update: bool,
+
+    /// Passed to brew as `HOMEBREW_CACHE`
+    #[builder(default)]
+    pub cache: Option<PathBuf>,
+
+    /// Passed to brew as `HOMEBREW_TEMP`
+    #[builder(default)]
+    pub temp: Option<PathBuf>,
+
+    /// Taps in order of preference, used when several taps provide the same name.
+    /// Official taps come right after the listed ones
+    #[builder(default)]
+    pub tap_priority: Vec<String>,
+
+    /// WSL distribution brew runs in. Commands are proxied through `wsl.exe`
+    /// and prefix paths are accessed through the `\\wsl.localhost` share
+    #[builder(default = "wsl_distro()")]
+    pub wsl: Option<String>,
+
+    /// How long a brew command may run before the watchdog is consulted
+    #[builder(default = "DEFAULT_TIMEOUT")]
+    pub timeout: Duration,
+
+    /// Decides whether to keep waiting for a brew command exceeding the timeout.
+    /// Without one, a warning is logged and brewer keeps waiting
+    #[builder(default)]
+    pub watchdog: Option<Arc<dyn Watchdog>>,
+
+    /// Parallel build jobs when installing or upgrading, passed as `HOMEBREW_MAKE_JOBS`
+    #[builder(default)]
+    pub jobs: Option<usize>,
+
+    /// Niceness brew runs with when installing or upgrading
+    #[builder(default)]
+    pub nice: Option<i32>,
+
+    /// Run brew in the idle IO class when installing or upgrading, Linux only
+    #[builder(default)]
+    pub ionice: bool,
+
+    /// Records brew commands and their output when set
+    #[builder(default)]
+    pub transcript: Option<Arc<Transcript>>,
 }
 
 impl Default for Brew {
@@ -51,80 +210,353 @@ impl Default for Brew {
         Brew {
             path: DEFAULT_BREW_PATH.into(),
             prefix: prefix.into(),
+            json_version: DEFAULT_JSON_VERSION.into(),
+            analytics: analytics_enabled(),
+            auto_update: false,
+            cache: None,
+            temp: None,
+            tap_priority: Vec::new(),
+            wsl: wsl_distro(),
+            timeout: DEFAULT_TIMEOUT,
+            watchdog: None,
+            jobs: None,
+            nice: None,
+            ionice: false,
+            transcript: None,
         }
     }
 }
 
-impl Brew {
-    const JSON_FLAG: &'static str = "--json=v2";
+/// Default WSL distribution when running on Windows
+#[cfg(target_os = "windows")]
+fn wsl_distro() -> Option<String> {
+    let output = Command::new(WSL_EXE)
+        .args(["-e", "printenv", "WSL_DISTRO_NAME"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+
+    let distro = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    if distro.is_empty() {
+        None
+    } else {
+        Some(distro)
+    }
+}
 
+#[cfg(not(target_os = "windows"))]
+fn wsl_distro() -> Option<String> {
+    None
+}
+
+/// Install counts are formatted with thousands separators, e.g. "1,234"
+fn parse_count(count: &str) -> u64 {
+    count.replace(',', "").parse().unwrap_or_default()
+}
+
+/// Analytics are disabled when `HOMEBREW_NO_ANALYTICS` is set, same as brew does
+fn analytics_enabled() -> bool {
+    std::env::var(BREW_NO_ANALYTICS_ENV_KEY)
+        .unwrap_or_default()
+        .is_empty()
+}
+
+impl Brew {
     fn brew(&self) -> Command {
-        let mut command = Command::new(self.path.clone());
+        self.brew_with(false)
+    }
+
+    fn brew_with(&self, mutating: bool) -> Command {
+        let mut env: Vec<(&str, OsString)> = vec![("HOMEBREW_NO_ENV_HINTS", "1".into())];
+
+        if !(mutating && self.auto_update) {
+            env.push(("HOMEBREW_NO_AUTO_UPDATE", "1".into()));
+        }
+
+        if let Some(jobs) = self.jobs.filter(|_| mutating) {
+            env.push((BREW_MAKE_JOBS_ENV_KEY, jobs.to_string().into()));
+        }
+
+        if let Some(cache) = &self.cache {
+            env.push((BREW_CACHE_ENV_KEY, cache.into()));
+        }
+
+        if let Some(temp) = &self.temp {
+            env.push((BREW_TEMP_ENV_KEY, temp.into()));
+        }
+
+        let mut program = if mutating {
+            self.throttle()
+        } else {
+            Vec::new()
+        };
+
+        program.push(self.path.clone().into_os_string());
+
+        match &self.wsl {
+            // variables are not inherited across wsl.exe, so they are passed through env
+            Some(_) => {
+                let mut command = self.command("env");
+
+                for (key, value) in env {
+                    let mut pair = OsString::from(format!("{key}="));
+                    pair.push(value);
+
+                    command.arg(pair);
+                }
+
+                command.args(program);
+
+                command
+            }
+            None => {
+                let mut command = Command::new(&program[0]);
 
-        command.env("HOMEBREW_NO_AUTO_UPDATE", "1");
-        command.env("HOMEBREW_NO_ENV_HINTS", "1");
+                command.args(&program[1..]);
+                command.envs(env);
 
-        command
+                command
+            }
+        }
     }
 
-    pub fn install(&self, kegs: Vec<Keg>) -> anyhow::Result<()> {
-        let (formulae, casks) = split_kegs(kegs);
+    /// `ionice` and `nice` invocations brew is wrapped in when installing or upgrading
+    fn throttle(&self) -> Vec<OsString> {
+        let mut wrapper = Vec::new();
 
-        if !formulae.is_empty() {
-            let status = self
-                .brew()
-                .arg("install")
-                .arg("--formulae")
-                .args(formulae.into_iter().map(|f| f.base.name))
-                .status()?;
+        if self.ionice && (cfg!(target_os = "linux") || self.wsl.is_some()) {
+            wrapper.extend(["ionice", "-c", "3"].map(OsString::from));
+        }
 
-            if !status.success() {
-                return Err(anyhow!("failed to install formulae"));
+        if let Some(nice) = self.nice {
+            wrapper.extend(["nice".into(), "-n".into(), nice.to_string().into()]);
+        }
+
+        wrapper
+    }
+
+    /// Runs the program where brew lives, inside WSL if configured
+    fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        match &self.wsl {
+            Some(distro) => {
+                let mut command = Command::new(WSL_EXE);
+
+                command.arg("-d").arg(distro).arg("-e").arg(program);
+
+                command
             }
+            None => Command::new(program),
         }
+    }
 
-        if !casks.is_empty() {
-            let status = self
-                .brew()
-                .arg("install")
-                .arg("--casks")
-                .args(casks.into_iter().map(|c| c.base.token))
-                .status()?;
+    /// Translates a path reported by brew to one accessible from this host
+    fn host_path(&self, path: &Path) -> PathBuf {
+        let Some(distro) = &self.wsl else {
+            return path.to_path_buf();
+        };
 
-            if !status.success() {
-                return Err(anyhow!("failed to install casks"));
+        let mut host = PathBuf::from(format!(r"\\wsl.localhost\{distro}"));
+
+        host.extend(path.components().filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part),
+            _ => None,
+        }));
+
+        host
+    }
+
+    /// Old kegs and cached downloads `brew cleanup` would remove
+    pub fn cleanup_plan(&self) -> anyhow::Result<cleanup::Plan> {
+        let output = self
+            .brew()
+            .arg("cleanup")
+            .arg("--dry-run")
+            .watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new("failed to preview cleanup").into());
+        }
+
+        Ok(cleanup::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    pub fn cleanup(&self) -> anyhow::Result<()> {
+        self.run_all(vec![self.cleanup_command()])
+    }
+
+    fn cleanup_command(&self) -> (Command, String) {
+        let mut command = self.brew();
+
+        command.arg("cleanup");
+
+        (command, "failed to clean up".into())
+    }
+
+    /// Runs `brew doctor` and parses the reported issues,
+    /// followed by notes on what brewer can't do in this setup
+    pub fn doctor(&self) -> anyhow::Result<Vec<doctor::Diagnostic>> {
+        // doctor exits unsuccessfully whenever it finds anything, so the status is not checked
+        let output = self.brew().arg("doctor").watched_output(self)?;
+
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push('\n');
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        let mut diagnostics = doctor::parse(&text);
+
+        diagnostics.extend(self.unavailable());
+
+        Ok(diagnostics)
+    }
+
+    /// Homebrew supports casks on macOS only, elsewhere installed casks are never scanned
+    /// and the index has no casks
+    pub fn casks_supported(&self) -> bool {
+        cfg!(target_os = "macos")
+    }
+
+    /// Functionality missing because of the platform or directories brew has not created yet
+    fn unavailable(&self) -> Vec<doctor::Diagnostic> {
+        let mut notes = Vec::new();
+
+        let mut note = |title: &str, details: String| {
+            notes.push(doctor::Diagnostic {
+                level: doctor::Level::Note,
+                title: title.to_string(),
+                details: vec![details],
+            })
+        };
+
+        let opt = self.prefix.join("opt");
+
+        if !self.host_path(&opt).exists() {
+            note(
+                "No installed formulae",
+                format!(
+                    "{} does not exist, brew creates it on the first install",
+                    opt.display()
+                ),
+            );
+        }
+
+        if !self.casks_supported() {
+            note(
+                "Casks are unavailable",
+                "Homebrew supports casks on macOS only, cask commands see no casks".to_string(),
+            );
+        } else {
+            let caskroom = self.prefix.join("Caskroom");
+
+            if !self.host_path(&caskroom).exists() {
+                note(
+                    "No installed casks",
+                    format!(
+                        "{} does not exist, brew creates it on the first cask install",
+                        caskroom.display()
+                    ),
+                );
             }
         }
 
-        Ok(())
+        notes
+    }
+
+    /// Directory brew downloads to, as reported by `brew --cache`
+    pub fn cache_dir(&self) -> anyhow::Result<PathBuf> {
+        let output = self.brew().arg("--cache").watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new("failed to get brew cache directory").into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().into())
+    }
+
+    /// Directory brew uses for temporary files
+    pub fn temp_dir(&self) -> PathBuf {
+        if let Some(temp) = &self.temp {
+            return temp.clone();
+        }
+
+        match std::env::var(BREW_TEMP_ENV_KEY) {
+            Ok(temp) if !temp.is_empty() => temp.into(),
+            _ => DEFAULT_BREW_TEMP.into(),
+        }
+    }
+
+    /// Same as [`Brew::brew`], but lets brew auto-update when enabled
+    /// and applies the configured build jobs and priority
+    fn brew_mutating(&self) -> Command {
+        self.brew_with(true)
+    }
+
+    pub fn install(&self, kegs: Vec<Keg>) -> anyhow::Result<()> {
+        self.run_all(self.keg_commands("install", true, kegs))
     }
 
     pub fn uninstall(&self, kegs: Vec<Keg>) -> anyhow::Result<()> {
+        self.run_all(self.keg_commands("uninstall", false, kegs))
+    }
+
+    pub fn upgrade(&self, kegs: Vec<Keg>) -> anyhow::Result<()> {
+        self.run_all(self.keg_commands("upgrade", true, kegs))
+    }
+
+    /// brew commands the action would run for the given kegs, without running them.
+    /// Kegs are ignored for cleanup
+    pub fn explain(&self, action: explain::Action, kegs: Vec<Keg>) -> Vec<explain::Invocation> {
+        let commands = match action {
+            explain::Action::Install => self.keg_commands("install", true, kegs),
+            explain::Action::Uninstall => self.keg_commands("uninstall", false, kegs),
+            explain::Action::Upgrade => self.keg_commands("upgrade", true, kegs),
+            explain::Action::Cleanup => vec![self.cleanup_command()],
+        };
+
+        commands
+            .iter()
+            .map(|(command, _)| explain::Invocation::from(command))
+            .collect()
+    }
+
+    /// One brew command for formulae and one for casks, paired with the failure message
+    fn keg_commands(&self, action: &str, mutating: bool, kegs: Vec<Keg>) -> Vec<(Command, String)> {
         let (formulae, casks) = split_kegs(kegs);
 
+        let mut commands = Vec::with_capacity(2);
+
         if !formulae.is_empty() {
-            let status = self
-                .brew()
-                .arg("uninstall")
+            let mut command = self.brew_with(mutating);
+
+            command
+                .arg(action)
                 .arg("--formulae")
-                .args(formulae.into_iter().map(|f| f.base.name))
-                .status()?;
+                .args(formulae.iter().map(|f| f.base.qualified_name()));
 
-            if !status.success() {
-                return Err(anyhow!("failed to uninstall formulae"));
-            }
+            commands.push((command, format!("failed to {action} formulae")));
         }
 
         if !casks.is_empty() {
-            let status = self
-                .brew()
-                .arg("uninstall")
+            let mut command = self.brew_with(mutating);
+
+            command
+                .arg(action)
                 .arg("--casks")
-                .args(casks.into_iter().map(|c| c.base.token))
-                .status()?;
+                .args(casks.iter().map(|c| c.base.qualified_token()));
+
+            commands.push((command, format!("failed to {action} casks")));
+        }
+
+        commands
+    }
+
+    /// Runs the commands in order, stopping at the first failing one
+    fn run_all(&self, commands: Vec<(Command, String)>) -> anyhow::Result<()> {
+        for (mut command, failure) in commands {
+            let status = command.watched_status(self)?;
 
             if !status.success() {
-                return Err(anyhow!("failed to uninstall casks"));
+                return Err(BrewFailure::new(failure).into());
             }
         }
 
@@ -134,9 +566,16 @@ impl Brew {
     pub fn analytics(&self) -> anyhow::Result<formula::analytics::Store> {
         let body = reqwest::blocking::get(BREW_ANALYTICS_URL)?.bytes()?;
 
+        #[derive(Deserialize)]
+        struct Item {
+            number: i64,
+            formula: String,
+            count: String,
+        }
+
         #[derive(Deserialize)]
         struct Result {
-            pub items: Vec<formula::analytics::Formula>,
+            pub items: Vec<Item>,
         }
 
         let result: Result = serde_json::from_slice(body.iter().as_slice())?;
@@ -144,7 +583,47 @@ impl Brew {
         let mut store = formula::analytics::Store::new();
 
         for item in result.items {
-            store.insert(item.formula.clone(), item);
+            store.insert(
+                item.formula.clone(),
+                formula::analytics::Formula {
+                    number: item.number,
+                    formula: item.formula,
+                    count: parse_count(&item.count),
+                },
+            );
+        }
+
+        Ok(store)
+    }
+
+    pub fn cask_analytics(&self) -> anyhow::Result<cask::analytics::Store> {
+        let body = reqwest::blocking::get(BREW_CASK_ANALYTICS_URL)?.bytes()?;
+
+        #[derive(Deserialize)]
+        struct Item {
+            number: i64,
+            cask: String,
+            count: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Result {
+            pub items: Vec<Item>,
+        }
+
+        let result: Result = serde_json::from_slice(body.iter().as_slice())?;
+
+        let mut store = cask::analytics::Store::new();
+
+        for item in result.items {
+            store.insert(
+                item.cask.clone(),
+                cask::analytics::Cask {
+                    number: item.number,
+                    cask: item.cask,
+                    count: parse_count(&item.count),
+                },
+            );
         }
 
         Ok(store)
@@ -164,7 +643,7 @@ impl Brew {
             };
 
             let name = &lhs[..index];
-            let executables: HashSet<String> =
+            let executables: BTreeSet<String> =
                 rhs.split_whitespace().map(|s| s.to_string()).collect();
 
             store.insert(name.to_string(), executables);
@@ -174,20 +653,84 @@ impl Brew {
     }
 
     pub fn state(&self) -> anyhow::Result<State<formula::State, cask::State>> {
-        let executables = self.executables()?;
-        let analytics = self.analytics()?;
-        let all = self.eval_all()?;
+        self.state_with(&progress::Silent)
+    }
+
+    /// Same as [`Brew::state`], reporting each phase to the progress
+    pub fn state_with(
+        &self,
+        progress: &dyn Progress,
+    ) -> anyhow::Result<State<formula::State, cask::State>> {
+        let previous = State {
+            formulae: formula::Store::new(),
+            casks: cask::Store::new(),
+        };
+
+        self.refresh_with(progress, Sections::ALL, previous)
+    }
 
-        let all: State<formula::Store, cask::Store> = State {
-            formulae: all
-                .formulae
+    /// Refreshes the given sections of the previous index and rescans installed kegs
+    pub fn refresh_with(
+        &self,
+        progress: &dyn Progress,
+        sections: Sections,
+        previous: State<formula::Store, cask::Store>,
+    ) -> anyhow::Result<State<formula::State, cask::State>> {
+        let sections = Sections {
+            casks: sections.casks && self.casks_supported(),
+            ..sections
+        };
+
+        let executables = if sections.executables {
+            progress.phase(Phase::Executables);
+
+            Some(self.executables()?)
+        } else {
+            None
+        };
+
+        // only formulae and casks have analytics, refreshing executables alone skips the phase
+        let wants_analytics = sections.formulae || sections.casks;
+
+        let (analytics, cask_analytics) = if self.analytics && wants_analytics {
+            progress.phase(Phase::Analytics);
+
+            let analytics = if sections.formulae {
+                self.analytics()?
+            } else {
+                formula::analytics::Store::new()
+            };
+
+            let cask_analytics = if sections.casks {
+                self.cask_analytics()?
+            } else {
+                cask::analytics::Store::new()
+            };
+
+            (analytics, cask_analytics)
+        } else {
+            if !self.analytics {
+                info!("analytics are disabled, skipping");
+            }
+
+            (
+                formula::analytics::Store::new(),
+                cask::analytics::Store::new(),
+            )
+        };
+
+        let all = self.eval_all(progress, sections)?;
+
+        let mut formulae: formula::Store = if sections.formulae {
+            all.formulae
                 .into_iter()
                 .map(|(name, base)| {
-                    let executables = if let Some(e) = executables.get(&name) {
-                        e.clone()
-                    } else {
-                        HashSet::new()
-                    };
+                    // kept from the previous index unless refreshed below
+                    let executables = previous
+                        .formulae
+                        .get(&name)
+                        .map(|f| f.executables.clone())
+                        .unwrap_or_default();
 
                     let analytics = if let Some(a) = analytics.get(&name) {
                         Some(a.clone())
@@ -197,23 +740,60 @@ impl Brew {
                             .cloned()
                     };
 
+                    let tags = keywords(base.desc.as_deref(), &base.tap);
+
                     (
                         name,
                         formula::Formula {
                             base,
                             executables,
                             analytics,
+                            tags,
+                            note: None,
+                            starred: false,
+                            github: None,
                         },
                     )
                 })
-                .collect(),
-            casks: all
-                .casks
+                .collect()
+        } else {
+            previous.formulae
+        };
+
+        if let Some(executables) = executables {
+            for (name, formula) in formulae.iter_mut() {
+                formula.executables = executables.get(name).cloned().unwrap_or_default();
+            }
+        }
+
+        let casks: cask::Store = if sections.casks {
+            all.casks
                 .into_iter()
-                .map(|(name, base)| (name, cask::Cask { base }))
-                .collect(),
+                .map(|(name, base)| {
+                    let analytics = cask_analytics.get(&name).cloned();
+                    let tags = keywords(base.desc.as_deref(), &base.tap);
+
+                    (
+                        name,
+                        cask::Cask {
+                            base,
+                            analytics,
+                            tags,
+                            note: None,
+                            starred: false,
+                            github: None,
+                        },
+                    )
+                })
+                .collect()
+        } else {
+            previous.casks
         };
 
+        let all: State<formula::Store, cask::Store> = State { formulae, casks };
+
+        progress.phase(Phase::Installed);
+
         let installed = self.installed(&all)?;
 
         Ok(State {
@@ -238,31 +818,254 @@ impl Brew {
         Ok(State { formulae, casks })
     }
 
-    fn eval_installed_casks(&self, store: &cask::Store) -> anyhow::Result<cask::installed::Store> {
-        let mut installed = cask::installed::Store::new();
+    /// Installed kegs according to the filesystem scan, reconciled with brew's own view.
+    ///
+    /// Slower than [`Brew::installed`], but catches stale opt symlinks
+    pub fn installed_verified(
+        &self,
+        all: &State<formula::Store, cask::Store>,
+    ) -> anyhow::Result<State<formula::installed::Store, cask::installed::Store>> {
+        let mut installed = self.installed(all)?;
+        let info = self.installed_info()?;
 
-        for (name, versions) in self.eval_installed_casks_versions()? {
-            let Some(cask) = store.get(&name) else {
+        installed
+            .formulae
+            .retain(|name, _| info.formulae.contains_key(name));
+        installed
+            .casks
+            .retain(|name, _| info.casks.contains_key(name));
+
+        for (name, info) in info.formulae {
+            let Some(version) = info.version() else {
                 continue;
             };
 
-            installed.insert(
-                name,
-                cask::installed::Cask {
-                    upstream: cask.clone(),
-                    versions,
-                },
-            );
-        }
-
-        Ok(installed)
-    }
+            let spec = if version.starts_with("HEAD") {
+                formula::receipt::Spec::Head
+            } else {
+                formula::receipt::Spec::Stable
+            };
 
-    fn eval_installed_casks_versions(&self) -> anyhow::Result<cask::installed::VersionsStore> {
-        let caskroom = self.prefix.join("Caskroom").read_dir()?;
+            // brew reports versions with the revision suffix, receipts don't have it
+            let (stable, revision) = models::version::revision(version);
+
+            if let Some(formula) = installed.formulae.get_mut(&name) {
+                let versions = &mut formula.receipt.source.versions;
+
+                match spec {
+                    formula::receipt::Spec::Stable if versions.stable != stable => {
+                        info!(
+                            "{name}: opt points to {}, brew reports {stable}",
+                            versions.stable
+                        );
+
+                        versions.stable = stable.to_string();
+                        formula.receipt.source.spec = spec;
+                        formula.receipt.revision = revision;
+                    }
+                    formula::receipt::Spec::Head => {
+                        versions.head = Some(version.to_string());
+                        formula.receipt.source.spec = spec;
+                    }
+                    _ => {}
+                }
 
+                continue;
+            }
+
+            let Some(upstream) = all.formulae.get(&name) else {
+                continue;
+            };
+
+            info!("{name}: reported by brew but missing from opt");
+
+            let (installed_as_dependency, installed_on_request) = info
+                .installed(version)
+                .map(|i| (i.installed_as_dependency, i.installed_on_request))
+                .unwrap_or((false, true));
+
+            let head = match spec {
+                formula::receipt::Spec::Head => Some(version.to_string()),
+                formula::receipt::Spec::Stable => None,
+            };
+
+            let linked = self.is_linked(&name);
+
+            installed.formulae.insert(
+                name,
+                formula::installed::Formula {
+                    upstream: upstream.clone(),
+                    receipt: formula::receipt::Receipt {
+                        source: formula::receipt::Source {
+                            spec,
+                            versions: formula::receipt::Versions {
+                                stable: stable.to_string(),
+                                head,
+                            },
+                            tap: Some(upstream.base.tap.clone()),
+                        },
+                        installed_as_dependency,
+                        installed_on_request,
+                        poured_from_bottle: None,
+                        time: None,
+                        runtime_dependencies: None,
+                        revision,
+                    },
+                    linked,
+                    pinned: self.is_pinned(&name),
+                },
+            );
+        }
+
+        for (name, info) in info.casks {
+            let Some(version) = info.installed else {
+                continue;
+            };
+
+            match installed.casks.get_mut(&name) {
+                Some(cask) => {
+                    if !cask.versions.contains(&version) {
+                        info!("{name}: Caskroom is missing version {version} reported by brew");
+                    }
+
+                    cask.versions = HashSet::from([version]);
+                }
+                None => {
+                    let Some(upstream) = all.casks.get(&name) else {
+                        continue;
+                    };
+
+                    installed.casks.insert(
+                        name,
+                        cask::installed::Cask {
+                            app_version: self.app_version(&upstream.base),
+                            upstream: upstream.clone(),
+                            versions: HashSet::from([version]),
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(installed)
+    }
+
+    /// Installed kegs as reported by `brew info --installed`
+    pub fn installed_info(&self) -> anyhow::Result<State<formula::info::Store, cask::info::Store>> {
+        let mut command = self.brew();
+
+        let command = command.arg("info").arg("--installed").arg(self.json_flag());
+
+        info!("running {:?}", command);
+
+        let output = command.watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new("failed to get installed kegs info").into());
+        }
+
+        let result: Entries = serde_json::from_slice(output.stdout.as_slice())?;
+
+        let formulae: formula::info::Store =
+            parse_entries::<formula::info::Formula>(result.formulae, "name")
+                .into_iter()
+                .map(|f| (f.name.clone(), f))
+                .collect();
+
+        let casks: cask::info::Store = parse_entries::<cask::info::Cask>(result.casks, "token")
+            .into_iter()
+            .map(|c| (c.token.clone(), c))
+            .collect();
+
+        Ok(State { formulae, casks })
+    }
+
+    fn eval_installed_casks(&self, store: &cask::Store) -> anyhow::Result<cask::installed::Store> {
+        let mut installed = cask::installed::Store::new();
+
+        for (name, versions) in self.eval_installed_casks_versions()? {
+            let Some(cask) = store.get(&name) else {
+                continue;
+            };
+
+            installed.insert(
+                name,
+                cask::installed::Cask {
+                    app_version: self.app_version(&cask.base),
+                    upstream: cask.clone(),
+                    versions,
+                },
+            );
+        }
+
+        Ok(installed)
+    }
+
+    /// Where the cask artifacts are placed, along with whether they exist
+    pub fn cask_artifacts(&self, cask: &cask::Cask) -> Vec<(cask::artifact::Artifact, bool)> {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+
+        cask::artifact::locations(
+            &cask.base.artifacts,
+            &self.prefix,
+            Path::new(DEFAULT_APP_DIR),
+            &home,
+        )
+        .into_iter()
+        .map(|artifact| {
+            let exists = self.host_path(&artifact.path).symlink_metadata().is_ok();
+
+            (artifact, exists)
+        })
+        .collect()
+    }
+
+    /// Version of the first app bundle of the cask found in the applications directories
+    #[cfg(target_os = "macos")]
+    fn app_version(&self, cask: &cask::base::Cask) -> Option<String> {
+        #[derive(Deserialize)]
+        struct Info {
+            #[serde(rename = "CFBundleShortVersionString")]
+            version: String,
+        }
+
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+
+        let dirs = std::iter::once(PathBuf::from(DEFAULT_APP_DIR))
+            .chain(home.map(|h| h.join("Applications")));
+
+        for dir in dirs {
+            for app in cask.apps() {
+                let plist = dir.join(&app).join("Contents").join("Info.plist");
+
+                if let Ok(info) = plist::from_file::<_, Info>(&plist) {
+                    return Some(info.version);
+                }
+            }
+        }
+
+        None
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn app_version(&self, _cask: &cask::base::Cask) -> Option<String> {
+        None
+    }
+
+    fn eval_installed_casks_versions(&self) -> anyhow::Result<cask::installed::VersionsStore> {
         let mut store = cask::installed::VersionsStore::new();
 
+        if !self.casks_supported() {
+            return Ok(store);
+        }
+
+        let Some(caskroom) = read_dir_if_exists(&self.host_path(&self.prefix.join("Caskroom")))?
+        else {
+            return Ok(store);
+        };
+
         for entry in caskroom {
             let entry = entry?;
             let path = entry.path();
@@ -303,16 +1106,18 @@ impl Brew {
     ) -> anyhow::Result<formula::installed::Store> {
         let mut installed = formula::installed::Store::new();
 
-        for (name, receipt) in self.eval_installed_formulae_receipts()? {
+        for (name, receipt) in self.installed_receipts()? {
             let Some(formula) = store.get(&name) else {
                 continue;
             };
 
             installed.insert(
-                name,
+                name.clone(),
                 formula::installed::Formula {
                     upstream: formula.clone(),
                     receipt,
+                    linked: self.is_linked(&name),
+                    pinned: self.is_pinned(&name),
                 },
             );
         }
@@ -320,11 +1125,14 @@ impl Brew {
         Ok(installed)
     }
 
-    fn eval_installed_formulae_receipts(&self) -> anyhow::Result<formula::receipt::Store> {
-        let opt = self.prefix.join("opt").read_dir()?;
-
+    /// Install receipts of all formulae in opt, including ones missing upstream
+    pub fn installed_receipts(&self) -> anyhow::Result<formula::receipt::Store> {
         let mut store = formula::receipt::Store::new();
 
+        let Some(opt) = read_dir_if_exists(&self.host_path(&self.prefix.join("opt")))? else {
+            return Ok(store);
+        };
+
         for entry in opt {
             let entry = entry?;
             let path = entry.path();
@@ -339,14 +1147,30 @@ impl Brew {
                 continue;
             }
 
-            let receipt_path = path.canonicalize()?.join("INSTALL_RECEIPT.json");
+            // a dangling opt symlink or a keg without a receipt is left for brew doctor to report
+            let found = path.canonicalize().and_then(|keg| {
+                let file = File::open(keg.join("INSTALL_RECEIPT.json"))?;
+
+                Ok((keg, file))
+            });
+
+            let (keg, mut file) = match found {
+                Ok(found) => found,
+                Err(e) => {
+                    warn!("{name}: skipping, failed to read the install receipt: {e}");
+                    continue;
+                }
+            };
 
-            let mut file = File::open(receipt_path)?;
             let mut data = Vec::new();
 
             file.read_to_end(&mut data)?;
 
-            let receipt: formula::receipt::Receipt = serde_json::from_slice(data.as_slice())?;
+            let mut receipt: formula::receipt::Receipt = serde_json::from_slice(data.as_slice())?;
+
+            if let Some(version) = keg.file_name() {
+                receipt.revision = models::version::revision(&version.to_string_lossy()).1;
+            }
 
             store.insert(name.clone(), receipt);
         }
@@ -354,43 +1178,534 @@ impl Brew {
         Ok(store)
     }
 
+    /// brew records linked kegs as symlinks in var/homebrew/linked
+    fn is_linked(&self, name: &str) -> bool {
+        let linked = self.prefix.join("var").join("homebrew").join("linked");
+
+        self.host_path(&linked.join(name))
+            .symlink_metadata()
+            .is_ok()
+    }
+
+    fn is_pinned(&self, name: &str) -> bool {
+        let pinned = self.prefix.join("var").join("homebrew").join("pinned");
+
+        self.host_path(&pinned.join(name))
+            .symlink_metadata()
+            .is_ok()
+    }
+
+    /// Names of the locks in var/homebrew/locks currently held by another brew process.
+    /// Lock files outlive their process, so each one is probed with a non-blocking flock.
+    /// Locks inside WSL can't be probed from the host and are never reported
+    pub fn held_locks(&self) -> anyhow::Result<Vec<String>> {
+        if self.wsl.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let locks = self.prefix.join("var").join("homebrew").join("locks");
+
+        let Some(entries) = read_dir_if_exists(&locks)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut held = Vec::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.extension() != Some(OsStr::new("lock")) {
+                continue;
+            }
+
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+
+            if let Err(std::fs::TryLockError::WouldBlock) = file.try_lock() {
+                if let Some(name) = path.file_stem() {
+                    held.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+
+        held.sort_unstable();
+
+        Ok(held)
+    }
+
+    /// Ruby source of the formula or cask, as printed by `brew cat`
+    pub fn cat(&self, keg: &Keg) -> anyhow::Result<String> {
+        let (flag, name) = match keg {
+            Keg::Formula(f) => ("--formula", f.base.qualified_name()),
+            Keg::Cask(c) => ("--cask", c.base.qualified_token()),
+        };
+
+        let output = self
+            .brew()
+            .arg("cat")
+            .arg(flag)
+            .arg(name)
+            .watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new(format!("failed to get the source of {name}")).into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Output of `brew shellenv`, setting up PATH and friends for the prefix
+    pub fn shellenv(&self) -> anyhow::Result<String> {
+        let output = self.brew().arg("shellenv").watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new("failed to get brew shellenv").into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Directory with all installed versions of the formula
+    pub fn cellar_dir(&self, name: &str) -> PathBuf {
+        self.host_path(&self.prefix.join("Cellar").join(name))
+    }
+
+    /// Directory with installed versions of the cask, apps moved elsewhere are not included
+    pub fn caskroom_dir(&self, token: &str) -> PathBuf {
+        self.host_path(&self.prefix.join("Caskroom").join(token))
+    }
+
+    /// Formula or cask the path resolves into through the Cellar or Caskroom.
+    /// Files placed outside of them, e.g. app bundles, are matched against the cask artifacts
+    pub fn owner<'a>(
+        &self,
+        path: &Path,
+        casks: impl IntoIterator<Item = &'a cask::Cask>,
+    ) -> anyhow::Result<Option<Owner>> {
+        let path = path.canonicalize()?;
+
+        let roots: [(&str, fn(String) -> Owner); 2] =
+            [("Cellar", Owner::Formula), ("Caskroom", Owner::Cask)];
+
+        for (dir, owner) in roots {
+            let Ok(root) = self.host_path(&self.prefix.join(dir)).canonicalize() else {
+                continue;
+            };
+
+            let Ok(rest) = path.strip_prefix(&root) else {
+                continue;
+            };
+
+            if let Some(std::path::Component::Normal(name)) = rest.components().next() {
+                return Ok(Some(owner(name.to_string_lossy().to_string())));
+            }
+        }
+
+        for cask in casks {
+            let owns = self
+                .cask_artifacts(cask)
+                .into_iter()
+                .any(|(artifact, exists)| {
+                    exists
+                        && self
+                            .host_path(&artifact.path)
+                            .canonicalize()
+                            .is_ok_and(|p| path.starts_with(p))
+                });
+
+            if owns {
+                return Ok(Some(Owner::Cask(cask.base.token.clone())));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Executables, shared and static libraries and pkg-config files of an installed formula.
+    /// brew publishes no file lists of bottles, so only installed kegs can be inspected
+    pub fn keg_files(&self, name: &str) -> formula::Files {
+        let opt = self.host_path(&self.prefix.join("opt").join(name));
+
+        let is_library =
+            |name: &str| name.ends_with(".dylib") || name.ends_with(".a") || name.contains(".so");
+        let is_pkgconfig = |name: &str| name.ends_with(".pc");
+
+        let mut libraries = file_names(&opt.join("lib"), is_library);
+        let mut pkgconfig = file_names(&opt.join("lib").join("pkgconfig"), is_pkgconfig);
+
+        pkgconfig.extend(file_names(
+            &opt.join("share").join("pkgconfig"),
+            is_pkgconfig,
+        ));
+
+        let mut executables = file_names(&opt.join("bin"), |_| true);
+
+        executables.extend(file_names(&opt.join("sbin"), |_| true));
+
+        executables.sort_unstable();
+        libraries.sort_unstable();
+        pkgconfig.sort_unstable();
+
+        formula::Files {
+            executables,
+            libraries,
+            pkgconfig,
+        }
+    }
+
+    /// Variables to prepend to for using a keg-only formula, as suggested by its caveats.
+    /// Only directories present in the keg are included
+    pub fn keg_env(&self, name: &str) -> Vec<(&'static str, String)> {
+        let opt = self.prefix.join("opt").join(name);
+
+        let exists = |dir: &str| self.host_path(&opt.join(dir)).is_dir();
+        let path = |dir: &str| opt.join(dir).to_string_lossy().to_string();
+
+        let mut env = Vec::new();
+
+        for dir in ["bin", "sbin"] {
+            if exists(dir) {
+                env.push(("PATH", path(dir)));
+            }
+        }
+
+        if exists("lib") {
+            env.push(("LDFLAGS", format!("-L{}", path("lib"))));
+        }
+
+        if exists("include") {
+            env.push(("CPPFLAGS", format!("-I{}", path("include"))));
+        }
+
+        if exists("lib/pkgconfig") {
+            env.push(("PKG_CONFIG_PATH", path("lib/pkgconfig")));
+        }
+
+        env
+    }
+
+    /// Symlinks the formula into the prefix. Keg-only formulae require force
+    pub fn link(&self, formula: &formula::Formula, force: bool) -> anyhow::Result<()> {
+        let mut command = self.brew();
+
+        command.arg("link");
+
+        if force {
+            command.arg("--force");
+        }
+
+        let status = command
+            .arg(formula.base.qualified_name())
+            .watched_status(self)?;
+
+        if !status.success() {
+            return Err(BrewFailure::new(format!("failed to link {}", formula.base.name)).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn unlink(&self, formula: &formula::Formula) -> anyhow::Result<()> {
+        let status = self
+            .brew()
+            .arg("unlink")
+            .arg(formula.base.qualified_name())
+            .watched_status(self)?;
+
+        if !status.success() {
+            return Err(BrewFailure::new(format!("failed to unlink {}", formula.base.name)).into());
+        }
+
+        Ok(())
+    }
+
+    /// Tapped taps. Official taps are always included since they may be served from the API
+    pub fn taps(&self) -> anyhow::Result<HashSet<String>> {
+        let output = self.brew().arg("tap").watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new("failed to list taps").into());
+        }
+
+        let mut taps: HashSet<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect();
+
+        taps.extend(OFFICIAL_TAPS.iter().map(|t| t.to_string()));
+
+        Ok(taps)
+    }
+
+    pub fn tap(&self, tap: &str) -> anyhow::Result<()> {
+        let status = self
+            .brew_mutating()
+            .arg("tap")
+            .arg(tap)
+            .watched_status(self)?;
+
+        if !status.success() {
+            return Err(BrewFailure::new(format!("failed to tap {tap}")).into());
+        }
+
+        Ok(())
+    }
+
+    /// Holds the given formulae at their installed versions
+    pub fn pin(&self, names: &[String]) -> anyhow::Result<()> {
+        let status = self
+            .brew_mutating()
+            .arg("pin")
+            .args(names)
+            .watched_status(self)?;
+
+        if !status.success() {
+            return Err(BrewFailure::new(format!("failed to pin {}", names.join(", "))).into());
+        }
+
+        Ok(())
+    }
+
+    /// Local checkout of the given tap. None if the tap is not cloned, e.g. served from the API
+    pub fn tap_checkout(&self, tap: &str) -> anyhow::Result<Option<tap::Checkout>> {
+        let output = self
+            .brew()
+            .arg("--repository")
+            .arg(tap)
+            .watched_output(self)?;
+
+        if !output.status.success() {
+            return Err(BrewFailure::new(format!("failed to locate tap {tap}")).into());
+        }
+
+        let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+        if !self.host_path(&path.join(".git")).exists() {
+            return Ok(None);
+        }
+
+        let dirty = self
+            .command("git")
+            .arg("-C")
+            .arg(&path)
+            .args(["status", "--porcelain"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| !o.stdout.is_empty());
+
+        let behind = self
+            .command("git")
+            .arg("-C")
+            .arg(&path)
+            .args(["rev-list", "--count", "HEAD..@{upstream}"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| String::from_utf8_lossy(&o.stdout).trim().parse().ok());
+
+        Ok(Some(tap::Checkout {
+            path,
+            dirty,
+            behind,
+        }))
+    }
+
+    fn json_flag(&self) -> String {
+        format!("--json={}", self.json_version)
+    }
+
     fn is_dotfile(name: &str) -> bool {
         name.starts_with('.')
     }
 
-    fn eval_all(&self) -> anyhow::Result<State<formula::base::Store, cask::base::Store>> {
+    /// Evaluates formulae and casks of all taps, skipped sections are left empty
+    fn eval_all(
+        &self,
+        progress: &dyn Progress,
+        sections: Sections,
+    ) -> anyhow::Result<State<formula::base::Store, cask::base::Store>> {
+        if !sections.formulae && !sections.casks {
+            return Ok(State {
+                formulae: formula::base::Store::new(),
+                casks: cask::base::Store::new(),
+            });
+        }
+
+        progress.phase(Phase::Brew);
+
         let mut command = self.brew();
 
-        let command = command.arg("info").arg("--eval-all").arg(Self::JSON_FLAG);
+        let command = command.arg("info").arg("--eval-all").arg(self.json_flag());
+
+        if !sections.casks {
+            command.arg("--formula");
+        } else if !sections.formulae {
+            command.arg("--cask");
+        }
 
         info!("running {:?}", command);
 
-        let output = command.output()?;
+        let output = command.watched_output(self)?;
 
-        #[derive(Deserialize)]
-        struct Result {
-            formulae: Vec<formula::base::Formula>,
-            casks: Vec<cask::base::Cask>,
-        }
+        progress.phase(Phase::Parse);
 
-        let result: Result = serde_json::from_slice(output.stdout.as_slice())?;
+        let result: Entries = serde_json::from_slice(output.stdout.as_slice())?;
 
-        let formulae: formula::base::Store = result
-            .formulae
-            .into_iter()
-            .map(|f| (f.name.clone(), f))
-            .collect();
+        let formulae: formula::base::Store = resolve_collisions(
+            parse_entries::<formula::base::Formula>(result.formulae, "name"),
+            &self.tap_priority,
+            |f| (&f.name, &f.tap, f.qualified_name()),
+        );
 
-        let casks: cask::base::Store = result
-            .casks
-            .into_iter()
-            .map(|c| (c.token.clone(), c))
-            .collect();
+        let casks: cask::base::Store = resolve_collisions(
+            parse_entries::<cask::base::Cask>(result.casks, "token"),
+            &self.tap_priority,
+            |c| (&c.token, &c.tap, c.qualified_token()),
+        );
 
         Ok(State { formulae, casks })
     }
 }
 
+/// Entries of the directory, None if it does not exist. brew creates Caskroom and opt
+/// on the first install, so they are missing on fresh installs
+fn read_dir_if_exists(dir: &Path) -> anyhow::Result<Option<std::fs::ReadDir>> {
+    match dir.read_dir() {
+        Ok(entries) => Ok(Some(entries)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Names of the files in the directory which pass the filter, nothing if it can't be read
+fn file_names(dir: &Path, keep: impl Fn(&str) -> bool) -> Vec<String> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| !t.is_dir()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| keep(name))
+        .collect()
+}
+
+/// Keys entries by name. When several taps provide the same name, the one from the most
+/// preferred tap keeps the bare name and the rest are keyed by their full names.
+/// If no single tap is preferred, all of them are keyed by full names.
+///
+/// `id` returns name, tap and full name of the entry
+fn resolve_collisions<T>(
+    entries: Vec<T>,
+    priority: &[String],
+    id: impl Fn(&T) -> (&str, &str, &str),
+) -> keg::Store<T> {
+    let mut groups: HashMap<String, Vec<T>> = HashMap::new();
+
+    for entry in entries {
+        let name = id(&entry).0.to_string();
+
+        groups.entry(name).or_default().push(entry);
+    }
+
+    let mut store = keg::Store::with_capacity(groups.len());
+
+    for (name, mut group) in groups {
+        if group.len() == 1 {
+            store.insert(name, group.pop().unwrap());
+            continue;
+        }
+
+        let rank = |entry: &T| tap_rank(id(entry).1, priority);
+
+        let best = group.iter().filter_map(rank).min();
+        let winners = group
+            .iter()
+            .filter(|e| best.is_some() && rank(e) == best)
+            .count();
+
+        for entry in group {
+            if winners == 1 && rank(&entry) == best {
+                store.insert(name.clone(), entry);
+            } else {
+                let (name, tap, full_name) = id(&entry);
+
+                let full_name = if full_name == name {
+                    format!("{tap}/{name}")
+                } else {
+                    full_name.to_string()
+                };
+
+                warn!("{name} is provided by several taps, keeping it as {full_name}");
+
+                store.insert(full_name, entry);
+            }
+        }
+    }
+
+    store
+}
+
+/// Position of the tap in the priority list, official taps follow the listed ones
+fn tap_rank(tap: &str, priority: &[String]) -> Option<usize> {
+    priority
+        .iter()
+        .position(|p| p == tap)
+        .or_else(|| OFFICIAL_TAPS.contains(&tap).then_some(priority.len()))
+}
+
+/// Lowercase keywords from the description and tap, used for tag filtering
+pub fn keywords(desc: Option<&str>, tap: &str) -> HashSet<String> {
+    const MIN_LEN: usize = 3;
+    const STOPWORDS: &[&str] = &[
+        "and", "the", "for", "with", "from", "that", "this", "your", "into", "via", "its", "using",
+    ];
+
+    let desc = desc.unwrap_or_default();
+
+    desc.split(|c: char| !c.is_alphanumeric())
+        .chain(tap.split('/'))
+        .map(|w| w.to_lowercase())
+        .filter(|w| w.len() >= MIN_LEN && !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Raw `brew info` output, entries are parsed one by one
+#[derive(Deserialize)]
+struct Entries {
+    #[serde(default)]
+    formulae: Vec<serde_json::Value>,
+
+    #[serde(default)]
+    casks: Vec<serde_json::Value>,
+}
+
+/// Parses each entry individually, skipping the broken ones.
+///
+/// `key` is the field used to identify the offending entry in the logs
+fn parse_entries<T: DeserializeOwned>(entries: Vec<serde_json::Value>, key: &str) -> Vec<T> {
+    let mut parsed = Vec::with_capacity(entries.len());
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        let id = entry
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| format!("#{i}"));
+
+        match serde_json::from_value::<T>(entry) {
+            Ok(value) => parsed.push(value),
+            Err(e) => warn!("skipping {key} {id}: {e}"),
+        }
+    }
+
+    parsed
+}
+
 fn split_kegs(kegs: Vec<Keg>) -> (Vec<formula::Formula>, Vec<cask::Cask>) {
     let mut formulae: Vec<formula::Formula> = Vec::with_capacity(kegs.len());
     let mut casks: Vec<cask::Cask> = Vec::with_capacity(kegs.len());
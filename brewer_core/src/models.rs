@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 pub struct State<F, C> {
     pub formulae: F,
     pub casks: C,
@@ -12,6 +12,24 @@ pub enum Keg {
     Cask(cask::Cask),
 }
 
+impl Keg {
+    /// Formula name or cask token
+    pub fn name(&self) -> &str {
+        match self {
+            Keg::Formula(f) => &f.base.name,
+            Keg::Cask(c) => &c.base.token,
+        }
+    }
+
+    /// Tap providing the formula or cask
+    pub fn tap(&self) -> &str {
+        match self {
+            Keg::Formula(f) => &f.base.tap,
+            Keg::Cask(c) => &c.base.tap,
+        }
+    }
+}
+
 impl From<formula::Formula> for Keg {
     fn from(value: formula::Formula) -> Self {
         Self::Formula(value)
@@ -24,22 +42,83 @@ impl From<cask::Cask> for Keg {
     }
 }
 
+/// GitHub releases page when the source or homepage is hosted on GitHub,
+/// the homepage otherwise
+fn release_notes(source: Option<&str>, homepage: Option<&str>) -> Option<String> {
+    let releases = |repo: String| format!("https://github.com/{repo}/releases");
+
+    source
+        .and_then(github::repository_of)
+        .or_else(|| homepage.and_then(github::repository_of))
+        .map(releases)
+        .or_else(|| homepage.map(String::from))
+}
+
+pub mod github {
+    use serde::{Deserialize, Serialize};
+
+    /// Metadata of the GitHub repository a formula or cask comes from
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct Repository {
+        pub stars: u64,
+        pub archived: bool,
+    }
+
+    /// "owner/repo" of a GitHub URL
+    pub fn repository_of(url: &str) -> Option<String> {
+        let path = url
+            .strip_prefix("https://github.com/")
+            .or_else(|| url.strip_prefix("http://github.com/"))?;
+
+        let mut parts = path.split('/');
+        let owner = parts.next().filter(|p| !p.is_empty())?;
+        let repo = parts.next().filter(|p| !p.is_empty())?;
+        let repo = repo.strip_suffix(".git").unwrap_or(repo);
+
+        Some(format!("{owner}/{repo}"))
+    }
+}
+
 pub mod formula {
-    use std::collections::HashSet;
+    use std::collections::{BTreeSet, HashSet};
 
     use serde::{Deserialize, Serialize};
 
     use super::keg;
 
-    pub type Executables = keg::Store<HashSet<String>>;
+    pub type Executables = keg::Store<BTreeSet<String>>;
     pub type State = keg::State<Formula, installed::Formula>;
     pub type Store = keg::Store<Formula>;
 
+    /// File names of an installed keg which other formulae and build systems look for
+    #[derive(Default)]
+    pub struct Files {
+        pub executables: Vec<String>,
+        pub libraries: Vec<String>,
+        pub pkgconfig: Vec<String>,
+    }
+
     #[derive(Deserialize, Serialize, Clone)]
     pub struct Formula {
         pub base: base::Formula,
-        pub executables: HashSet<String>,
+        pub executables: BTreeSet<String>,
         pub analytics: Option<analytics::Formula>,
+
+        /// Keywords extracted from the description and tap, plus user-defined ones
+        #[serde(default)]
+        pub tags: HashSet<String>,
+
+        /// User-defined note
+        #[serde(default)]
+        pub note: Option<String>,
+
+        /// Starred by the user
+        #[serde(default)]
+        pub starred: bool,
+
+        /// Metadata of the upstream GitHub repository, when enabled
+        #[serde(default)]
+        pub github: Option<super::github::Repository>,
     }
 
     impl AsRef<str> for Formula {
@@ -48,8 +127,15 @@ pub mod formula {
         }
     }
 
+    impl Formula {
+        /// Installs over the last 30 days, 0 when analytics are unavailable
+        pub fn installs(&self) -> u64 {
+            self.analytics.as_ref().map(|a| a.count).unwrap_or_default()
+        }
+    }
+
     pub mod base {
-        use std::collections::HashSet;
+        use std::collections::{HashMap, HashSet};
 
         use serde::{Deserialize, Serialize};
 
@@ -62,29 +148,212 @@ pub mod formula {
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Formula {
             pub name: String,
+
+            /// Tap-qualified name, e.g. "user/tap/name". Same as name for homebrew/core
+            #[serde(default)]
+            pub full_name: String,
+
+            #[serde(default)]
             pub tap: String,
+
+            #[serde(default, alias = "description")]
             pub desc: Option<String>,
+
+            #[serde(default)]
             pub homepage: Option<String>,
+
+            #[serde(default)]
             pub caveats: Option<String>,
 
+            #[serde(default, alias = "build_deps")]
             pub build_dependencies: Vec<String>,
+
+            #[serde(default, alias = "deps")]
             pub dependencies: Vec<String>,
 
+            #[serde(default)]
             pub deprecated: bool,
+
+            #[serde(default)]
             pub deprecation_reason: Option<String>,
 
+            #[serde(default)]
             pub disabled: bool,
+
+            #[serde(default)]
             pub disable_reason: Option<String>,
 
+            /// Formulae which can't be linked alongside this one
+            #[serde(default)]
+            pub conflicts_with: Vec<String>,
+
             #[serde(default)]
             pub aliases: HashSet<String>,
 
             pub versions: Versions,
+
+            /// Bumped when the formula is rebuilt without a version change, e.g. 1 in "1.2_1"
+            #[serde(default)]
+            pub revision: u32,
+
+            #[serde(default)]
+            pub bottle: Bottles,
+
+            #[serde(default)]
+            pub urls: Urls,
+
+            /// Not symlinked into the prefix by default, e.g. to avoid shadowing system libraries
+            #[serde(default)]
+            pub keg_only: bool,
+
+            #[serde(default)]
+            pub requirements: Vec<Requirement>,
+        }
+
+        /// Checked by brew before installing, e.g. a minimum macOS or Xcode version
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Requirement {
+            pub name: String,
+
+            #[serde(default)]
+            pub version: Option<String>,
+
+            /// When the requirement applies, e.g. "build". Always when empty
+            #[serde(default)]
+            pub contexts: Vec<String>,
+        }
+
+        impl Requirement {
+            /// Only needed when building from source, so bottles install without it
+            pub fn build_only(&self) -> bool {
+                !self.contexts.is_empty() && self.contexts.iter().all(|c| c == "build")
+            }
+        }
+
+        impl Formula {
+            /// Name which unambiguously identifies the formula for brew
+            pub fn qualified_name(&self) -> &str {
+                if self.full_name.is_empty() {
+                    &self.name
+                } else {
+                    &self.full_name
+                }
+            }
+
+            /// Bottle file for the current platform, if any
+            pub fn bottle_file(&self) -> Option<&BottleFile> {
+                let files = &self.bottle.stable.as_ref()?.files;
+
+                files.get("all").or_else(|| {
+                    files
+                        .iter()
+                        .find(|(tag, _)| is_native_bottle_tag(tag))
+                        .map(|(_, file)| file)
+                })
+            }
+
+            pub fn download_size(&self) -> Option<u64> {
+                self.bottle_file().and_then(|f| f.size)
+            }
+
+            pub fn installed_size(&self) -> Option<u64> {
+                self.bottle_file().and_then(|f| f.installed_size)
+            }
+
+            /// "tap/name", unambiguous even for homebrew/core formulae
+            pub fn tap_qualified_name(&self) -> String {
+                if self.tap.is_empty() {
+                    self.name.clone()
+                } else {
+                    format!("{}/{}", self.tap, self.name)
+                }
+            }
+
+            /// "owner/repo" when the homepage is a GitHub repository
+            pub fn github_repository(&self) -> Option<String> {
+                self.homepage
+                    .as_deref()
+                    .and_then(crate::models::github::repository_of)
+            }
+
+            /// Where to read about changes of the formula
+            pub fn release_notes(&self) -> Option<String> {
+                let source = self.urls.stable.as_ref().map(|u| u.url.as_str());
+
+                crate::models::release_notes(source, self.homepage.as_deref())
+            }
+        }
+
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        fn is_native_bottle_tag(tag: &str) -> bool {
+            tag.starts_with("arm64_") && !tag.ends_with("_linux")
+        }
+
+        #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+        fn is_native_bottle_tag(tag: &str) -> bool {
+            !tag.starts_with("arm64_") && !tag.ends_with("_linux")
+        }
+
+        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        fn is_native_bottle_tag(tag: &str) -> bool {
+            tag == "x86_64_linux"
+        }
+
+        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+        fn is_native_bottle_tag(tag: &str) -> bool {
+            tag == "arm64_linux"
+        }
+
+        /// brew runs inside the WSL distro, which pours Linux bottles of the same architecture
+        #[cfg(target_os = "windows")]
+        fn is_native_bottle_tag(tag: &str) -> bool {
+            if cfg!(target_arch = "aarch64") {
+                tag == "arm64_linux"
+            } else {
+                tag == "x86_64_linux"
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Default)]
+        pub struct Bottles {
+            #[serde(default)]
+            pub stable: Option<Bottle>,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Default)]
+        pub struct Bottle {
+            /// Bottle files by platform tag, e.g. "arm64_sonoma"
+            #[serde(default)]
+            pub files: HashMap<String, BottleFile>,
+        }
+
+        /// Sizes are in bytes, brew reports them only for some bottles
+        #[derive(Serialize, Deserialize, Clone, Default)]
+        pub struct BottleFile {
+            #[serde(default)]
+            pub size: Option<u64>,
+
+            #[serde(default)]
+            pub installed_size: Option<u64>,
+        }
+
+        #[derive(Serialize, Deserialize, Clone, Default)]
+        pub struct Urls {
+            #[serde(default)]
+            pub stable: Option<Url>,
+        }
+
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Url {
+            pub url: String,
         }
 
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Versions {
+            #[serde(default)]
             pub stable: String,
+
+            #[serde(default)]
             pub head: Option<String>,
         }
     }
@@ -101,6 +370,52 @@ pub mod formula {
         pub struct Formula {
             pub upstream: super::Formula,
             pub receipt: receipt::Receipt,
+
+            /// Symlinked into the prefix
+            #[serde(default)]
+            pub linked: bool,
+
+            /// Held at the installed version by `brew pin`
+            #[serde(default)]
+            pub pinned: bool,
+        }
+    }
+
+    pub mod info {
+        use serde::{Deserialize, Serialize};
+
+        use crate::models::keg;
+
+        pub type Store = keg::Store<Formula>;
+
+        /// Installed formula as reported by `brew info --installed`
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Formula {
+            pub name: String,
+            pub linked_keg: Option<String>,
+
+            #[serde(default)]
+            pub installed: Vec<Installed>,
+        }
+
+        impl Formula {
+            /// Version brew considers active, the linked keg if any, latest installed otherwise
+            pub fn version(&self) -> Option<&str> {
+                self.linked_keg
+                    .as_deref()
+                    .or_else(|| self.installed.last().map(|i| i.version.as_str()))
+            }
+
+            pub fn installed(&self, version: &str) -> Option<&Installed> {
+                self.installed.iter().find(|i| i.version == version)
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Installed {
+            pub version: String,
+            pub installed_as_dependency: bool,
+            pub installed_on_request: bool,
         }
     }
 
@@ -113,8 +428,13 @@ pub mod formula {
 
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Formula {
+            /// Rank by installs, starting from 1
             pub number: i64,
             pub formula: String,
+
+            /// Installs over the last 30 days
+            #[serde(default)]
+            pub count: u64,
         }
     }
 
@@ -128,14 +448,47 @@ pub mod formula {
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Receipt {
             pub source: Source,
+
+            #[serde(default)]
             pub installed_as_dependency: bool,
+
+            #[serde(default)]
             pub installed_on_request: bool,
+
+            /// Installed from a bottle rather than built from source. None if unknown
+            #[serde(default)]
+            pub poured_from_bottle: Option<bool>,
+
+            /// Unix timestamp of the installation
+            #[serde(default)]
+            pub time: Option<i64>,
+
+            /// Missing in receipts written by old brew versions
+            #[serde(default)]
+            pub runtime_dependencies: Option<Vec<RuntimeDependency>>,
+
+            /// Not part of the receipt, taken from the name of the keg directory, e.g. "1.2_1"
+            #[serde(default)]
+            pub revision: u32,
+        }
+
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct RuntimeDependency {
+            pub full_name: String,
+
+            /// Required by the formula itself rather than by one of its dependencies
+            #[serde(default)]
+            pub declared_directly: bool,
         }
 
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Source {
             pub spec: Spec,
             pub versions: Versions,
+
+            /// Tap the formula was installed from
+            #[serde(default)]
+            pub tap: Option<String>,
         }
 
         impl Source {
@@ -156,13 +509,18 @@ pub mod formula {
 
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Versions {
+            #[serde(default)]
             pub stable: String,
+
+            #[serde(default)]
             pub head: Option<String>,
         }
     }
 }
 
 pub mod cask {
+    use std::collections::HashSet;
+
     use serde::{Deserialize, Serialize};
 
     use super::keg;
@@ -173,6 +531,25 @@ pub mod cask {
     #[derive(Serialize, Deserialize, Clone)]
     pub struct Cask {
         pub base: base::Cask,
+
+        #[serde(default)]
+        pub analytics: Option<analytics::Cask>,
+
+        /// Keywords extracted from the description and tap, plus user-defined ones
+        #[serde(default)]
+        pub tags: HashSet<String>,
+
+        /// User-defined note
+        #[serde(default)]
+        pub note: Option<String>,
+
+        /// Starred by the user
+        #[serde(default)]
+        pub starred: bool,
+
+        /// Metadata of the upstream GitHub repository, when enabled
+        #[serde(default)]
+        pub github: Option<super::github::Repository>,
     }
 
     impl AsRef<str> for Cask {
@@ -181,6 +558,13 @@ pub mod cask {
         }
     }
 
+    impl Cask {
+        /// Installs over the last 30 days, 0 when analytics are unavailable
+        pub fn installs(&self) -> u64 {
+            self.analytics.as_ref().map(|a| a.count).unwrap_or_default()
+        }
+    }
+
     pub mod base {
         use std::collections::HashSet;
 
@@ -192,26 +576,261 @@ pub mod cask {
         #[derive(Serialize, Deserialize, Clone)]
         pub struct Cask {
             pub token: String,
+
+            /// Tap-qualified token, e.g. "user/tap/token". Same as token for homebrew/cask
+            #[serde(default)]
+            pub full_token: String,
+
+            #[serde(default)]
             pub tap: String,
+
+            #[serde(default, alias = "description")]
             pub desc: Option<String>,
+
+            #[serde(default)]
             pub version: String,
+
+            #[serde(default)]
             pub caveats: Option<String>,
+
+            #[serde(default)]
             pub homepage: Option<String>,
 
+            #[serde(default)]
             pub deprecated: bool,
+
+            #[serde(default)]
             pub deprecation_reason: Option<String>,
 
+            #[serde(default)]
             pub disabled: bool,
-            pub disable_reason: Option<String>,
 
             #[serde(default)]
+            pub disable_reason: Option<String>,
+
+            #[serde(default, alias = "name")]
             pub names: HashSet<String>,
+
+            /// Download size in bytes, when available
+            #[serde(default)]
+            pub download_size: Option<u64>,
+
+            #[serde(default)]
+            pub url: Option<String>,
+
+            /// Artifacts stanza, e.g. `{"app": ["Foo.app"]}` or `{"binary": ["foo"]}`
+            #[serde(default)]
+            pub artifacts: Vec<serde_json::Value>,
+        }
+
+        impl Cask {
+            /// Token which unambiguously identifies the cask for brew
+            pub fn qualified_token(&self) -> &str {
+                if self.full_token.is_empty() {
+                    &self.token
+                } else {
+                    &self.full_token
+                }
+            }
+
+            /// "tap/token", unambiguous even for homebrew/cask casks
+            pub fn tap_qualified_token(&self) -> String {
+                if self.tap.is_empty() {
+                    self.token.clone()
+                } else {
+                    format!("{}/{}", self.tap, self.token)
+                }
+            }
+
+            /// "owner/repo" when the homepage is a GitHub repository
+            pub fn github_repository(&self) -> Option<String> {
+                self.homepage
+                    .as_deref()
+                    .and_then(crate::models::github::repository_of)
+            }
+
+            /// Where to read about changes of the cask
+            pub fn release_notes(&self) -> Option<String> {
+                crate::models::release_notes(self.url.as_deref(), self.homepage.as_deref())
+            }
+
+            /// Names of app bundles the cask moves to the applications directory,
+            /// renamed to the target when one is given
+            pub fn apps(&self) -> Vec<String> {
+                self.artifacts
+                    .iter()
+                    .filter_map(|a| a.get("app")?.as_array())
+                    .filter_map(|app| {
+                        let target = app
+                            .iter()
+                            .find_map(|v| v.get("target")?.as_str())
+                            .and_then(|t| t.rsplit('/').next());
+
+                        target.or_else(|| app.first()?.as_str()).map(String::from)
+                    })
+                    .collect()
+            }
         }
 
         pub type State = keg::State<Cask, installed::Cask>;
         pub type Store = keg::Store<Cask>;
     }
 
+    pub mod analytics {
+        use serde::{Deserialize, Serialize};
+
+        use crate::models::keg;
+
+        pub type Store = keg::Store<Cask>;
+
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Cask {
+            /// Rank by installs, starting from 1
+            pub number: i64,
+            pub cask: String,
+
+            /// Installs over the last 30 days
+            #[serde(default)]
+            pub count: u64,
+        }
+    }
+
+    pub mod artifact {
+        use std::path::{Path, PathBuf};
+
+        use serde_json::Value;
+
+        /// Where an artifact of the cask is placed on installation
+        pub struct Artifact {
+            /// Stanza the artifact comes from, e.g. "app" or "binary"
+            pub kind: String,
+            pub path: PathBuf,
+        }
+
+        /// Locations of app bundles, binaries, manpages, launch agents and files removed
+        /// by zap. Globs in zap paths are skipped since they can not be checked directly
+        pub fn locations(
+            artifacts: &[Value],
+            prefix: &Path,
+            app_dir: &Path,
+            home: &Path,
+        ) -> Vec<Artifact> {
+            let mut locations = Vec::new();
+
+            let mut push = |kind: &str, path: PathBuf| {
+                locations.push(Artifact {
+                    kind: kind.to_string(),
+                    path,
+                })
+            };
+
+            for stanza in artifacts.iter().filter_map(Value::as_object) {
+                for (kind, value) in stanza {
+                    let entries = value.as_array().map(Vec::as_slice).unwrap_or_default();
+
+                    match kind.as_str() {
+                        "app" => {
+                            if let Some(name) = target(entries) {
+                                push(kind.as_str(), app_dir.join(name));
+                            }
+                        }
+                        "binary" => {
+                            if let Some(name) = target(entries) {
+                                push(kind.as_str(), prefix.join("bin").join(name));
+                            }
+                        }
+                        "manpage" => {
+                            if let Some(name) = target(entries) {
+                                let section = name.rsplit('.').next().unwrap_or("1");
+
+                                push(
+                                    kind.as_str(),
+                                    prefix
+                                        .join("share")
+                                        .join("man")
+                                        .join(format!("man{section}"))
+                                        .join(name),
+                                );
+                            }
+                        }
+                        "font" => {
+                            if let Some(name) = target(entries) {
+                                let fonts = home.join("Library").join("Fonts");
+
+                                push(kind.as_str(), fonts.join(name));
+                            }
+                        }
+                        "uninstall" | "zap" => {
+                            for directive in entries.iter().filter_map(Value::as_object) {
+                                for label in strings(directive.get("launchctl")) {
+                                    for dir in [
+                                        home.join("Library/LaunchAgents"),
+                                        PathBuf::from("/Library/LaunchAgents"),
+                                        PathBuf::from("/Library/LaunchDaemons"),
+                                    ] {
+                                        push("launchctl", dir.join(format!("{label}.plist")));
+                                    }
+                                }
+
+                                if kind == "zap" {
+                                    for path in strings(directive.get("trash")) {
+                                        if path.contains('*') {
+                                            continue;
+                                        }
+
+                                        let path = match path.strip_prefix("~/") {
+                                            Some(rest) => home.join(rest),
+                                            None => PathBuf::from(path),
+                                        };
+
+                                        push("zap", path);
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            locations
+        }
+
+        /// File name an artifact ends up with, the target if given, the source otherwise
+        fn target(entries: &[Value]) -> Option<&str> {
+            let target = entries
+                .iter()
+                .find_map(|v| v.get("target")?.as_str())
+                .or_else(|| entries.first()?.as_str())?;
+
+            target.rsplit('/').next()
+        }
+
+        /// A directive value which may be either a string or an array of strings
+        fn strings(value: Option<&Value>) -> Vec<&str> {
+            match value {
+                Some(Value::String(s)) => vec![s.as_str()],
+                Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    pub mod info {
+        use serde::{Deserialize, Serialize};
+
+        use crate::models::keg;
+
+        pub type Store = keg::Store<Cask>;
+
+        /// Installed cask as reported by `brew info --installed`
+        #[derive(Serialize, Deserialize, Clone)]
+        pub struct Cask {
+            pub token: String,
+            pub installed: Option<String>,
+        }
+    }
+
     pub mod installed {
         use std::collections::HashSet;
 
@@ -226,6 +845,614 @@ pub mod cask {
         pub struct Cask {
             pub upstream: super::Cask,
             pub versions: HashSet<String>,
+
+            /// Version of the installed app bundle, it differs when the app updates itself
+            #[serde(default)]
+            pub app_version: Option<String>,
+        }
+
+        impl Cask {
+            /// The app bundle updated itself past the version brew installed
+            pub fn locally_newer(&self) -> bool {
+                let Some(app) = &self.app_version else {
+                    return false;
+                };
+
+                self.versions
+                    .iter()
+                    .all(|v| crate::models::version::compare(app, v).is_gt())
+            }
+        }
+    }
+}
+
+pub mod tap {
+    use std::path::PathBuf;
+
+    /// Local git checkout of a tap
+    pub struct Checkout {
+        pub path: PathBuf,
+
+        /// Has uncommitted changes. None if git status failed
+        pub dirty: Option<bool>,
+
+        /// Number of commits the checkout is behind its upstream as of the last fetch.
+        /// None if unknown
+        pub behind: Option<u64>,
+    }
+}
+
+pub mod graph {
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::models::formula;
+
+    /// Dependency edges between formulae
+    pub struct Graph {
+        edges: HashMap<String, Vec<String>>,
+    }
+
+    /// Dependencies of a formula, expanded recursively
+    pub struct Tree {
+        pub name: String,
+        pub children: Vec<Tree>,
+    }
+
+    impl Graph {
+        /// Builds the graph from runtime dependencies, optionally including build ones.
+        /// Dependencies from third-party taps are tap-qualified, they are reduced to the bare
+        /// names formulae are keyed by
+        pub fn new(formulae: &formula::Store, build: bool) -> Graph {
+            let edges = formulae
+                .iter()
+                .map(|(name, formula)| {
+                    let mut deps = formula.base.dependencies.clone();
+
+                    if build {
+                        deps.extend(formula.base.build_dependencies.iter().cloned());
+                    }
+
+                    let deps = deps
+                        .iter()
+                        .map(|dep| dep.rsplit('/').next().unwrap_or(dep).to_string())
+                        .collect();
+
+                    (name.clone(), deps)
+                })
+                .collect();
+
+            Graph { edges }
+        }
+
+        /// Direct dependencies of the formula
+        pub fn direct(&self, name: &str) -> &[String] {
+            self.edges.get(name).map(Vec::as_slice).unwrap_or_default()
+        }
+
+        /// All dependencies of the formula, excluding the formula itself
+        pub fn recursive(&self, name: &str) -> BTreeSet<String> {
+            let mut visited = BTreeSet::new();
+            let mut queue: Vec<_> = self.direct(name).to_vec();
+
+            while let Some(dep) = queue.pop() {
+                if dep == name || !visited.insert(dep.clone()) {
+                    continue;
+                }
+
+                queue.extend(self.direct(&dep).iter().cloned());
+            }
+
+            visited
+        }
+
+        /// Dependency tree of the formula. Cycles are cut at the repeated formula
+        pub fn tree(&self, name: &str) -> Tree {
+            self.subtree(name, &mut Vec::new())
+        }
+
+        fn subtree(&self, name: &str, path: &mut Vec<String>) -> Tree {
+            path.push(name.to_string());
+
+            let children = self
+                .direct(name)
+                .iter()
+                .filter(|dep| !path.contains(dep))
+                .map(|dep| self.subtree(dep, path))
+                .collect();
+
+            path.pop();
+
+            Tree {
+                name: name.to_string(),
+                children,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Formulae by name with their runtime and build dependencies
+        fn formulae(specs: &[(&str, &[&str], &[&str])]) -> formula::Store {
+            specs
+                .iter()
+                .map(|(name, deps, build)| {
+                    let formula = serde_json::from_value(serde_json::json!({
+                        "base": {
+                            "name": name,
+                            "versions": { "stable": "1.0" },
+                            "dependencies": deps,
+                            "build_dependencies": build,
+                        },
+                        "executables": [],
+                    }))
+                    .unwrap();
+
+                    (name.to_string(), formula)
+                })
+                .collect()
+        }
+
+        /// Tree in a compact form, e.g. "a(b,c(d))"
+        fn render(tree: &Tree) -> String {
+            if tree.children.is_empty() {
+                return tree.name.clone();
+            }
+
+            let children: Vec<_> = tree.children.iter().map(render).collect();
+
+            format!("{}({})", tree.name, children.join(","))
+        }
+
+        fn diamond() -> formula::Store {
+            formulae(&[
+                ("ffmpeg", &["lame", "x264"], &["pkgconf"]),
+                ("lame", &["libogg"], &[]),
+                ("x264", &["libogg"], &[]),
+                ("libogg", &[], &[]),
+                ("pkgconf", &[], &[]),
+            ])
+        }
+
+        #[test]
+        fn recursive_visits_shared_dependencies_once() {
+            let graph = Graph::new(&diamond(), false);
+
+            assert_eq!(
+                graph.recursive("ffmpeg"),
+                BTreeSet::from(["lame", "libogg", "x264"].map(String::from))
+            );
+            assert!(graph.recursive("libogg").is_empty());
+            assert!(graph.recursive("missing").is_empty());
+        }
+
+        #[test]
+        fn build_dependencies_are_opt_in() {
+            let graph = Graph::new(&diamond(), true);
+
+            assert!(graph.recursive("ffmpeg").contains("pkgconf"));
+            assert_eq!(
+                render(&graph.tree("ffmpeg")),
+                "ffmpeg(lame(libogg),x264(libogg),pkgconf)"
+            );
+        }
+
+        #[test]
+        fn tree_repeats_shared_dependencies() {
+            let graph = Graph::new(&diamond(), false);
+
+            assert_eq!(
+                render(&graph.tree("ffmpeg")),
+                "ffmpeg(lame(libogg),x264(libogg))"
+            );
+            assert_eq!(render(&graph.tree("missing")), "missing");
+        }
+
+        #[test]
+        fn cycles_are_cut() {
+            let graph = Graph::new(
+                &formulae(&[
+                    ("python", &["sqlite"], &[]),
+                    ("sqlite", &["readline", "python"], &[]),
+                    ("readline", &[], &[]),
+                ]),
+                false,
+            );
+
+            assert_eq!(
+                graph.recursive("python"),
+                BTreeSet::from(["readline", "sqlite"].map(String::from))
+            );
+            assert_eq!(render(&graph.tree("python")), "python(sqlite(readline))");
+            assert_eq!(render(&graph.tree("sqlite")), "sqlite(readline,python)");
+        }
+    }
+}
+
+pub mod cleanup {
+    use std::path::PathBuf;
+
+    /// What `brew cleanup` would remove
+    #[derive(Default)]
+    pub struct Plan {
+        pub entries: Vec<Entry>,
+    }
+
+    pub struct Entry {
+        pub path: PathBuf,
+
+        /// Size in bytes as reported by brew, if any
+        pub size: Option<u64>,
+    }
+
+    impl Entry {
+        /// Old keg version rather than a cached download
+        pub fn is_keg(&self) -> bool {
+            self.path
+                .components()
+                .any(|c| c.as_os_str() == "Cellar" || c.as_os_str() == "Caskroom")
+        }
+    }
+
+    impl Plan {
+        pub fn size(&self) -> u64 {
+            self.entries.iter().filter_map(|e| e.size).sum()
+        }
+    }
+
+    /// Parses `brew cleanup --dry-run` output, e.g.
+    /// "Would remove: /opt/homebrew/Cellar/foo/1.0 (12 files, 3.4MB)"
+    pub fn parse(output: &str) -> Plan {
+        let entries = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("Would remove: "))
+            .map(|rest| match rest.rsplit_once(" (") {
+                Some((path, details)) => Entry {
+                    path: path.into(),
+                    size: details
+                        .trim_end_matches(')')
+                        .rsplit(", ")
+                        .next()
+                        .and_then(parse_size),
+                },
+                None => Entry {
+                    path: rest.into(),
+                    size: None,
+                },
+            })
+            .collect();
+
+        Plan { entries }
+    }
+
+    /// Brew reports sizes with binary multiples, e.g. "3.4MB"
+    fn parse_size(size: &str) -> Option<u64> {
+        const UNITS: [(&str, u64); 4] =
+            [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)];
+
+        UNITS.iter().find_map(|(suffix, multiplier)| {
+            let value: f64 = size.strip_suffix(suffix)?.trim().parse().ok()?;
+
+            Some((value * *multiplier as f64) as u64)
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::path::Path;
+
+        use super::*;
+
+        const DRY_RUN: &str = "\
+Would remove: /opt/homebrew/Cellar/openssl@3/3.3.0 (6,988 files, 32.4MB)
+Would remove: /Users/me/Library/Caches/Homebrew/downloads/jq--1.7.1.arm64_sonoma.bottle.tar.gz (1.2KB)
+Would remove: /Users/me/Library/Caches/Homebrew/Cask/notes (1).dmg (1GB)
+Would remove: /Users/me/Library/Logs/Homebrew/wget
+==> This operation would free approximately 1.0GB of disk space.
+";
+
+        #[test]
+        fn parses_paths_and_sizes() {
+            let plan = parse(DRY_RUN);
+
+            let paths: Vec<_> = plan.entries.iter().map(|e| e.path.as_path()).collect();
+            let sizes: Vec<_> = plan.entries.iter().map(|e| e.size).collect();
+
+            assert_eq!(
+                paths,
+                [
+                    Path::new("/opt/homebrew/Cellar/openssl@3/3.3.0"),
+                    Path::new("/Users/me/Library/Caches/Homebrew/downloads/jq--1.7.1.arm64_sonoma.bottle.tar.gz"),
+                    Path::new("/Users/me/Library/Caches/Homebrew/Cask/notes (1).dmg"),
+                    Path::new("/Users/me/Library/Logs/Homebrew/wget"),
+                ]
+            );
+            assert_eq!(sizes, [Some(33_973_862), Some(1_228), Some(1 << 30), None]);
+            assert_eq!(plan.size(), 33_973_862 + 1_228 + (1 << 30));
+        }
+
+        #[test]
+        fn tells_kegs_from_downloads() {
+            let plan = parse(DRY_RUN);
+
+            let kegs: Vec<_> = plan.entries.iter().map(Entry::is_keg).collect();
+
+            assert_eq!(kegs, [true, false, false, false]);
+        }
+
+        #[test]
+        fn nothing_to_remove() {
+            assert!(parse("").entries.is_empty());
+            assert_eq!(parse("").size(), 0);
+        }
+    }
+}
+
+pub mod doctor {
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum Level {
+        /// Not an issue, explains functionality missing in this setup
+        Note,
+        Warning,
+        Error,
+    }
+
+    /// Single issue reported by `brew doctor`, or a note added by brewer
+    pub struct Diagnostic {
+        pub level: Level,
+        pub title: String,
+        pub details: Vec<String>,
+    }
+
+    /// Splits `brew doctor` output into diagnostics, each starting with
+    /// a "Warning:" or "Error:" line. Text outside of them is ignored
+    pub fn parse(output: &str) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = Vec::new();
+        let mut current: Option<Diagnostic> = None;
+
+        for line in output.lines() {
+            let header = if let Some(title) = line.strip_prefix("Warning: ") {
+                Some((Level::Warning, title))
+            } else {
+                line.strip_prefix("Error: ")
+                    .map(|title| (Level::Error, title))
+            };
+
+            if let Some((level, title)) = header {
+                diagnostics.extend(current.take());
+
+                current = Some(Diagnostic {
+                    level,
+                    title: title.trim().to_string(),
+                    details: Vec::new(),
+                });
+            } else if let Some(diagnostic) = &mut current {
+                diagnostic.details.push(line.to_string());
+            }
+        }
+
+        diagnostics.extend(current);
+
+        for diagnostic in &mut diagnostics {
+            while diagnostic
+                .details
+                .last()
+                .is_some_and(|l| l.trim().is_empty())
+            {
+                diagnostic.details.pop();
+            }
+        }
+
+        diagnostics
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const DOCTOR: &str = "\
+Please note that these warnings are just used to help the Homebrew maintainers
+with debugging if you file an issue. If everything you use Homebrew for is
+working fine: please don't worry or file an issue; just ignore this. Thanks!
+
+Warning: Some installed formulae are deprecated or disabled.
+You should find replacements for the following formulae:
+  python@3.8
+
+Warning: Unbrewed dylibs were found in /usr/local/lib.
+If you didn't put them there on purpose they could cause problems when
+building Homebrew formulae and may need to be deleted.
+
+Unexpected dylibs:
+  /usr/local/lib/libusb-1.0.0.dylib
+
+";
+
+        #[test]
+        fn splits_warnings_skipping_the_preamble() {
+            let diagnostics = parse(DOCTOR);
+
+            let titles: Vec<_> = diagnostics.iter().map(|d| d.title.as_str()).collect();
+
+            assert_eq!(
+                titles,
+                [
+                    "Some installed formulae are deprecated or disabled.",
+                    "Unbrewed dylibs were found in /usr/local/lib.",
+                ]
+            );
+            assert!(diagnostics.iter().all(|d| d.level == Level::Warning));
+        }
+
+        #[test]
+        fn keeps_inner_blank_lines_and_trims_trailing_ones() {
+            let diagnostics = parse(DOCTOR);
+
+            assert_eq!(
+                diagnostics[0].details,
+                [
+                    "You should find replacements for the following formulae:",
+                    "  python@3.8",
+                ]
+            );
+            assert_eq!(
+                diagnostics[1].details,
+                [
+                    "If you didn't put them there on purpose they could cause problems when",
+                    "building Homebrew formulae and may need to be deleted.",
+                    "",
+                    "Unexpected dylibs:",
+                    "  /usr/local/lib/libusb-1.0.0.dylib",
+                ]
+            );
+        }
+
+        #[test]
+        fn parses_errors() {
+            let diagnostics = parse("Error: The following directories are not writable by your user:\n/opt/homebrew/share/zsh\n");
+
+            assert_eq!(diagnostics.len(), 1);
+            assert!(diagnostics[0].level == Level::Error);
+            assert_eq!(diagnostics[0].details, ["/opt/homebrew/share/zsh"]);
+        }
+
+        #[test]
+        fn healthy_setup_has_no_diagnostics() {
+            assert!(parse("Your system is ready to brew.\n").is_empty());
+            assert!(parse("").is_empty());
+        }
+    }
+}
+
+pub mod version {
+    use std::cmp::Ordering;
+
+    /// Compares versions by their numeric components, e.g. "1.10" > "1.9".
+    /// Cask build suffixes after a comma, like in "1.2.3,456", are ignored
+    pub fn compare(a: &str, b: &str) -> Ordering {
+        let (a, b) = (components(a), components(b));
+
+        for i in 0..a.len().max(b.len()) {
+            let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Size of a version change
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Bump {
+        Patch,
+        Minor,
+        Major,
+    }
+
+    impl Bump {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Bump::Patch => "patch",
+                Bump::Minor => "minor",
+                Bump::Major => "major",
+            }
+        }
+    }
+
+    /// Classifies a change by the first differing numeric component, semver-style.
+    /// Like in 0.x releases, a leading zero shifts the meaning, so "0.1" -> "0.2" is major
+    pub fn bump(from: &str, to: &str) -> Bump {
+        let (a, b) = (components(from), components(to));
+        let at = |v: &[u64], i: usize| v.get(i).copied().unwrap_or_default();
+
+        let offset = usize::from(at(&a, 0) == 0 && at(&b, 0) == 0);
+
+        match (0..a.len().max(b.len())).find(|&i| at(&a, i) != at(&b, i)) {
+            Some(i) if i <= offset => Bump::Major,
+            Some(i) if i == offset + 1 => Bump::Minor,
+            _ => Bump::Patch,
+        }
+    }
+
+    /// Splits the formula revision off a keg version, "1.2_1" is ("1.2", 1)
+    pub fn revision(version: &str) -> (&str, u32) {
+        match version.rsplit_once('_') {
+            Some((stable, revision)) if revision.chars().all(|c| c.is_ascii_digit()) => {
+                (stable, revision.parse().unwrap_or_default())
+            }
+            _ => (version, 0),
+        }
+    }
+
+    fn components(v: &str) -> Vec<u64> {
+        v.split(',')
+            .next()
+            .unwrap_or(v)
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| s.parse().ok())
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn compare_numerically() {
+            assert_eq!(compare("1.10", "1.9"), Ordering::Greater);
+            assert_eq!(compare("3.3.0", "3.3.1"), Ordering::Less);
+            assert_eq!(compare("1.2", "1.2.0"), Ordering::Equal);
+            assert_eq!(compare("", "0"), Ordering::Equal);
+        }
+
+        #[test]
+        fn compare_ignores_cask_builds() {
+            assert_eq!(compare("1.2.3,456", "1.2.3,789"), Ordering::Equal);
+            assert_eq!(compare("125.0.1,20240415", "125.0"), Ordering::Greater);
+        }
+
+        #[test]
+        fn compare_orders_revisions_after_the_version() {
+            assert_eq!(compare("1.2_1", "1.2"), Ordering::Greater);
+            assert_eq!(compare("1.2_1", "1.2_2"), Ordering::Less);
+        }
+
+        #[test]
+        fn revision_is_split_off() {
+            assert_eq!(revision("1.2_1"), ("1.2", 1));
+            assert_eq!(revision("3.3.0_12"), ("3.3.0", 12));
+            assert_eq!(revision("1.2"), ("1.2", 0));
+            assert_eq!(revision("2.0_beta"), ("2.0_beta", 0));
+        }
+
+        fn bumped(from: &str, to: &str) -> &'static str {
+            bump(from, to).name()
+        }
+
+        #[test]
+        fn bump_by_first_differing_component() {
+            assert_eq!(bumped("1.2.3", "1.2.4"), "patch");
+            assert_eq!(bumped("1.2.3", "1.3.0"), "minor");
+            assert_eq!(bumped("1.2.3", "2.0"), "major");
+            assert_eq!(bumped("3.3", "3.3.1"), "patch");
+        }
+
+        #[test]
+        fn bump_shifts_below_one() {
+            assert_eq!(bumped("0.1.0", "0.2.0"), "major");
+            assert_eq!(bumped("0.1.0", "0.1.1"), "minor");
+            assert_eq!(bumped("0.9", "1.0"), "major");
+        }
+
+        #[test]
+        fn bump_of_revisions_and_builds_is_patch() {
+            assert_eq!(bumped("1.2", "1.2_1"), "patch");
+            assert_eq!(bumped("1.2_1", "1.2_2"), "patch");
+            assert_eq!(bumped("1.2.3,456", "1.2.3,789"), "patch");
         }
     }
 }
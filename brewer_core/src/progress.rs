@@ -0,0 +1,44 @@
+/// Steps of fetching the latest formulae and casks, in order
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Executables,
+    Analytics,
+    Brew,
+    Parse,
+    Installed,
+    Store,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Executables,
+        Phase::Analytics,
+        Phase::Brew,
+        Phase::Parse,
+        Phase::Installed,
+        Phase::Store,
+    ];
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Phase::Executables => "Downloading the executables registry",
+            Phase::Analytics => "Downloading install analytics",
+            Phase::Brew => "Running brew info --eval-all",
+            Phase::Parse => "Parsing formulae and casks",
+            Phase::Installed => "Scanning installed kegs",
+            Phase::Store => "Writing the cache",
+        }
+    }
+}
+
+/// Told when fetching moves on to the next phase
+pub trait Progress {
+    fn phase(&self, phase: Phase);
+}
+
+/// Reports nothing
+pub struct Silent;
+
+impl Progress for Silent {
+    fn phase(&self, _: Phase) {}
+}
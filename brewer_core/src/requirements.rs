@@ -0,0 +1,125 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::models::formula::base::{Formula, Requirement};
+use crate::models::version;
+
+const CLT_PATH: &str = "/Library/Developer/CommandLineTools";
+
+/// Explanations of the requirements of the formula this machine does not meet.
+/// Only requirements which can be checked locally are considered
+pub fn unmet(formula: &Formula) -> Vec<String> {
+    let bottled = formula.bottle_file().is_some();
+
+    let mut unmet: Vec<_> = formula
+        .requirements
+        .iter()
+        .filter(|r| !(bottled && r.build_only()))
+        .filter_map(check)
+        .collect();
+
+    if cfg!(target_os = "macos") && !bottled && !clt_installed() {
+        unmet.push(
+            "has no bottle for this system, building it requires the Command Line Tools, run `xcode-select --install`"
+                .into(),
+        );
+    }
+
+    unmet
+}
+
+fn check(requirement: &Requirement) -> Option<String> {
+    let required = requirement.version.as_deref();
+
+    match requirement.name.as_str() {
+        "xcode" => match (xcode_version(), required) {
+            (None, Some(required)) => Some(format!("requires Xcode {required} or newer")),
+            (None, None) => Some("requires Xcode".into()),
+            (Some(installed), Some(required)) if version::compare(installed, required).is_lt() => {
+                Some(format!(
+                    "requires Xcode {required} or newer, {installed} is installed"
+                ))
+            }
+            _ => None,
+        },
+        "macos" => match (macos_version(), required) {
+            (None, Some(required)) => Some(format!("requires macOS {required} or newer")),
+            (None, None) => Some("requires macOS".into()),
+            (Some(running), Some(required)) if version::compare(running, required).is_lt() => Some(
+                format!("requires macOS {required} or newer, {running} is running"),
+            ),
+            _ => None,
+        },
+        "maximum_macos" => match (macos_version(), required) {
+            (Some(running), Some(required)) if version::compare(running, required).is_gt() => Some(
+                format!("requires macOS {required} or older, {running} is running"),
+            ),
+            _ => None,
+        },
+        "linux" => cfg!(target_os = "macos").then(|| "requires Linux".into()),
+        "arch" => required
+            .filter(|required| *required != host_arch())
+            .map(|required| format!("requires {required}, this machine is {}", host_arch())),
+        _ => None,
+    }
+}
+
+/// Architecture as brew names it
+fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+fn macos_version() -> Option<&'static str> {
+    static VERSION: OnceLock<Option<String>> = OnceLock::new();
+
+    VERSION
+        .get_or_init(|| {
+            if !cfg!(target_os = "macos") {
+                return None;
+            }
+
+            let output = Command::new("sw_vers")
+                .arg("-productVersion")
+                .output()
+                .ok()
+                .filter(|o| o.status.success())?;
+
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .as_deref()
+}
+
+/// Version of the full Xcode, `xcodebuild` fails when only the Command Line Tools are installed
+fn xcode_version() -> Option<&'static str> {
+    static VERSION: OnceLock<Option<String>> = OnceLock::new();
+
+    VERSION
+        .get_or_init(|| {
+            if !cfg!(target_os = "macos") {
+                return None;
+            }
+
+            let output = Command::new("xcodebuild")
+                .arg("-version")
+                .output()
+                .ok()
+                .filter(|o| o.status.success())?;
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+
+            stdout
+                .lines()
+                .next()?
+                .strip_prefix("Xcode ")
+                .map(|v| v.trim().to_string())
+        })
+        .as_deref()
+}
+
+fn clt_installed() -> bool {
+    Path::new(CLT_PATH).exists() || xcode_version().is_some()
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::keg;
+
+/// Sizes by keg directory, e.g. Cellar/wget
+pub type Cache = keg::Store<Measured>;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Measured {
+    /// Version directories present when measured, the size is reused while they stay the same
+    pub versions: Vec<String>,
+    pub bytes: u64,
+}
+
+/// Size of the keg directory, walking it only when its version directories changed
+pub fn measure(cache: &mut Cache, key: &str, dir: &Path) -> u64 {
+    let versions = versions(dir);
+
+    match cache.get(key) {
+        Some(measured) if measured.versions == versions => measured.bytes,
+        _ => {
+            let bytes = dir_size(dir);
+
+            cache.insert(key.to_string(), Measured { versions, bytes });
+
+            bytes
+        }
+    }
+}
+
+/// Bytes taken by the files under the directory. Symlinks are not followed
+/// and unreadable entries are skipped
+pub fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = dir.read_dir() else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.path().symlink_metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&e.path()),
+            Ok(meta) if meta.is_file() => meta.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+fn versions(dir: &Path) -> Vec<String> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+
+    versions.sort_unstable();
+
+    versions
+}
@@ -0,0 +1,67 @@
+use std::process::{Command, ExitStatus};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::explain::Invocation;
+
+/// brew commands run during a session along with their output, in the order they finished
+#[derive(Default)]
+pub struct Transcript {
+    entries: Mutex<Vec<Entry>>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub invocation: Invocation,
+
+    /// Seconds since the unix epoch
+    pub started_at: u64,
+    pub duration_ms: u64,
+
+    /// None if the command was killed or terminated by a signal
+    pub status: Option<i32>,
+
+    /// None for commands attached to the terminal, their output is part of the session one
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+impl Transcript {
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record(
+        &self,
+        command: &Command,
+        started: Instant,
+        status: Option<&ExitStatus>,
+        output: Option<(&[u8], &[u8])>,
+    ) {
+        let elapsed = started.elapsed();
+
+        let started_at = (SystemTime::now() - elapsed)
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let entry = Entry {
+            invocation: Invocation::from(command),
+            started_at,
+            duration_ms: elapsed.as_millis() as u64,
+            status: status.and_then(ExitStatus::code),
+            stdout: output.map(|(stdout, _)| String::from_utf8_lossy(stdout).to_string()),
+            stderr: output.map(|(_, stderr)| String::from_utf8_lossy(stderr).to_string()),
+        };
+
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+}
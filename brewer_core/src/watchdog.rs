@@ -0,0 +1,198 @@
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::{Brew, BrewFailure};
+
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decides what to do with a brew command running longer than the timeout
+pub trait Watchdog: Send + Sync {
+    /// Called each time the timeout elapses, returning false kills the command
+    fn keep_waiting(&self, command: &str, elapsed: Duration) -> bool;
+}
+
+/// Same as [`Command::status`] and [`Command::output`], but consults the watchdog of brew
+/// when the command takes longer than the timeout
+pub(crate) trait Watched {
+    fn watched_status(&mut self, brew: &Brew) -> anyhow::Result<ExitStatus>;
+    fn watched_output(&mut self, brew: &Brew) -> anyhow::Result<Output>;
+}
+
+impl Watched for Command {
+    fn watched_status(&mut self, brew: &Brew) -> anyhow::Result<ExitStatus> {
+        let started = Instant::now();
+
+        // output is left attached to the terminal so that brew keeps its progress and prompts,
+        // the transcript only gets the invocation and its outcome
+        let mut child = self.spawn()?;
+
+        let status = wait(&mut child, &describe(self), brew);
+
+        if let Some(transcript) = &brew.transcript {
+            transcript.record(self, started, status.as_ref().ok(), None);
+        }
+
+        status
+    }
+
+    fn watched_output(&mut self, brew: &Brew) -> anyhow::Result<Output> {
+        let started = Instant::now();
+
+        // brew gets a process group of its own, so that a kill also reaches whatever it spawned
+        // and keeps the pipes open
+        #[cfg(unix)]
+        self.process_group(0);
+
+        let mut child = self
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // pipes are drained while waiting, otherwise a large output would block the command
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let status = wait(&mut child, &describe(self), brew);
+
+        // after a kill, a descendant that left the group may still hold the pipes open
+        let (stdout, stderr) = match status {
+            Ok(_) => (
+                stdout.join().unwrap_or_default(),
+                stderr.join().unwrap_or_default(),
+            ),
+            Err(_) => (Vec::new(), Vec::new()),
+        };
+
+        if let Some(transcript) = &brew.transcript {
+            transcript.record(
+                self,
+                started,
+                status.as_ref().ok(),
+                Some((&stdout, &stderr)),
+            );
+        }
+
+        Ok(Output {
+            status: status?,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+fn wait(child: &mut Child, command: &str, brew: &Brew) -> anyhow::Result<ExitStatus> {
+    let started = Instant::now();
+    let mut deadline = started + brew.timeout;
+    let mut interval = Duration::from_millis(1);
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if Instant::now() >= deadline {
+            let elapsed = started.elapsed();
+
+            let keep_waiting = match &brew.watchdog {
+                Some(watchdog) => watchdog.keep_waiting(command, elapsed),
+                None => {
+                    warn!("still waiting for `{command}` after {}s", elapsed.as_secs());
+                    true
+                }
+            };
+
+            if !keep_waiting {
+                kill(child)?;
+                child.wait()?;
+
+                return Err(BrewFailure::new(format!(
+                    "killed `{command}` after {}s",
+                    elapsed.as_secs()
+                ))
+                .into());
+            }
+
+            deadline = Instant::now() + brew.timeout;
+        }
+
+        thread::sleep(interval);
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    }
+}
+
+/// Kills the command, along with its process group when it leads one
+fn kill(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: plain syscalls on a pid that is not reaped yet
+        unsafe {
+            if libc::getpgid(pid) == pid {
+                libc::killpg(pid, libc::SIGKILL);
+            }
+        }
+    }
+
+    child.kill()
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+
+        buf
+    })
+}
+
+/// Program and arguments, e.g. "brew install wget"
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct GiveUp;
+
+    impl Watchdog for GiveUp {
+        fn keep_waiting(&self, _command: &str, _elapsed: Duration) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn kill_reaches_commands_spawned_by_brew() {
+        let brew = Brew {
+            timeout: Duration::from_millis(100),
+            watchdog: Some(Arc::new(GiveUp)),
+            ..Brew::default()
+        };
+
+        let started = Instant::now();
+
+        // the background sleep inherits the pipes and outlives its parent unless the group is killed
+        let output = Command::new("sh")
+            .args(["-c", "sleep 30 & sleep 30"])
+            .watched_output(&brew);
+
+        assert!(output.is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,92 @@
+use serde::Deserialize;
+
+const REPOLOGY_PROJECT_URL: &str = "https://repology.org/api/v1/project";
+
+/// Known names which differ between Homebrew, nixpkgs and apt: (formula, nix attribute, apt package)
+const BUNDLED: &[(&str, &str, &str)] = &[
+    ("fd", "fd", "fd-find"),
+    ("gnu-sed", "gnused", "sed"),
+    ("gnu-tar", "gnutar", "tar"),
+    ("grep", "gnugrep", "grep"),
+    ("node", "nodejs", "nodejs"),
+    ("openssl@3", "openssl", "libssl-dev"),
+    ("pkgconf", "pkgconf", "pkgconf"),
+    ("python@3.12", "python312", "python3.12"),
+    ("python@3.13", "python313", "python3.13"),
+    ("postgresql@16", "postgresql_16", "postgresql-16"),
+    ("rust", "rustc", "rustc"),
+    (
+        "the_silver_searcher",
+        "silver-searcher",
+        "silversearcher-ag",
+    ),
+    ("gnupg", "gnupg", "gnupg"),
+    ("libyaml", "libyaml", "libyaml-dev"),
+    ("readline", "readline", "libreadline-dev"),
+    ("zstd", "zstd", "zstd"),
+];
+
+/// Equivalent packages of a formula in other package managers
+#[derive(Default)]
+pub struct Equivalents {
+    pub nix: Vec<String>,
+    pub apt: Vec<String>,
+}
+
+impl Equivalents {
+    pub fn is_empty(&self) -> bool {
+        self.nix.is_empty() && self.apt.is_empty()
+    }
+}
+
+/// Looks the formula up in the bundled table of known renames
+pub fn bundled(formula: &str) -> Option<Equivalents> {
+    BUNDLED
+        .iter()
+        .find(|(name, _, _)| *name == formula)
+        .map(|(_, nix, apt)| Equivalents {
+            nix: vec![nix.to_string()],
+            apt: vec![apt.to_string()],
+        })
+}
+
+/// Looks the formula up on repology.org, which groups packages of the same project.
+/// The project name is assumed to match the formula name without the version suffix
+pub fn repology(formula: &str) -> anyhow::Result<Equivalents> {
+    #[derive(Deserialize)]
+    struct Package {
+        repo: String,
+        srcname: Option<String>,
+        binname: Option<String>,
+    }
+
+    let project = formula.split('@').next().unwrap_or(formula);
+
+    let client = reqwest::blocking::Client::new();
+
+    let body = client
+        .get(format!("{REPOLOGY_PROJECT_URL}/{project}"))
+        .header("User-Agent", "brewer")
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    let packages: Vec<Package> = serde_json::from_slice(&body)?;
+
+    let mut equivalents = Equivalents::default();
+
+    for package in packages {
+        if package.repo.starts_with("nix_") {
+            equivalents.nix.extend(package.srcname);
+        } else if package.repo.starts_with("debian_") || package.repo.starts_with("ubuntu_") {
+            equivalents.apt.extend(package.binname);
+        }
+    }
+
+    for names in [&mut equivalents.nix, &mut equivalents.apt] {
+        names.sort_unstable();
+        names.dedup();
+    }
+
+    Ok(equivalents)
+}
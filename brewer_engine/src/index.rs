@@ -0,0 +1,125 @@
+use std::cell::OnceCell;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::State;
+
+/// Lookups derived from the state. Each one is built on first use and shared
+/// for as long as the state is borrowed, so commands don't rebuild them per keg
+pub struct Indexes<'a> {
+    state: &'a State,
+
+    executables: OnceCell<HashMap<&'a str, Vec<&'a str>>>,
+
+    aliases: OnceCell<HashMap<&'a str, &'a str>>,
+
+    reverse: OnceCell<HashMap<&'a str, Vec<&'a str>>>,
+}
+
+impl<'a> Indexes<'a> {
+    pub fn new(state: &'a State) -> Self {
+        Self {
+            state,
+            executables: OnceCell::new(),
+            aliases: OnceCell::new(),
+            reverse: OnceCell::new(),
+        }
+    }
+
+    /// Keys of formulae providing each executable
+    pub fn executables(&self) -> &HashMap<&'a str, Vec<&'a str>> {
+        self.executables.get_or_init(|| {
+            let mut index: HashMap<&str, Vec<&str>> = HashMap::new();
+
+            for (key, formula) in &self.state.formulae.all {
+                for executable in &formula.executables {
+                    index.entry(executable).or_default().push(key);
+                }
+            }
+
+            index
+        })
+    }
+
+    /// Keys of formulae providing the executable
+    pub fn providers(&self, executable: &str) -> &[&'a str] {
+        self.executables()
+            .get(executable)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Key of the formula each alias refers to
+    pub fn aliases(&self) -> &HashMap<&'a str, &'a str> {
+        self.aliases.get_or_init(|| {
+            self.state
+                .formulae
+                .all
+                .iter()
+                .flat_map(|(key, f)| {
+                    f.base
+                        .aliases
+                        .iter()
+                        .map(move |a| (a.as_str(), key.as_str()))
+                })
+                .collect()
+        })
+    }
+
+    /// Key of the formula the alias refers to
+    pub fn alias(&self, name: &str) -> Option<&'a str> {
+        self.aliases().get(name).copied()
+    }
+
+    /// Keys of formulae directly depending on each formula at runtime
+    fn reverse(&self) -> &HashMap<&'a str, Vec<&'a str>> {
+        self.reverse.get_or_init(|| {
+            let mut index: HashMap<&str, Vec<&str>> = HashMap::new();
+
+            for (key, formula) in &self.state.formulae.all {
+                for dep in &formula.base.dependencies {
+                    index.entry(dep).or_default().push(key);
+                }
+            }
+
+            index
+        })
+    }
+
+    /// Formulae depending on the given one at runtime, directly or recursively
+    pub fn dependents(&self, name: &str, recursive: bool) -> BTreeSet<String> {
+        let reverse = self.reverse();
+
+        let mut found = BTreeSet::new();
+        let mut queue = vec![name];
+
+        while let Some(current) = queue.pop() {
+            for &dependent in reverse.get(current).into_iter().flatten() {
+                if dependent != name && found.insert(dependent.to_string()) && recursive {
+                    queue.push(dependent);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Installed formulae directly depending on the given one at runtime, sorted by name
+    pub fn installed_dependents(&self, name: &str) -> Vec<String> {
+        let formulae = &self.state.formulae;
+
+        let mut dependents: Vec<_> = self
+            .reverse()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|key| &formulae.all[*key].base.name)
+            .filter(|name| formulae.installed.contains_key(*name))
+            .cloned()
+            .collect();
+
+        dependents.sort_unstable();
+        dependents.dedup();
+
+        dependents
+    }
+}
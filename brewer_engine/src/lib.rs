@@ -1,17 +1,394 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
 use std::time::Duration;
 
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use derive_builder::Builder;
 
-use brewer_core::{models, Brew};
-use log::info;
+use brewer_core::models::graph::Graph;
+use brewer_core::progress::{self, Progress};
+use brewer_core::{explain, models, Brew, Owner, Sections};
+use log::{info, warn};
 
 use crate::store::Store;
 
+pub mod index;
+pub mod outdated;
+pub mod search;
 pub mod store;
 
 pub type State = models::State<models::formula::State, models::cask::State>;
 
+pub type Names = models::State<HashSet<String>, HashSet<String>>;
+
+/// Installed kegs difference between two scans
+pub struct InstalledDelta {
+    pub added: models::State<Vec<String>, Vec<String>>,
+    pub removed: models::State<Vec<String>, Vec<String>>,
+
+    /// Kegs installed with a version other than planned
+    pub drift: Vec<Drift>,
+}
+
+pub struct Drift {
+    pub name: String,
+    pub planned: String,
+    pub installed: String,
+}
+
+/// Installed version of a keg at the end of the last run and now, None when it is not installed
+pub struct Change {
+    pub name: String,
+    pub cask: bool,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl InstalledDelta {
+    pub fn new(before: &Names, after: &Names) -> InstalledDelta {
+        fn diff(lhs: &HashSet<String>, rhs: &HashSet<String>) -> Vec<String> {
+            let mut names: Vec<_> = lhs.difference(rhs).cloned().collect();
+
+            names.sort_unstable();
+
+            names
+        }
+
+        InstalledDelta {
+            added: models::State {
+                formulae: diff(&after.formulae, &before.formulae),
+                casks: diff(&after.casks, &before.casks),
+            },
+            removed: models::State {
+                formulae: diff(&before.formulae, &after.formulae),
+                casks: diff(&before.casks, &after.casks),
+            },
+            drift: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.formulae.is_empty()
+            && self.added.casks.is_empty()
+            && self.removed.formulae.is_empty()
+            && self.removed.casks.is_empty()
+    }
+}
+
+/// Outcome of looking a formula or cask up by name
+pub enum Resolution {
+    /// Key of the matching entry in the store
+    Found(String),
+
+    /// Keys of all candidates when the tap priority can't decide
+    Ambiguous(Vec<String>),
+
+    Missing,
+}
+
+impl Resolution {
+    pub fn is_ambiguous(&self) -> bool {
+        matches!(self, Resolution::Ambiguous(_))
+    }
+}
+
+/// Looks a formula up by bare or tap-qualified name
+pub fn resolve_formula(state: &State, name: &str) -> Resolution {
+    resolve(&state.formulae.all, name, |f| {
+        (f.base.name.as_str(), f.base.qualified_name())
+    })
+}
+
+/// Looks a cask up by bare or tap-qualified token
+pub fn resolve_cask(state: &State, name: &str) -> Resolution {
+    resolve(&state.casks.all, name, |c| {
+        (c.base.token.as_str(), c.base.qualified_token())
+    })
+}
+
+/// Whether the tap matches the filter given by the user. Case is ignored and
+/// the `homebrew-` prefix of the repository may be omitted, as brew does
+pub fn tap_matches(tap: &str, filter: &str) -> bool {
+    fn normalize(tap: &str) -> String {
+        let tap = tap.to_lowercase();
+
+        match tap.split_once('/') {
+            Some((owner, repo)) => {
+                format!("{owner}/{}", repo.strip_prefix("homebrew-").unwrap_or(repo))
+            }
+            None => tap,
+        }
+    }
+
+    normalize(tap) == normalize(filter)
+}
+
+fn resolve<T>(
+    store: &models::keg::Store<T>,
+    name: &str,
+    id: impl Fn(&T) -> (&str, &str),
+) -> Resolution {
+    if store.contains_key(name) {
+        return Resolution::Found(name.to_string());
+    }
+
+    let mut candidates: Vec<_> = store
+        .iter()
+        .filter(|(_, entry)| {
+            let (bare, full) = id(entry);
+
+            bare == name || full == name
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    candidates.sort_unstable();
+
+    match candidates.len() {
+        0 => Resolution::Missing,
+        1 => Resolution::Found(candidates.remove(0)),
+        _ => Resolution::Ambiguous(candidates),
+    }
+}
+
+/// Formulae which are not installed yet and would be installed along with the given one,
+/// following runtime dependencies. Includes the formula itself unless it is installed
+pub fn missing_closure(state: &State, name: &str) -> HashSet<String> {
+    let mut missing = HashSet::new();
+    let mut queue = vec![name.to_string()];
+
+    while let Some(name) = queue.pop() {
+        if state.formulae.installed.contains_key(&name) || missing.contains(&name) {
+            continue;
+        }
+
+        let Some(formula) = state.formulae.all.get(&name) else {
+            continue;
+        };
+
+        queue.extend(formula.base.dependencies.iter().cloned());
+        missing.insert(name);
+    }
+
+    missing
+}
+
+/// Installed formulae with the runtime dependencies which are not installed, sorted by name
+pub fn missing_dependencies(state: &State) -> BTreeMap<String, Vec<String>> {
+    let mut broken = BTreeMap::new();
+
+    for (name, formula) in &state.formulae.installed {
+        let mut missing: Vec<String> = formula
+            .upstream
+            .base
+            .dependencies
+            .iter()
+            .filter(|dep| {
+                // dependencies from third-party taps are tap-qualified
+                let short = dep.rsplit('/').next().unwrap_or(dep);
+
+                !state.formulae.installed.contains_key(short)
+            })
+            .cloned()
+            .collect();
+
+        if !missing.is_empty() {
+            missing.sort_unstable();
+            broken.insert(name.clone(), missing);
+        }
+    }
+
+    broken
+}
+
+/// Installed formulae which no other installed formula depends on at runtime,
+/// tap-qualified dependencies included
+pub fn leaves(state: &State) -> BTreeSet<String> {
+    let graph = Graph::new(&state.formulae.all, false);
+
+    let required: HashSet<&str> = state
+        .formulae
+        .installed
+        .keys()
+        .flat_map(|name| graph.direct(name))
+        .map(String::as_str)
+        .collect();
+
+    state
+        .formulae
+        .installed
+        .keys()
+        .filter(|name| !required.contains(name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Formulae installed as dependencies which no longer are required at runtime
+/// by anything installed on request, directly or transitively
+pub fn orphans(state: &State) -> BTreeSet<String> {
+    let graph = Graph::new(&state.formulae.all, false);
+
+    let is_orphan_candidate =
+        |f: &models::formula::installed::Formula| f.receipt.installed_as_dependency;
+
+    let required: HashSet<String> = state
+        .formulae
+        .installed
+        .values()
+        .filter(|f| !is_orphan_candidate(f))
+        .flat_map(|f| graph.recursive(&f.upstream.base.name))
+        .collect();
+
+    state
+        .formulae
+        .installed
+        .values()
+        .filter(|f| is_orphan_candidate(f) && !required.contains(&f.upstream.base.name))
+        .map(|f| f.upstream.base.name.clone())
+        .collect()
+}
+
+/// Kegs added, removed or installed with another version in `after`, sorted by name
+pub fn installed_changes(before: &store::Snapshot, after: &State) -> Vec<Change> {
+    let old: HashMap<_, _> = before
+        .formulae
+        .iter()
+        .chain(&before.casks)
+        .map(|(name, s)| (name.clone(), s.version.clone()))
+        .collect();
+
+    let new = installed_versions(after);
+
+    let names: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| Change {
+            name: name.clone(),
+            cask: after.casks.installed.contains_key(name) || before.casks.contains_key(name),
+            before: old.get(name).cloned(),
+            after: new.get(name).cloned(),
+        })
+        .collect()
+}
+
+/// Installed version of each formula and cask by name.
+/// Casks with several installed versions have them joined
+fn installed_versions(state: &State) -> HashMap<String, String> {
+    let formulae = state
+        .formulae
+        .installed
+        .iter()
+        .map(|(name, f)| (name.clone(), f.receipt.source.versions.stable.clone()));
+
+    let casks = state.casks.installed.iter().map(|(name, c)| {
+        let mut versions: Vec<_> = c.versions.iter().cloned().collect();
+        versions.sort_unstable();
+
+        (name.clone(), versions.join(", "))
+    });
+
+    formulae.chain(casks).collect()
+}
+
+/// Generations of installed formulae as far as their receipts tell, so that `brewer changes`
+/// works for formulae installed before the cache saw their version. Directly declared
+/// dependencies come from the receipt, everything else from the cached entry
+fn seeds(state: &State) -> Vec<(String, store::Generation)> {
+    state
+        .formulae
+        .installed
+        .iter()
+        .map(|(name, f)| {
+            let receipt = &f.receipt;
+            let mut release = store::Release::from(&f.upstream.base);
+
+            release.version = receipt.source.versions.stable.clone();
+
+            if let Some(dependencies) = &receipt.runtime_dependencies {
+                release.dependencies = dependencies
+                    .iter()
+                    .filter(|d| d.declared_directly)
+                    .map(|d| d.full_name.clone())
+                    .collect();
+            }
+
+            let recorded_at = receipt
+                .time
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .map(|t| t.naive_utc())
+                .unwrap_or_else(|| Utc::now().naive_utc());
+
+            (
+                name.clone(),
+                store::Generation {
+                    recorded_at,
+                    release,
+                },
+            )
+        })
+        .collect()
+}
+
+fn snapshot(state: &State) -> store::Snapshot {
+    let versions = installed_versions(state);
+
+    let entry = |name: &String, on_request: bool| store::Snapshotted {
+        version: versions.get(name).cloned().unwrap_or_default(),
+        on_request,
+    };
+
+    store::Snapshot {
+        created_at: Utc::now().naive_utc(),
+        formulae: state
+            .formulae
+            .installed
+            .iter()
+            .map(|(name, f)| (name.clone(), entry(name, f.receipt.installed_on_request)))
+            .collect(),
+        casks: state
+            .casks
+            .installed
+            .keys()
+            .map(|name| (name.clone(), entry(name, true)))
+            .collect(),
+    }
+}
+
+fn installed_names(state: &State) -> Names {
+    Names {
+        formulae: state.formulae.installed.keys().cloned().collect(),
+        casks: state.casks.installed.keys().cloned().collect(),
+    }
+}
+
+/// Login name of the current user, recorded with events
+fn current_user() -> String {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Operations which durations are recorded
+#[derive(Clone, Copy)]
+pub enum Operation {
+    Update,
+    Install,
+    Load,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Update => "update",
+            Operation::Install => "install",
+            Operation::Load => "load",
+        }
+    }
+}
+
 #[derive(Builder)]
 pub struct Engine {
     store: Store,
@@ -21,6 +398,27 @@ pub struct Engine {
 
     /// How often cache should expire. None means never
     cache_duration: Option<Duration>,
+
+    /// Reconcile installed kegs with `brew info --installed`
+    #[builder(default)]
+    verify_installed: bool,
+
+    /// Enrich formulae and casks with GitHub metadata. None disables it
+    #[builder(default)]
+    github: Option<GitHub>,
+
+    /// Never update the cache or fetch anything from the network
+    #[builder(default)]
+    offline: bool,
+}
+
+/// GitHub metadata enrichment settings
+#[derive(Clone)]
+pub struct GitHub {
+    pub token: String,
+
+    /// How long fetched metadata stays fresh
+    pub ttl: Duration,
 }
 
 impl Engine {
@@ -29,35 +427,294 @@ impl Engine {
             store,
             brew,
             cache_duration: None,
+            verify_installed: false,
+            github: None,
+            offline: false,
         }
     }
 
-    pub fn install(&self, kegs: Vec<models::Keg>) -> anyhow::Result<()> {
-        self.brew.install(kegs)?;
+    /// Installs the given kegs and rescans installed ones afterwards,
+    /// reporting kegs installed with versions other than planned
+    pub fn install(&mut self, kegs: Vec<models::Keg>) -> anyhow::Result<InstalledDelta> {
+        let before = self.installed_names()?;
 
-        Ok(())
+        let planned: Vec<_> = kegs
+            .iter()
+            .map(|k| match k {
+                models::Keg::Formula(f) => (f.base.name.clone(), f.base.versions.stable.clone()),
+                models::Keg::Cask(c) => (c.base.token.clone(), c.base.version.clone()),
+            })
+            .collect();
+
+        if let Err(e) = self.brew.install(kegs) {
+            let versions = planned.into_iter().collect();
+
+            self.record_failure(store::Action::Install, &versions, &HashMap::new());
+
+            return Err(e);
+        }
+
+        let Some(state) = self.rescan()? else {
+            return Ok(InstalledDelta::new(&before, &Names::default()));
+        };
+
+        let mut drift = Vec::new();
+
+        for (name, planned) in planned {
+            let installed = if let Some(f) = state.formulae.installed.get(&name) {
+                Some(f.receipt.source.versions.stable.clone())
+            } else if let Some(c) = state.casks.installed.get(&name) {
+                if c.versions.contains(&planned) {
+                    Some(planned.clone())
+                } else {
+                    c.versions.iter().next().cloned()
+                }
+            } else {
+                None
+            };
+
+            if let Some(installed) = installed {
+                if installed != planned {
+                    drift.push(Drift {
+                        name,
+                        planned,
+                        installed,
+                    });
+                }
+            }
+        }
+
+        let mut delta = InstalledDelta::new(&before, &installed_names(&state));
+
+        delta.drift = drift;
+
+        let added = delta.added.formulae.iter().chain(&delta.added.casks);
+
+        self.record_events(
+            store::Action::Install,
+            store::Outcome::Succeeded,
+            added.cloned(),
+            &installed_versions(&state),
+            &HashMap::new(),
+        )?;
+
+        Ok(delta)
     }
 
-    pub fn uninstall(&self, kegs: Vec<models::Keg>) -> anyhow::Result<()> {
-        self.brew.uninstall(kegs)?;
+    /// Uninstalls the given kegs and rescans installed ones afterwards
+    pub fn uninstall(&mut self, kegs: Vec<models::Keg>) -> anyhow::Result<InstalledDelta> {
+        let state = self.cache()?;
+
+        let before = state.as_ref().map(installed_names).unwrap_or_default();
+        let versions = state.as_ref().map(installed_versions).unwrap_or_default();
+
+        let targets: HashMap<_, _> = kegs
+            .iter()
+            .map(|k| {
+                let version = versions.get(k.name()).cloned().unwrap_or_default();
+
+                (k.name().to_string(), version)
+            })
+            .collect();
+
+        if let Err(e) = self.brew.uninstall(kegs) {
+            self.record_failure(store::Action::Uninstall, &targets, &HashMap::new());
+
+            return Err(e);
+        }
+
+        let after = self
+            .rescan()?
+            .as_ref()
+            .map(installed_names)
+            .unwrap_or_default();
+
+        let delta = InstalledDelta::new(&before, &after);
+
+        let removed = delta.removed.formulae.iter().chain(&delta.removed.casks);
+
+        self.record_events(
+            store::Action::Uninstall,
+            store::Outcome::Succeeded,
+            removed.cloned(),
+            &versions,
+            &HashMap::new(),
+        )?;
+
+        Ok(delta)
+    }
+
+    /// Outdated formulae and casks sorted by name
+    pub fn outdated(
+        &mut self,
+    ) -> anyhow::Result<models::State<Vec<outdated::Outdated>, Vec<outdated::Outdated>>> {
+        let state = self.cache_or_latest()?;
+
+        Ok(outdated::all(&state))
+    }
+
+    /// Kegs an upgrade with the given options would touch, and the ones left out with the reason
+    pub fn upgrade_plan(&mut self, options: &outdated::Options) -> anyhow::Result<outdated::Plan> {
+        let state = self.cache_or_latest()?;
+
+        Ok(outdated::plan(&state, options))
+    }
+
+    pub fn upgrade(&mut self, kegs: Vec<models::Keg>) -> anyhow::Result<()> {
+        let before = self.installed_versions()?;
+
+        let targets: HashMap<_, _> = kegs
+            .iter()
+            .map(|k| match k {
+                models::Keg::Formula(f) => (f.base.name.clone(), f.base.versions.stable.clone()),
+                models::Keg::Cask(c) => (c.base.token.clone(), c.base.version.clone()),
+            })
+            .collect();
+
+        if let Err(e) = self.brew.upgrade(kegs) {
+            self.record_failure(store::Action::Upgrade, &targets, &before);
+
+            return Err(e);
+        }
+
+        let after = self
+            .rescan()?
+            .as_ref()
+            .map(installed_versions)
+            .unwrap_or_default();
+
+        let upgraded = after
+            .iter()
+            .filter(|(name, version)| before.get(*name).is_some_and(|b| b != *version))
+            .map(|(name, _)| name.clone());
+
+        self.record_events(
+            store::Action::Upgrade,
+            store::Outcome::Succeeded,
+            upgraded,
+            &after,
+            &before,
+        )?;
 
         Ok(())
     }
 
+    /// Names of installed formulae and casks, scanned from the local filesystem
+    pub fn installed_names(&self) -> anyhow::Result<Names> {
+        Ok(self
+            .cache()?
+            .as_ref()
+            .map(installed_names)
+            .unwrap_or_default())
+    }
+
+    fn installed_versions(&self) -> anyhow::Result<HashMap<String, String>> {
+        Ok(self
+            .cache()?
+            .as_ref()
+            .map(installed_versions)
+            .unwrap_or_default())
+    }
+
+    /// Appends an event per name to the log, versions are looked up by name
+    fn record_events(
+        &mut self,
+        action: store::Action,
+        outcome: store::Outcome,
+        names: impl Iterator<Item = String>,
+        versions: &HashMap<String, String>,
+        previous: &HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let at = Utc::now().naive_utc();
+        let user = current_user();
+
+        let mut events: Vec<_> = names
+            .map(|name| store::Event {
+                at,
+                action,
+                version: versions.get(&name).cloned().unwrap_or_default(),
+                previous: previous.get(&name).cloned(),
+                user: user.clone(),
+                outcome,
+                name,
+            })
+            .collect();
+
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        events.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        self.store.add_events(&events)
+    }
+
+    /// Records each of the planned kegs of a failed operation.
+    /// Failing to record is only logged, so the error of brew is not masked
+    fn record_failure(
+        &mut self,
+        action: store::Action,
+        planned: &HashMap<String, String>,
+        previous: &HashMap<String, String>,
+    ) {
+        let names = planned.keys().cloned();
+
+        if let Err(e) = self.record_events(action, store::Outcome::Failed, names, planned, previous)
+        {
+            warn!("failed to record the {} failure: {e}", action.name());
+        }
+    }
+
+    /// Succeeded events of the most recent operation, events of one operation share the time
+    pub fn last_batch(&self) -> anyhow::Result<Vec<store::Event>> {
+        let mut events = self.store.get_events(None)?;
+
+        events.retain(|e| e.outcome == store::Outcome::Succeeded);
+
+        let Some(last) = events.last().map(|e| e.at) else {
+            return Ok(Vec::new());
+        };
+
+        events.retain(|e| e.at == last);
+
+        Ok(events)
+    }
+
+    /// Installs, upgrades and uninstalls performed through brewer, oldest first
+    pub fn events(&self, since: Option<NaiveDateTime>) -> anyhow::Result<Vec<store::Event>> {
+        self.store.get_events(since)
+    }
+
     pub fn cache_or_latest(&mut self) -> anyhow::Result<State> {
         let cache = self.cache()?;
 
-        if self.cache_expired()? || cache.is_none() {
+        let mut state = if self.cache_expired()? || cache.is_none() {
             info!("updating the cache, this will take some time");
 
-            let latest = self.fetch_latest()?;
+            let mut latest = self.fetch_latest()?;
 
             self.update_cache(&latest)?;
+            self.apply_user_data(&mut latest)?;
+            self.apply_github(&mut latest)?;
 
-            Ok(latest)
+            latest
         } else {
-            Ok(cache.unwrap())
+            let state = cache.unwrap();
+
+            self.remember_installed(&state)?;
+
+            state
+        };
+
+        if self.github_expired()? {
+            // enrichment is optional, a failed fetch should not break the command
+            match self.refresh_github(&state) {
+                Ok(()) => self.apply_github(&mut state)?,
+                Err(e) => warn!("failed to fetch GitHub metadata: {e}"),
+            }
         }
+
+        Ok(state)
     }
 
     pub fn cache(&self) -> anyhow::Result<Option<State>> {
@@ -65,9 +722,13 @@ impl Engine {
             return Ok(None);
         };
 
-        let installed = self.brew.installed(&all)?;
+        let installed = if self.verify_installed {
+            self.brew.installed_verified(&all)?
+        } else {
+            self.brew.installed(&all)?
+        };
 
-        let state = State {
+        let mut state = State {
             formulae: models::formula::State {
                 all: all.formulae,
                 installed: installed.formulae,
@@ -78,39 +739,688 @@ impl Engine {
             },
         };
 
+        self.apply_user_data(&mut state)?;
+        self.apply_github(&mut state)?;
+
         Ok(Some(state))
     }
 
-    pub fn cache_expired(&self) -> anyhow::Result<bool> {
-        let Some(cache_duration) = self.cache_duration else {
-            return Ok(false);
-        };
+    /// Saved snapshots of installed formulae and casks by name
+    pub fn snapshots(&self) -> anyhow::Result<models::keg::Store<store::Snapshot>> {
+        self.store.get_snapshots()
+    }
+
+    /// Saves installed formulae and casks under the given name, replacing an existing snapshot
+    pub fn create_snapshot(&mut self, name: &str) -> anyhow::Result<store::Snapshot> {
+        let state = self.cache_or_latest()?;
+        let snapshot = snapshot(&state);
+
+        self.store.set_snapshot(name, &snapshot)?;
+
+        Ok(snapshot)
+    }
+
+    pub fn delete_snapshot(&mut self, name: &str) -> anyhow::Result<()> {
+        self.store.delete_snapshot(name)
+    }
+
+    /// User-defined tags by formula or cask name
+    pub fn user_tags(&self) -> anyhow::Result<models::keg::Store<HashSet<String>>> {
+        self.store.get_tags()
+    }
+
+    /// Adds user-defined tags to the given formula or cask
+    pub fn tag(&mut self, name: &str, tags: &[String]) -> anyhow::Result<()> {
+        let mut current = self.store.get_tags()?.remove(name).unwrap_or_default();
+
+        current.extend(tags.iter().map(|t| t.to_lowercase()));
+
+        self.store.set_tags(name, &current)
+    }
+
+    /// Removes user-defined tags from the given formula or cask
+    pub fn untag(&mut self, name: &str, tags: &[String]) -> anyhow::Result<()> {
+        let mut current = self.store.get_tags()?.remove(name).unwrap_or_default();
+
+        for tag in tags {
+            current.remove(&tag.to_lowercase());
+        }
+
+        self.store.set_tags(name, &current)
+    }
+
+    /// Sets a note on the given formula or cask
+    pub fn note(&mut self, name: &str, note: &str) -> anyhow::Result<()> {
+        self.store.set_note(name, note)
+    }
+
+    pub fn delete_note(&mut self, name: &str) -> anyhow::Result<()> {
+        self.store.delete_note(name)
+    }
+
+    pub fn star(&mut self, name: &str) -> anyhow::Result<()> {
+        self.store.set_starred(name, true)
+    }
+
+    pub fn unstar(&mut self, name: &str) -> anyhow::Result<()> {
+        self.store.set_starred(name, false)
+    }
+
+    /// Merges user-defined tags, notes and stars into the state
+    fn apply_user_data(&self, state: &mut State) -> anyhow::Result<()> {
+        for name in self.store.get_starred()? {
+            if let Some(f) = state.formulae.all.get_mut(&name) {
+                f.starred = true;
+            }
+
+            if let Some(f) = state.formulae.installed.get_mut(&name) {
+                f.upstream.starred = true;
+            }
+
+            if let Some(c) = state.casks.all.get_mut(&name) {
+                c.starred = true;
+            }
+
+            if let Some(c) = state.casks.installed.get_mut(&name) {
+                c.upstream.starred = true;
+            }
+        }
+
+        for (name, note) in self.store.get_notes()? {
+            if let Some(f) = state.formulae.all.get_mut(&name) {
+                f.note = Some(note.clone());
+            }
+
+            if let Some(f) = state.formulae.installed.get_mut(&name) {
+                f.upstream.note = Some(note.clone());
+            }
 
-        let last_update = self.store.last_update()?;
+            if let Some(c) = state.casks.all.get_mut(&name) {
+                c.note = Some(note.clone());
+            }
+
+            if let Some(c) = state.casks.installed.get_mut(&name) {
+                c.upstream.note = Some(note.clone());
+            }
+        }
+
+        for (name, tags) in self.store.get_tags()? {
+            if let Some(f) = state.formulae.all.get_mut(&name) {
+                f.tags.extend(tags.iter().cloned());
+            }
+
+            if let Some(f) = state.formulae.installed.get_mut(&name) {
+                f.upstream.tags.extend(tags.iter().cloned());
+            }
 
-        match last_update {
-            Some(last_update) => {
-                let now = Utc::now().naive_utc();
+            if let Some(c) = state.casks.all.get_mut(&name) {
+                c.tags.extend(tags.iter().cloned());
+            }
 
-                Ok(last_update + cache_duration <= now)
+            if let Some(c) = state.casks.installed.get_mut(&name) {
+                c.upstream.tags.extend(tags.iter().cloned());
             }
+        }
+
+        Ok(())
+    }
+
+    /// Merges fetched GitHub metadata into the state
+    fn apply_github(&self, state: &mut State) -> anyhow::Result<()> {
+        if self.github.is_none() {
+            return Ok(());
+        }
+
+        let repos = self.store.get_github()?;
+
+        if repos.is_empty() {
+            return Ok(());
+        }
+
+        for f in state.formulae.all.values_mut() {
+            f.github = f
+                .base
+                .github_repository()
+                .and_then(|r| repos.get(&r).cloned());
+        }
+
+        for f in state.formulae.installed.values_mut() {
+            f.upstream.github = f
+                .upstream
+                .base
+                .github_repository()
+                .and_then(|r| repos.get(&r).cloned());
+        }
+
+        for c in state.casks.all.values_mut() {
+            c.github = c
+                .base
+                .github_repository()
+                .and_then(|r| repos.get(&r).cloned());
+        }
+
+        for c in state.casks.installed.values_mut() {
+            c.upstream.github = c
+                .upstream
+                .base
+                .github_repository()
+                .and_then(|r| repos.get(&r).cloned());
+        }
+
+        Ok(())
+    }
+
+    fn github_expired(&self) -> anyhow::Result<bool> {
+        let Some(github) = &self.github else {
+            return Ok(false);
+        };
+
+        if self.offline {
+            return Ok(false);
+        }
+
+        match self.store.last_github_update()? {
+            Some(last_update) => Ok(last_update + github.ttl <= Utc::now().naive_utc()),
             None => Ok(true),
         }
     }
 
+    /// Fetches metadata of all GitHub repositories referenced by homepages
+    fn refresh_github(&mut self, state: &State) -> anyhow::Result<()> {
+        let Some(github) = &self.github else {
+            return Ok(());
+        };
+
+        info!("fetching GitHub metadata, this will take some time");
+
+        let repos: BTreeSet<String> = state
+            .formulae
+            .all
+            .values()
+            .filter_map(|f| f.base.github_repository())
+            .chain(
+                state
+                    .casks
+                    .all
+                    .values()
+                    .filter_map(|c| c.base.github_repository()),
+            )
+            .collect();
+
+        let repos: Vec<_> = repos.into_iter().collect();
+        let fetched = brewer_core::github::repositories(&github.token, &repos)?;
+
+        if fetched.complete {
+            return self.store.set_github(&fetched.repos);
+        }
+
+        warn!("GitHub rate limit reached, keeping cached metadata of the remaining repositories");
+
+        // still recorded as an update, so that the limit is not hit again until the ttl passes
+        let mut cached = self.store.get_github()?;
+
+        cached.extend(fetched.repos);
+
+        self.store.set_github(&cached)
+    }
+
+    /// Time passed since the last cache update. None if the cache was never updated
+    pub fn cache_age(&self) -> anyhow::Result<Option<Duration>> {
+        self.store.age()
+    }
+
+    pub fn cache_expired(&self) -> anyhow::Result<bool> {
+        if self.offline {
+            return Ok(false);
+        }
+
+        self.store.expired(self.cache_duration)
+    }
+
     pub fn update_cache(&mut self, state: &State) -> anyhow::Result<()> {
-        self.store.set_state(store::State {
-            formulae: state.formulae.all.clone(),
-            casks: state.casks.all.clone(),
-        })?;
+        self.update_cache_of(state, Sections::ALL)
+    }
 
-        Ok(())
+    /// Stores the state, recording that the given sections were refreshed
+    pub fn update_cache_of(&mut self, state: &State, sections: Sections) -> anyhow::Result<()> {
+        self.store.set_state_of(
+            store::State {
+                formulae: state.formulae.all.clone(),
+                casks: state.casks.all.clone(),
+            },
+            sections,
+        )?;
+
+        let installed = state
+            .formulae
+            .installed
+            .iter()
+            .map(|(name, f)| (name.clone(), f.receipt.source.versions.stable.clone()))
+            .collect();
+
+        self.store.seed_history(seeds(state))?;
+        self.store.record_history(&state.formulae.all, &installed)?;
+        self.remember_installed(state)
+    }
+
+    /// Same as [`Engine::cache`], also saving the scanned installed kegs as the baseline
+    /// [`Engine::installed_diff`] compares against next time
+    fn rescan(&mut self) -> anyhow::Result<Option<State>> {
+        let state = self.cache()?;
+
+        if let Some(state) = &state {
+            self.remember_installed(state)?;
+        }
+
+        Ok(state)
+    }
+
+    fn remember_installed(&mut self, state: &State) -> anyhow::Result<()> {
+        self.store.set_installed(&snapshot(state))
+    }
+
+    /// Kegs changed since installed ones were last scanned by a command,
+    /// None if they never were or the cache is missing
+    pub fn installed_diff(&mut self) -> anyhow::Result<Option<Vec<Change>>> {
+        let Some(before) = self.store.get_installed()? else {
+            return Ok(None);
+        };
+
+        let Some(after) = self.rescan()? else {
+            return Ok(None);
+        };
+
+        Ok(Some(installed_changes(&before, &after)))
+    }
+
+    /// Time since the given section of the index was refreshed, None if never
+    pub fn section_age(&self, section: &str) -> anyhow::Result<Option<Duration>> {
+        let Some(last_update) = self.store.last_section_update(section)? else {
+            return Ok(None);
+        };
+
+        let age = Utc::now().naive_utc() - last_update;
+
+        Ok(Some(age.to_std().unwrap_or_default()))
+    }
+
+    /// Search index of the cache, updating the cache first when it is expired or missing
+    pub fn search_index(&mut self) -> anyhow::Result<search::Index> {
+        if self.cache_expired()? || self.cache_age()?.is_none() {
+            self.cache_or_latest()?;
+        }
+
+        if let Some(index) = self.store.get_search_index()? {
+            return Ok(index);
+        }
+
+        // cache written before the index existed
+        let Some(state) = self.store.get_state()? else {
+            return Ok(search::Index::default());
+        };
+
+        let index = search::build(&state);
+
+        self.store.set_search_index(&index)?;
+
+        Ok(index)
+    }
+
+    /// Search index as cached, never updating the cache or running brew
+    pub fn cached_search_index(&self) -> anyhow::Result<Option<search::Index>> {
+        self.store.get_search_index()
+    }
+
+    /// Cached formulae matching the predicate without loading the whole state.
+    /// Entries are as fetched, user tags, notes and stars are not applied
+    pub fn query_formulae(
+        &self,
+        predicate: impl FnMut(&models::formula::Formula) -> bool,
+    ) -> anyhow::Result<Vec<models::formula::Formula>> {
+        self.store.query_formulae(predicate)
+    }
+
+    /// Cached casks matching the predicate, see [`Engine::query_formulae`]
+    pub fn query_casks(
+        &self,
+        predicate: impl FnMut(&models::cask::Cask) -> bool,
+    ) -> anyhow::Result<Vec<models::cask::Cask>> {
+        self.store.query_casks(predicate)
+    }
+
+    /// First cached formula matching the predicate, stops scanning as soon as it is found
+    pub fn find_formula(
+        &self,
+        predicate: impl FnMut(&models::formula::Formula) -> bool,
+    ) -> anyhow::Result<Option<models::formula::Formula>> {
+        self.store.find_formula(predicate)
+    }
+
+    /// First cached cask matching the predicate, see [`Engine::find_formula`]
+    pub fn find_cask(
+        &self,
+        predicate: impl FnMut(&models::cask::Cask) -> bool,
+    ) -> anyhow::Result<Option<models::cask::Cask>> {
+        self.store.find_cask(predicate)
+    }
+
+    /// Recorded upstream generations of the given formula, oldest first
+    pub fn history(&self, name: &str) -> anyhow::Result<Vec<store::Generation>> {
+        self.store.get_history(name)
+    }
+
+    /// Installed formulae with their taps, whose taps are no longer tapped.
+    /// Such formulae will never be updated
+    pub fn untapped(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let taps = self.brew.taps()?;
+
+        let mut untapped: Vec<_> = self
+            .brew
+            .installed_receipts()?
+            .into_iter()
+            .filter_map(|(name, receipt)| {
+                let tap = receipt.source.tap?;
+
+                if taps.contains(&tap) {
+                    None
+                } else {
+                    Some((name, tap))
+                }
+            })
+            .collect();
+
+        untapped.sort_unstable();
+
+        Ok(untapped)
+    }
+
+    /// Records how long the operation took.
+    /// Returns its typical duration if this run was unusually slow
+    pub fn record_timing(
+        &mut self,
+        operation: Operation,
+        took: Duration,
+    ) -> anyhow::Result<Option<Duration>> {
+        const SAMPLES: usize = 20;
+        const MIN_SAMPLES: usize = 3;
+        const SLOW_FACTOR: u32 = 2;
+        const MIN_SLOW: Duration = Duration::from_secs(1);
+
+        let mut timings = self.store.get_timings(operation.name())?;
+
+        let typical = if timings.len() >= MIN_SAMPLES {
+            let average = timings.iter().sum::<u64>() / timings.len() as u64;
+
+            Some(Duration::from_millis(average))
+        } else {
+            None
+        };
+
+        timings.push(took.as_millis() as u64);
+
+        if timings.len() > SAMPLES {
+            timings.drain(..timings.len() - SAMPLES);
+        }
+
+        self.store.set_timings(operation.name(), &timings)?;
+
+        Ok(typical.filter(|typical| took >= MIN_SLOW && took > *typical * SLOW_FACTOR))
+    }
+
+    pub fn cleanup_plan(&self) -> anyhow::Result<models::cleanup::Plan> {
+        self.brew.cleanup_plan()
+    }
+
+    pub fn cleanup(&self) -> anyhow::Result<()> {
+        self.brew.cleanup()
+    }
+
+    /// brew commands the action would run, nothing is executed
+    pub fn explain(
+        &self,
+        action: explain::Action,
+        kegs: Vec<models::Keg>,
+    ) -> Vec<explain::Invocation> {
+        self.brew.explain(action, kegs)
+    }
+
+    pub fn doctor(&self) -> anyhow::Result<Vec<models::doctor::Diagnostic>> {
+        self.brew.doctor()
+    }
+
+    /// Tapped taps, including the official ones
+    pub fn taps(&self) -> anyhow::Result<HashSet<String>> {
+        self.brew.taps()
+    }
+
+    pub fn tap(&self, tap: &str) -> anyhow::Result<()> {
+        self.brew.tap(tap)
+    }
+
+    pub fn cask_artifacts(
+        &self,
+        cask: &models::cask::Cask,
+    ) -> Vec<(models::cask::artifact::Artifact, bool)> {
+        self.brew.cask_artifacts(cask)
+    }
+
+    pub fn cat(&self, keg: &models::Keg) -> anyhow::Result<String> {
+        self.brew.cat(keg)
+    }
+
+    pub fn shellenv(&self) -> anyhow::Result<String> {
+        self.brew.shellenv()
+    }
+
+    pub fn keg_env(&self, name: &str) -> Vec<(&'static str, String)> {
+        self.brew.keg_env(name)
+    }
+
+    /// Disk usage in bytes of installed formulae in Cellar and casks in Caskroom by name.
+    /// Sizes are cached until the installed versions change
+    pub fn installed_sizes(
+        &mut self,
+        state: &State,
+    ) -> anyhow::Result<models::State<HashMap<String, u64>, HashMap<String, u64>>> {
+        let cached = self.store.get_sizes()?;
+        let mut sizes = brewer_core::size::Cache::new();
+
+        let mut measure = |key: String, dir: &std::path::Path| {
+            if let Some(measured) = cached.get(&key) {
+                sizes.insert(key.clone(), measured.clone());
+            }
+
+            brewer_core::size::measure(&mut sizes, &key, dir)
+        };
+
+        let formulae = state
+            .formulae
+            .installed
+            .keys()
+            .map(|name| {
+                let dir = self.brew.cellar_dir(name);
+
+                (name.clone(), measure(format!("Cellar/{name}"), &dir))
+            })
+            .collect();
+
+        let casks = state
+            .casks
+            .installed
+            .keys()
+            .map(|name| {
+                let dir = self.brew.caskroom_dir(name);
+
+                (name.clone(), measure(format!("Caskroom/{name}"), &dir))
+            })
+            .collect();
+
+        self.store.set_sizes(&sizes)?;
+
+        Ok(models::State { formulae, casks })
+    }
+
+    pub fn keg_files(&self, name: &str) -> models::formula::Files {
+        self.brew.keg_files(name)
+    }
+
+    /// Installed formula or cask owning the file, the inverse of which
+    pub fn owner(&self, state: &State, path: &Path) -> anyhow::Result<Option<models::Keg>> {
+        let casks = state.casks.installed.values().map(|c| &c.upstream);
+
+        let keg = match self.brew.owner(path, casks)? {
+            Some(Owner::Formula(name)) => state
+                .formulae
+                .installed
+                .get(&name)
+                .map(|f| f.upstream.clone().into()),
+            Some(Owner::Cask(token)) => state
+                .casks
+                .installed
+                .get(&token)
+                .map(|c| c.upstream.clone().into()),
+            None => None,
+        };
+
+        Ok(keg)
+    }
+
+    pub fn link(&self, formula: &models::formula::Formula, force: bool) -> anyhow::Result<()> {
+        self.brew.link(formula, force)
+    }
+
+    pub fn unlink(&self, formula: &models::formula::Formula) -> anyhow::Result<()> {
+        self.brew.unlink(formula)
+    }
+
+    pub fn held_locks(&self) -> anyhow::Result<Vec<String>> {
+        self.brew.held_locks()
+    }
+
+    pub fn pin(&self, names: &[String]) -> anyhow::Result<()> {
+        self.brew.pin(names)
+    }
+
+    pub fn tap_checkout(&self, tap: &str) -> anyhow::Result<Option<models::tap::Checkout>> {
+        self.brew.tap_checkout(tap)
     }
 
     pub fn fetch_latest(&self) -> anyhow::Result<State> {
-        let state = self.brew.state()?;
+        self.fetch_latest_with(&progress::Silent)
+    }
+
+    /// Same as [`Engine::fetch_latest`], reporting each phase to the progress
+    pub fn fetch_latest_with(&self, progress: &dyn Progress) -> anyhow::Result<State> {
+        if self.offline {
+            return Err(anyhow::anyhow!("can not fetch the index in offline mode"));
+        }
+
+        let state = self.brew.state_with(progress)?;
 
         Ok(state)
     }
+
+    /// Refreshes the given sections, keeping the rest from the cache
+    pub fn fetch_sections_with(
+        &self,
+        progress: &dyn Progress,
+        sections: Sections,
+    ) -> anyhow::Result<State> {
+        if sections.is_all() {
+            return self.fetch_latest_with(progress);
+        }
+
+        if self.offline {
+            return Err(anyhow::anyhow!("can not fetch the index in offline mode"));
+        }
+
+        let previous = self.store.get_state()?.unwrap_or_default();
+
+        self.brew.refresh_with(progress, sections, previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(name: &str, tap: &str, deps: &[&str]) -> models::formula::Formula {
+        serde_json::from_value(serde_json::json!({
+            "base": {
+                "name": name,
+                "tap": tap,
+                "versions": { "stable": "1.0" },
+                "dependencies": deps,
+            },
+            "executables": [],
+        }))
+        .unwrap()
+    }
+
+    /// State with every formula installed, on request unless listed in `as_dependency`
+    fn state(formulae: Vec<models::formula::Formula>, as_dependency: &[&str]) -> State {
+        let installed = formulae
+            .iter()
+            .map(|f| {
+                let dependency = as_dependency.contains(&f.base.name.as_str());
+
+                let installed = serde_json::from_value(serde_json::json!({
+                    "upstream": f,
+                    "receipt": {
+                        "source": { "spec": "stable", "versions": { "stable": "1.0" } },
+                        "installed_as_dependency": dependency,
+                        "installed_on_request": !dependency,
+                    },
+                }))
+                .unwrap();
+
+                (f.base.name.clone(), installed)
+            })
+            .collect();
+
+        let all = formulae
+            .into_iter()
+            .map(|f| (f.base.name.clone(), f))
+            .collect();
+
+        State {
+            formulae: models::formula::State { all, installed },
+            casks: models::cask::State {
+                all: HashMap::new(),
+                installed: HashMap::new(),
+            },
+        }
+    }
+
+    /// `app` from homebrew/core depending on `codec` from a third-party tap,
+    /// which depends on `libogg` from homebrew/core
+    fn third_party_chain(as_dependency: &[&str]) -> State {
+        state(
+            vec![
+                formula("app", "homebrew/core", &["acme/media/codec"]),
+                formula("codec", "acme/media", &["libogg"]),
+                formula("libogg", "homebrew/core", &[]),
+                formula("stale", "homebrew/core", &[]),
+            ],
+            as_dependency,
+        )
+    }
+
+    #[test]
+    fn orphans_keep_dependencies_from_third_party_taps() {
+        let state = third_party_chain(&["codec", "libogg", "stale"]);
+
+        assert_eq!(orphans(&state), BTreeSet::from(["stale".to_string()]));
+    }
+
+    #[test]
+    fn leaves_exclude_dependencies_of_third_party_formulae() {
+        let state = third_party_chain(&[]);
+
+        assert_eq!(
+            leaves(&state),
+            BTreeSet::from(["app", "stale"].map(String::from))
+        );
+    }
 }
 
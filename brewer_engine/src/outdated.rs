@@ -0,0 +1,300 @@
+use std::cmp::Ordering;
+
+use brewer_core::models;
+use brewer_core::models::version::{self, Bump};
+
+use crate::State;
+
+/// Installed keg older than the cached upstream one
+#[derive(Clone)]
+pub struct Outdated {
+    pub name: String,
+    pub tap: String,
+    pub installed: String,
+    pub latest: String,
+
+    /// Pinned formulae are left alone by upgrades
+    pub pinned: bool,
+
+    /// Version the app updated itself to, when newer than the installed cask
+    pub locally_newer: Option<String>,
+}
+
+impl Outdated {
+    /// Size of the pending upgrade, from the newest installed version for casks
+    pub fn bump(&self) -> models::version::Bump {
+        let installed = self
+            .installed
+            .split(", ")
+            .max_by(|a, b| models::version::compare(a, b))
+            .unwrap_or_default();
+
+        models::version::bump(installed, &self.latest)
+    }
+}
+
+/// Compares the receipt version with the upstream stable version, then the revisions.
+/// Formulae installed from HEAD are never considered outdated.
+pub fn formula(formula: &models::formula::installed::Formula) -> Option<Outdated> {
+    let source = &formula.receipt.source;
+    let upstream = &formula.upstream.base;
+
+    if matches!(source.spec, models::formula::receipt::Spec::Head) {
+        return None;
+    }
+
+    let installed = &source.versions.stable;
+    let latest = &upstream.versions.stable;
+
+    if latest.is_empty() {
+        return None;
+    }
+
+    let outdated = if installed == latest {
+        formula.receipt.revision < upstream.revision
+    } else {
+        newer(installed, latest)
+    };
+
+    if !outdated {
+        return None;
+    }
+
+    Some(Outdated {
+        name: upstream.name.clone(),
+        tap: upstream.tap.clone(),
+        installed: with_revision(installed, formula.receipt.revision),
+        latest: with_revision(latest, upstream.revision),
+        pinned: formula.pinned,
+        locally_newer: None,
+    })
+}
+
+/// Compares the newest installed version with the upstream one.
+/// Casks versioned as `latest` update themselves and are never considered outdated.
+pub fn cask(cask: &models::cask::installed::Cask) -> Option<Outdated> {
+    let latest = &cask.upstream.base.version;
+
+    if latest.is_empty() || latest == "latest" || cask.versions.contains(latest) {
+        return None;
+    }
+
+    let newest = cask.versions.iter().max_by(|a, b| version::compare(a, b))?;
+
+    if !newer(newest, latest) {
+        return None;
+    }
+
+    let mut versions: Vec<_> = cask.versions.iter().cloned().collect();
+    versions.sort_unstable();
+
+    Some(Outdated {
+        name: cask.upstream.base.token.clone(),
+        tap: cask.upstream.base.tap.clone(),
+        installed: versions.join(", "),
+        latest: latest.clone(),
+        pinned: false,
+        locally_newer: cask
+            .locally_newer()
+            .then(|| cask.app_version.clone())
+            .flatten(),
+    })
+}
+
+/// Whether the upstream version is newer than the installed one. Versions equal by their
+/// numeric components but spelled differently, e.g. with another cask build suffix, count as newer
+fn newer(installed: &str, latest: &str) -> bool {
+    match version::compare(installed, latest) {
+        Ordering::Less => true,
+        Ordering::Equal => installed != latest,
+        Ordering::Greater => false,
+    }
+}
+
+/// Keg version as brew shows it, "1.2_1" for revision 1
+fn with_revision(version: &str, revision: u32) -> String {
+    if revision == 0 {
+        version.to_string()
+    } else {
+        format!("{version}_{revision}")
+    }
+}
+
+/// Outdated formulae and casks sorted by name
+pub fn all(state: &State) -> models::State<Vec<Outdated>, Vec<Outdated>> {
+    let mut formulae: Vec<_> = state
+        .formulae
+        .installed
+        .values()
+        .filter_map(formula)
+        .collect();
+    let mut casks: Vec<_> = state.casks.installed.values().filter_map(cask).collect();
+
+    formulae.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    casks.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    models::State { formulae, casks }
+}
+
+/// What to upgrade, every outdated formula and cask by default
+#[derive(Default)]
+pub struct Options {
+    /// Formulae and casks to upgrade, all outdated ones if empty
+    pub names: Vec<String>,
+
+    pub formulae_only: bool,
+
+    pub casks_only: bool,
+
+    /// Include casks which apps updated themselves past the installed version
+    pub locally_newer: bool,
+
+    /// Only upgrade kegs from these taps, any tap if empty
+    pub taps: Vec<String>,
+
+    /// Only apply upgrades of these sizes, any size if empty
+    pub bumps: Vec<Bump>,
+}
+
+/// Why a keg is left out of an upgrade
+#[derive(Clone, Copy, PartialEq)]
+pub enum Skip {
+    /// Not installed, or not of the requested kind
+    NotInstalled,
+
+    /// The latest version is installed already
+    UpToDate,
+
+    Pinned,
+
+    /// The app updated itself past the installed version
+    LocallyNewer,
+
+    /// From a tap not given in the options
+    Tap,
+
+    /// Upgrade of a size not given in the options
+    Bump(Bump),
+}
+
+/// Outdated keg picked for an upgrade
+pub struct Upgrade {
+    pub keg: models::Keg,
+    pub outdated: Outdated,
+}
+
+pub struct Skipped {
+    pub name: String,
+
+    /// Missing for kegs which are not installed or up to date
+    pub outdated: Option<Outdated>,
+
+    pub reason: Skip,
+}
+
+#[derive(Default)]
+pub struct Plan {
+    pub upgrades: Vec<Upgrade>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// Splits the requested kegs, or all outdated ones, into upgrades and skipped kegs.
+/// Both are sorted by name unless names are given, then their order is kept
+pub fn plan(state: &State, options: &Options) -> Plan {
+    let mut plan = Plan::default();
+
+    let names: Vec<&String> = if options.names.is_empty() {
+        let formulae = state
+            .formulae
+            .installed
+            .keys()
+            .filter(|_| !options.casks_only);
+        let casks = state
+            .casks
+            .installed
+            .keys()
+            .filter(|_| !options.formulae_only);
+
+        let mut names: Vec<_> = formulae.chain(casks).collect();
+        names.sort_unstable();
+
+        names
+    } else {
+        options.names.iter().collect()
+    };
+
+    for name in names {
+        let formula = state
+            .formulae
+            .installed
+            .get(name)
+            .filter(|_| !options.casks_only);
+        let cask = state
+            .casks
+            .installed
+            .get(name)
+            .filter(|_| !options.formulae_only);
+
+        let found = match (formula, cask) {
+            (Some(f), _) => Some((models::Keg::Formula(f.upstream.clone()), self::formula(f))),
+            (None, Some(c)) => Some((models::Keg::Cask(c.upstream.clone()), self::cask(c))),
+            (None, None) => None,
+        };
+
+        let skip = |outdated, reason| Skipped {
+            name: name.clone(),
+            outdated,
+            reason,
+        };
+
+        let (keg, outdated) = match found {
+            Some((keg, Some(outdated))) => (keg, outdated),
+            Some((_, None)) => {
+                // everything installed is looked at when no names are given
+                if !options.names.is_empty() {
+                    plan.skipped.push(skip(None, Skip::UpToDate));
+                }
+
+                continue;
+            }
+            None => {
+                plan.skipped.push(skip(None, Skip::NotInstalled));
+                continue;
+            }
+        };
+
+        match skipped(&outdated, options) {
+            Some(reason) => plan.skipped.push(skip(Some(outdated), reason)),
+            None => plan.upgrades.push(Upgrade { keg, outdated }),
+        }
+    }
+
+    plan
+}
+
+fn skipped(outdated: &Outdated, options: &Options) -> Option<Skip> {
+    if outdated.pinned {
+        return Some(Skip::Pinned);
+    }
+
+    if outdated.locally_newer.is_some() && !options.locally_newer {
+        return Some(Skip::LocallyNewer);
+    }
+
+    if !options.taps.is_empty()
+        && !options
+            .taps
+            .iter()
+            .any(|t| crate::tap_matches(&outdated.tap, t))
+    {
+        return Some(Skip::Tap);
+    }
+
+    let bump = outdated.bump();
+
+    if !options.bumps.is_empty() && !options.bumps.contains(&bump) {
+        return Some(Skip::Bump(bump));
+    }
+
+    None
+}
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+
+use crate::store;
+
+/// Lightweight view of the cache, enough to search without loading the whole state
+#[derive(Serialize, Deserialize, Default)]
+pub struct Index {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Formula,
+    Cask,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub kind: Kind,
+
+    /// Key of the keg in the state
+    pub name: String,
+
+    /// Tap-qualified name, e.g. homebrew/core/wget
+    pub full_name: String,
+
+    pub aliases: Vec<String>,
+
+    /// Keywords extracted from the description and tap
+    pub tokens: Vec<String>,
+
+    /// Sorted trigrams of the lowercase description
+    pub trigrams: Vec<u32>,
+
+    /// Installs over the last 30 days
+    pub installs: u64,
+}
+
+impl Entry {
+    /// Whether the description may contain the query.
+    /// Trigrams give false positives for scattered matches, never false negatives
+    pub fn desc_matches(&self, query: &str) -> bool {
+        let lowercase = query.to_lowercase().into_bytes();
+
+        // shorter queries have no trigrams of their own, they are looked up inside the ones
+        // of the description instead
+        if lowercase.len() < 3 {
+            return !lowercase.is_empty()
+                && self.trigrams.iter().any(|t| {
+                    t.to_be_bytes()[1..]
+                        .windows(lowercase.len())
+                        .any(|w| w == lowercase)
+                });
+        }
+
+        trigrams(query)
+            .iter()
+            .all(|t| self.trigrams.binary_search(t).is_ok())
+    }
+}
+
+pub fn build(state: &store::State) -> Index {
+    let formulae = state.formulae.iter().map(|(name, f)| Entry {
+        kind: Kind::Formula,
+        name: name.clone(),
+        full_name: f.base.tap_qualified_name(),
+        aliases: f.base.aliases.iter().cloned().collect(),
+        tokens: f.tags.iter().cloned().collect(),
+        trigrams: trigrams(f.base.desc.as_deref().unwrap_or_default()),
+        installs: f.installs(),
+    });
+
+    let casks = state.casks.iter().map(|(name, c)| Entry {
+        kind: Kind::Cask,
+        name: name.clone(),
+        full_name: c.base.tap_qualified_token(),
+        aliases: c.base.names.iter().cloned().collect(),
+        tokens: c.tags.iter().cloned().collect(),
+        trigrams: trigrams(c.base.desc.as_deref().unwrap_or_default()),
+        installs: c.installs(),
+    });
+
+    Index {
+        entries: formulae.chain(casks).collect(),
+    }
+}
+
+/// Sorted and deduplicated trigrams of the lowercase text, packed into integers
+pub fn trigrams(text: &str) -> Vec<u32> {
+    let bytes = text.to_lowercase().into_bytes();
+
+    let mut trigrams: Vec<u32> = bytes
+        .windows(3)
+        .map(|w| u32::from_be_bytes([0, w[0], w[1], w[2]]))
+        .collect();
+
+    trigrams.sort_unstable();
+    trigrams.dedup();
+
+    trigrams
+}
+
+/// Part of a text around the first match of a query
+pub struct Snippet {
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+}
+
+/// Case-insensitive match of the query in the text, with up to `radius` characters of
+/// context on each side. Whitespace is collapsed so multi-line caveats fit on one line
+pub fn snippet(text: &str, query: &str, radius: usize) -> Option<Snippet> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (start, end) = find(&text, query)?;
+
+    let mut before: String = {
+        let chars: Vec<char> = text[..start].chars().rev().take(radius).collect();
+
+        chars.into_iter().rev().collect()
+    };
+
+    if before.len() < start {
+        before.insert(0, '…');
+    }
+
+    let mut after: String = text[end..].chars().take(radius).collect();
+
+    if after.len() < text.len() - end {
+        after.push('…');
+    }
+
+    Some(Snippet {
+        before,
+        matched: text[start..end].to_string(),
+        after,
+    })
+}
+
+/// Byte range of the first case-insensitive occurrence of the query
+fn find(text: &str, query: &str) -> Option<(usize, usize)> {
+    if query.is_empty() {
+        return None;
+    }
+
+    text.char_indices().find_map(|(start, _)| {
+        let mut chars = text[start..].chars();
+        let mut end = start;
+
+        for q in query.chars() {
+            let c = chars.next()?;
+
+            if !c.to_lowercase().eq(q.to_lowercase()) {
+                return None;
+            }
+
+            end += c.len_utf8();
+        }
+
+        Some((start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(trigram: &[u8; 3]) -> u32 {
+        u32::from_be_bytes([0, trigram[0], trigram[1], trigram[2]])
+    }
+
+    fn entry(desc: &str) -> Entry {
+        Entry {
+            kind: Kind::Formula,
+            name: "jq".into(),
+            full_name: "jq".into(),
+            aliases: Vec::new(),
+            tokens: Vec::new(),
+            trigrams: trigrams(desc),
+            installs: 0,
+        }
+    }
+
+    #[test]
+    fn trigrams_are_sorted_lowercase_and_unique() {
+        assert_eq!(trigrams("Abcd"), [pack(b"abc"), pack(b"bcd")]);
+        assert_eq!(trigrams("zzzz"), [pack(b"zzz")]);
+        assert_eq!(trigrams("dcba"), [pack(b"cba"), pack(b"dcb")]);
+        assert_eq!(trigrams("JSON"), trigrams("json"));
+    }
+
+    #[test]
+    fn too_short_for_trigrams() {
+        assert!(trigrams("").is_empty());
+        assert!(trigrams("ab").is_empty());
+    }
+
+    #[test]
+    fn desc_matches_substrings_in_any_case() {
+        let jq = entry("Lightweight and flexible command-line JSON processor");
+
+        assert!(jq.desc_matches("json"));
+        assert!(jq.desc_matches("Command-Line"));
+        assert!(jq.desc_matches("json processor"));
+        assert!(!jq.desc_matches("xml"));
+        assert!(!jq.desc_matches(""));
+    }
+
+    #[test]
+    fn desc_matches_queries_shorter_than_a_trigram() {
+        let jq = entry("Lightweight and flexible command-line JSON processor");
+
+        assert!(jq.desc_matches("js"));
+        assert!(jq.desc_matches("J"));
+        assert!(jq.desc_matches("r"));
+        assert!(!jq.desc_matches("q"));
+        assert!(!jq.desc_matches("zx"));
+    }
+
+    fn snipped(text: &str, query: &str, radius: usize) -> Option<(String, String, String)> {
+        snippet(text, query, radius).map(|s| (s.before, s.matched, s.after))
+    }
+
+    fn parts(before: &str, matched: &str, after: &str) -> Option<(String, String, String)> {
+        Some((before.into(), matched.into(), after.into()))
+    }
+
+    #[test]
+    fn snippet_elides_context_beyond_the_radius() {
+        assert_eq!(
+            snipped("Search tool like grep and The Silver Searcher", "grep", 5),
+            parts("…like ", "grep", " and …")
+        );
+    }
+
+    #[test]
+    fn snippet_keeps_the_case_of_the_text() {
+        assert_eq!(
+            snipped("Search tool like grep", "GREP", 40),
+            parts("Search tool like ", "grep", "")
+        );
+        assert_eq!(
+            snipped("Le café au lait", "CAFÉ", 3),
+            parts("Le ", "café", " au…")
+        );
+    }
+
+    #[test]
+    fn snippet_collapses_whitespace() {
+        assert_eq!(
+            snipped("Multi\n  line\tcaveats", "line", 10),
+            parts("Multi ", "line", " caveats")
+        );
+    }
+
+    #[test]
+    fn snippet_without_a_match() {
+        assert_eq!(snipped("grep", "ack", 5), None);
+        assert_eq!(snipped("grep", "", 5), None);
+        assert_eq!(snipped("", "grep", 5), None);
+    }
+}
@@ -1,9 +1,16 @@
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::time::Duration;
 
 use chrono::{NaiveDateTime, Utc};
 use jammdb::Tx;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-use brewer_core::models;
+use brewer_core::{models, Sections};
+
+use crate::search;
 
 #[derive(Clone)]
 pub struct Store {
@@ -12,11 +19,134 @@ pub struct Store {
 
 pub type State = models::State<models::formula::Store, models::cask::Store>;
 
+/// Upstream release of a formula as it was seen at the time of a cache update
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Generation {
+    pub recorded_at: NaiveDateTime,
+    pub release: Release,
+}
+
+/// Parts of a formula `brewer changes` reports on
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Release {
+    pub version: String,
+    pub dependencies: Vec<String>,
+    pub build_dependencies: Vec<String>,
+    pub caveats: Option<String>,
+    pub deprecated: bool,
+    pub disabled: bool,
+}
+
+impl From<&models::formula::base::Formula> for Release {
+    fn from(formula: &models::formula::base::Formula) -> Self {
+        Release {
+            version: formula.versions.stable.clone(),
+            dependencies: formula.dependencies.clone(),
+            build_dependencies: formula.build_dependencies.clone(),
+            caveats: formula.caveats.clone(),
+            deprecated: formula.deprecated,
+            disabled: formula.disabled,
+        }
+    }
+}
+
+/// Install, upgrade or uninstall performed through brewer
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub at: NaiveDateTime,
+    pub action: Action,
+    pub name: String,
+
+    /// Version installed or upgraded to, the removed one for uninstalls
+    pub version: String,
+
+    /// Version before an upgrade
+    pub previous: Option<String>,
+
+    /// Login name of the user who ran brewer
+    pub user: String,
+
+    #[serde(default)]
+    pub outcome: Outcome,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Install,
+    Upgrade,
+    Uninstall,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Install => "install",
+            Action::Upgrade => "upgrade",
+            Action::Uninstall => "uninstall",
+        }
+    }
+}
+
+/// Whether brew succeeded, failed operations are recorded for each planned keg
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    #[default]
+    Succeeded,
+    Failed,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Succeeded => "succeeded",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// Installed formulae and casks saved under a name
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub created_at: NaiveDateTime,
+    pub formulae: models::keg::Store<Snapshotted>,
+    pub casks: models::keg::Store<Snapshotted>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshotted {
+    pub version: String,
+
+    /// Installed explicitly rather than as a dependency, always set for casks
+    pub on_request: bool,
+}
+
 impl Store {
     const UPDATE_BUCKET: &'static str = "update";
     const STATE_BUCKET: &'static str = "state";
+    const HISTORY_BUCKET: &'static str = "releases";
+    const LEGACY_HISTORY_BUCKET: &'static str = "history";
+    const TAGS_BUCKET: &'static str = "tags";
+    const NOTES_BUCKET: &'static str = "notes";
+    const STARRED_BUCKET: &'static str = "starred";
+    const TIMINGS_BUCKET: &'static str = "timings";
+    const GITHUB_BUCKET: &'static str = "github";
+    const FORMULAE_BUCKET: &'static str = "formulae";
+    const CASKS_BUCKET: &'static str = "casks";
+    const SEARCH_BUCKET: &'static str = "search";
+    const EVENTS_BUCKET: &'static str = "events";
+    const SNAPSHOTS_BUCKET: &'static str = "snapshots";
+    const SIZES_BUCKET: &'static str = "sizes";
+    const INSTALLED_BUCKET: &'static str = "installed";
 
     const STATE_KEY: &'static str = "state";
+    const GITHUB_KEY: &'static str = "github";
+    const SEARCH_KEY: &'static str = "index";
+    const INSTALLED_KEY: &'static str = "last";
+
+    /// Generations kept besides the ones of the installed version and newer
+    const KEPT_GENERATIONS: usize = 10;
 
     pub fn open(path: &Path) -> anyhow::Result<Store> {
         Ok(Store {
@@ -25,11 +155,53 @@ impl Store {
     }
 
     pub fn last_update(&self) -> anyhow::Result<Option<NaiveDateTime>> {
+        self.updated_at(Self::STATE_KEY)
+    }
+
+    /// Time since the state was stored, None if it never was
+    pub fn age(&self) -> anyhow::Result<Option<Duration>> {
+        let Some(last_update) = self.last_update()? else {
+            return Ok(None);
+        };
+
+        let age = Utc::now().naive_utc() - last_update;
+
+        Ok(Some(age.to_std().unwrap_or_default()))
+    }
+
+    /// Whether the state is older than the given duration or was never stored,
+    /// it never expires without one
+    pub fn expired(&self, duration: Option<Duration>) -> anyhow::Result<bool> {
+        let Some(duration) = duration else {
+            return Ok(false);
+        };
+
+        match self.last_update()? {
+            Some(last_update) => Ok(last_update + duration <= Utc::now().naive_utc()),
+            None => Ok(true)
+        }
+    }
+
+    /// When the given section of the index was refreshed last time. Caches written before
+    /// sections were tracked only know the time of the last full update
+    pub fn last_section_update(&self, section: &str) -> anyhow::Result<Option<NaiveDateTime>> {
+        match self.updated_at(section)? {
+            Some(at) => Ok(Some(at)),
+            None => self.last_update(),
+        }
+    }
+
+    /// When GitHub metadata was fetched last time
+    pub fn last_github_update(&self) -> anyhow::Result<Option<NaiveDateTime>> {
+        self.updated_at(Self::GITHUB_KEY)
+    }
+
+    fn updated_at(&self, key: &str) -> anyhow::Result<Option<NaiveDateTime>> {
         let tx = self.db.tx(false)?;
 
         match tx.get_bucket(Self::UPDATE_BUCKET) {
             Ok(bucket) => {
-                let Some(data) = bucket.get(Self::STATE_KEY) else {
+                let Some(data) = bucket.get(key) else {
                     return Ok(None);
                 };
 
@@ -43,48 +215,588 @@ impl Store {
         }
     }
 
-    fn commit_update(tx: Tx) -> anyhow::Result<()> {
+    fn commit_update_of(tx: Tx, key: &str) -> anyhow::Result<()> {
+        Self::commit_updates_of(tx, &[key])
+    }
+
+    fn commit_updates_of(tx: Tx, keys: &[&str]) -> anyhow::Result<()> {
         let bucket = tx.get_or_create_bucket(Self::UPDATE_BUCKET)?;
 
         let now = Utc::now().naive_utc();
         let now_bytes = rmp_serde::to_vec(&now)?;
 
-        bucket.put(Self::STATE_KEY, now_bytes)?;
+        for key in keys {
+            bucket.put(key.to_string(), now_bytes.clone())?;
+        }
 
         tx.commit()?;
 
         Ok(())
     }
 
+    /// Cached formulae and casks, assembled from their per-keg buckets
     pub fn get_state(&self) -> anyhow::Result<Option<State>> {
+        let formulae = self.find_entries(Self::FORMULAE_BUCKET);
+        let casks = self.find_entries(Self::CASKS_BUCKET);
+
+        match (formulae, casks) {
+            (Ok(Some(formulae)), Ok(Some(casks))) => Ok(Some(State { formulae, casks })),
+            (Ok(_), Ok(_)) => Ok(None),
+
+            // cache written by an older version may have a different shape,
+            // treat it as missing so that it gets rebuilt
+            (Err(e), _) | (_, Err(e)) if e.is::<rmp_serde::decode::Error>() => {
+                warn!("ignoring incompatible cache: {e}");
+
+                Ok(None)
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e)
+        }
+    }
+
+    pub fn set_state(&mut self, state: State) -> anyhow::Result<()> {
+        self.set_state_of(state, Sections::ALL)
+    }
+
+    /// Same as [`Store::set_state`], also recording when the given sections were refreshed
+    pub fn set_state_of(&mut self, state: State, sections: Sections) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        // older versions stored the whole state as a single entry
+        match tx.delete_bucket(Self::STATE_BUCKET) {
+            Ok(_) | Err(jammdb::Error::BucketMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        Self::put_kegs(&tx, Self::FORMULAE_BUCKET, &state.formulae)?;
+        Self::put_kegs(&tx, Self::CASKS_BUCKET, &state.casks)?;
+
+        let index = tx.get_or_create_bucket(Self::SEARCH_BUCKET)?;
+        index.put(Self::SEARCH_KEY, rmp_serde::to_vec(&search::build(&state))?)?;
+
+        let mut keys = vec![Self::STATE_KEY];
+        keys.extend(sections.names());
+
+        Self::commit_updates_of(tx, &keys)?;
+
+        Ok(())
+    }
+
+    /// Search index built along with the cached state
+    pub fn get_search_index(&self) -> anyhow::Result<Option<search::Index>> {
         let tx = self.db.tx(false)?;
 
-        match tx.get_bucket(Self::STATE_BUCKET) {
+        match tx.get_bucket(Self::SEARCH_BUCKET) {
             Ok(bucket) => {
-                let Some(data) = bucket.get(Self::STATE_KEY) else {
+                let Some(data) = bucket.get(Self::SEARCH_KEY) else {
                     return Ok(None);
                 };
 
-                let state: State = rmp_serde::from_slice(data.kv().value())?;
+                match rmp_serde::from_slice(data.kv().value()) {
+                    Ok(index) => Ok(Some(index)),
+                    Err(e) => {
+                        warn!("ignoring incompatible search index: {e}");
 
-                Ok(Some(state))
+                        Ok(None)
+                    }
+                }
             }
             Err(jammdb::Error::BucketMissing) => Ok(None),
             Err(e) => Err(anyhow::anyhow!(e))
         }
     }
 
-    pub fn set_state(&mut self, state: State) -> anyhow::Result<()> {
+    pub fn set_search_index(&mut self, index: &search::Index) -> anyhow::Result<()> {
+        self.put_entry(Self::SEARCH_BUCKET, Self::SEARCH_KEY, index)
+    }
+
+    /// Cached formulae matching the predicate, deserialized one by one so that only
+    /// matching ones are kept in memory
+    pub fn query_formulae(
+        &self,
+        predicate: impl FnMut(&models::formula::Formula) -> bool,
+    ) -> anyhow::Result<Vec<models::formula::Formula>> {
+        self.query(Self::FORMULAE_BUCKET, predicate, usize::MAX)
+    }
+
+    /// Same as [`Store::query_formulae`], but stops at the first match
+    pub fn find_formula(
+        &self,
+        predicate: impl FnMut(&models::formula::Formula) -> bool,
+    ) -> anyhow::Result<Option<models::formula::Formula>> {
+        Ok(self.query(Self::FORMULAE_BUCKET, predicate, 1)?.pop())
+    }
+
+    /// Cached casks matching the predicate, see [`Store::query_formulae`]
+    pub fn query_casks(
+        &self,
+        predicate: impl FnMut(&models::cask::Cask) -> bool,
+    ) -> anyhow::Result<Vec<models::cask::Cask>> {
+        self.query(Self::CASKS_BUCKET, predicate, usize::MAX)
+    }
+
+    /// Same as [`Store::query_casks`], but stops at the first match
+    pub fn find_cask(
+        &self,
+        predicate: impl FnMut(&models::cask::Cask) -> bool,
+    ) -> anyhow::Result<Option<models::cask::Cask>> {
+        Ok(self.query(Self::CASKS_BUCKET, predicate, 1)?.pop())
+    }
+
+    fn query<T: DeserializeOwned>(
+        &self,
+        bucket: &str,
+        mut predicate: impl FnMut(&T) -> bool,
+        limit: usize,
+    ) -> anyhow::Result<Vec<T>> {
+        let tx = self.db.tx(false)?;
+
+        match tx.get_bucket(bucket) {
+            Ok(bucket) => {
+                let mut found = Vec::new();
+
+                for data in bucket.cursor() {
+                    if found.len() >= limit {
+                        break;
+                    }
+
+                    let jammdb::Data::KeyValue(kv) = data else {
+                        continue;
+                    };
+
+                    let value: T = rmp_serde::from_slice(kv.value())?;
+
+                    if predicate(&value) {
+                        found.push(value);
+                    }
+                }
+
+                Ok(found)
+            }
+            Err(jammdb::Error::BucketMissing) => Ok(Vec::new()),
+            Err(e) => Err(anyhow::anyhow!(e))
+        }
+    }
+
+    /// Replaces the bucket with one entry per keg
+    fn put_kegs<T: Serialize>(
+        tx: &Tx,
+        bucket: &str,
+        kegs: &models::keg::Store<T>,
+    ) -> anyhow::Result<()> {
+        match tx.delete_bucket(bucket) {
+            Ok(_) | Err(jammdb::Error::BucketMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        let bucket = tx.create_bucket(bucket)?;
+
+        for (name, keg) in kegs {
+            bucket.put(name.clone(), rmp_serde::to_vec(keg)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Generations of the given formula, oldest first
+    pub fn get_history(&self, name: &str) -> anyhow::Result<Vec<Generation>> {
+        let tx = self.db.tx(false)?;
+
+        match tx.get_bucket(Self::HISTORY_BUCKET) {
+            Ok(bucket) => {
+                let Some(data) = bucket.get(name) else {
+                    return Ok(Vec::new());
+                };
+
+                let generations: Vec<Generation> = rmp_serde::from_slice(data.kv().value())?;
+
+                Ok(generations)
+            }
+            Err(jammdb::Error::BucketMissing) => Ok(Vec::new()),
+            Err(e) => Err(anyhow::anyhow!(e))
+        }
+    }
+
+    /// Inserts generations among the recorded ones of each formula by the time they were seen,
+    /// unless a generation of the same version is already recorded
+    pub fn seed_history(&mut self, seeds: Vec<(String, Generation)>) -> anyhow::Result<()> {
         let tx = self.db.tx(true)?;
 
-        let bucket = tx.get_or_create_bucket(Self::STATE_BUCKET)?;
+        let bucket = tx.get_or_create_bucket(Self::HISTORY_BUCKET)?;
+
+        for (name, seed) in seeds {
+            let mut generations: Vec<Generation> = match bucket.get(&name) {
+                Some(data) => rmp_serde::from_slice(data.kv().value())?,
+                None => Vec::new(),
+            };
+
+            let known = generations
+                .iter()
+                .any(|g| g.release.version == seed.release.version);
+
+            if known {
+                continue;
+            }
+
+            let at = generations.partition_point(|g| g.recorded_at <= seed.recorded_at);
 
-        let state_bytes = rmp_serde::to_vec(&state)?;
+            generations.insert(at, seed);
 
-        bucket.put(Self::STATE_KEY, state_bytes)?;
+            bucket.put(name, rmp_serde::to_vec(&generations)?)?;
+        }
 
-        Self::commit_update(tx)?;
+        tx.commit()?;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// User-defined tags by formula or cask name
+    pub fn get_tags(&self) -> anyhow::Result<models::keg::Store<HashSet<String>>> {
+        self.get_entries(Self::TAGS_BUCKET)
+    }
+
+    /// Replaces user-defined tags of the given formula or cask, empty tags remove the entry
+    pub fn set_tags(&mut self, name: &str, tags: &HashSet<String>) -> anyhow::Result<()> {
+        if tags.is_empty() {
+            self.delete_entry(Self::TAGS_BUCKET, name)
+        } else {
+            self.put_entry(Self::TAGS_BUCKET, name, tags)
+        }
+    }
+
+    /// User-defined notes by formula or cask name
+    pub fn get_notes(&self) -> anyhow::Result<models::keg::Store<String>> {
+        self.get_entries(Self::NOTES_BUCKET)
+    }
+
+    pub fn set_note(&mut self, name: &str, note: &str) -> anyhow::Result<()> {
+        self.put_entry(Self::NOTES_BUCKET, name, &note)
+    }
+
+    pub fn delete_note(&mut self, name: &str) -> anyhow::Result<()> {
+        self.delete_entry(Self::NOTES_BUCKET, name)
+    }
+
+    /// Names of starred formulae and casks
+    pub fn get_starred(&self) -> anyhow::Result<HashSet<String>> {
+        let starred: models::keg::Store<bool> = self.get_entries(Self::STARRED_BUCKET)?;
+
+        Ok(starred.into_keys().collect())
+    }
+
+    pub fn set_starred(&mut self, name: &str, starred: bool) -> anyhow::Result<()> {
+        if starred {
+            self.put_entry(Self::STARRED_BUCKET, name, &true)
+        } else {
+            self.delete_entry(Self::STARRED_BUCKET, name)
+        }
+    }
+
+    /// Recorded events since the given time, oldest first
+    pub fn get_events(&self, since: Option<NaiveDateTime>) -> anyhow::Result<Vec<Event>> {
+        self.query(
+            Self::EVENTS_BUCKET,
+            |e: &Event| since.is_none_or(|since| e.at >= since),
+            usize::MAX,
+        )
+    }
+
+    /// Appends events to the log, which is never pruned
+    pub fn add_events(&mut self, events: &[Event]) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        let bucket = tx.get_or_create_bucket(Self::EVENTS_BUCKET)?;
+
+        for (i, event) in events.iter().enumerate() {
+            // keys sort chronologically, the index keeps events of the same operation apart
+            let at = event.at.and_utc().timestamp_nanos_opt().unwrap_or_default();
+            let key = format!("{at:020}-{i:06}");
+
+            bucket.put(key, rmp_serde::to_vec(event)?)?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Saved snapshots by name
+    pub fn get_snapshots(&self) -> anyhow::Result<models::keg::Store<Snapshot>> {
+        self.get_entries(Self::SNAPSHOTS_BUCKET)
+    }
+
+    pub fn set_snapshot(&mut self, name: &str, snapshot: &Snapshot) -> anyhow::Result<()> {
+        self.put_entry(Self::SNAPSHOTS_BUCKET, name, snapshot)
+    }
+
+    pub fn delete_snapshot(&mut self, name: &str) -> anyhow::Result<()> {
+        self.delete_entry(Self::SNAPSHOTS_BUCKET, name)
+    }
+
+    /// Installed kegs as they were seen at the end of the last run, None if never saved
+    pub fn get_installed(&self) -> anyhow::Result<Option<Snapshot>> {
+        let mut installed: models::keg::Store<Snapshot> = self.get_entries(Self::INSTALLED_BUCKET)?;
+
+        Ok(installed.remove(Self::INSTALLED_KEY))
+    }
+
+    pub fn set_installed(&mut self, installed: &Snapshot) -> anyhow::Result<()> {
+        self.put_entry(Self::INSTALLED_BUCKET, Self::INSTALLED_KEY, installed)
+    }
+
+    pub fn get_sizes(&self) -> anyhow::Result<brewer_core::size::Cache> {
+        self.get_entries(Self::SIZES_BUCKET)
+    }
+
+    /// Replaces all cached sizes, dropping kegs which are not in the given cache
+    pub fn set_sizes(&mut self, sizes: &brewer_core::size::Cache) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        match tx.delete_bucket(Self::SIZES_BUCKET) {
+            Ok(_) | Err(jammdb::Error::BucketMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        let bucket = tx.create_bucket(Self::SIZES_BUCKET)?;
+
+        for (key, measured) in sizes {
+            bucket.put(key.clone(), rmp_serde::to_vec(measured)?)?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Recent durations of the given operation in milliseconds, oldest first
+    pub fn get_timings(&self, operation: &str) -> anyhow::Result<Vec<u64>> {
+        let mut timings: models::keg::Store<Vec<u64>> = self.get_entries(Self::TIMINGS_BUCKET)?;
+
+        Ok(timings.remove(operation).unwrap_or_default())
+    }
+
+    pub fn set_timings(&mut self, operation: &str, timings: &[u64]) -> anyhow::Result<()> {
+        self.put_entry(Self::TIMINGS_BUCKET, operation, &timings)
+    }
+
+    /// GitHub repository metadata by "owner/repo"
+    pub fn get_github(&self) -> anyhow::Result<models::keg::Store<models::github::Repository>> {
+        self.get_entries(Self::GITHUB_BUCKET)
+    }
+
+    /// Replaces all GitHub metadata and records the time of the fetch
+    pub fn set_github(
+        &mut self,
+        repos: &models::keg::Store<models::github::Repository>,
+    ) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        match tx.delete_bucket(Self::GITHUB_BUCKET) {
+            Ok(_) | Err(jammdb::Error::BucketMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        let bucket = tx.create_bucket(Self::GITHUB_BUCKET)?;
+
+        for (name, repo) in repos {
+            bucket.put(name.clone(), rmp_serde::to_vec(repo)?)?;
+        }
+
+        Self::commit_update_of(tx, Self::GITHUB_KEY)
+    }
+
+    fn get_entries<T: DeserializeOwned>(
+        &self,
+        bucket: &str,
+    ) -> anyhow::Result<models::keg::Store<T>> {
+        Ok(self.find_entries(bucket)?.unwrap_or_default())
+    }
+
+    /// Entries of the bucket by key, None if the bucket doesn't exist
+    fn find_entries<T: DeserializeOwned>(
+        &self,
+        bucket: &str,
+    ) -> anyhow::Result<Option<models::keg::Store<T>>> {
+        let tx = self.db.tx(false)?;
+
+        match tx.get_bucket(bucket) {
+            Ok(bucket) => {
+                let mut entries = HashMap::new();
+
+                for data in bucket.cursor() {
+                    let jammdb::Data::KeyValue(kv) = data else {
+                        continue;
+                    };
+
+                    let name = String::from_utf8_lossy(kv.key()).to_string();
+                    let value: T = rmp_serde::from_slice(kv.value())?;
+
+                    entries.insert(name, value);
+                }
+
+                Ok(Some(entries))
+            }
+            Err(jammdb::Error::BucketMissing) => Ok(None),
+            Err(e) => Err(anyhow::anyhow!(e))
+        }
+    }
+
+    fn put_entry<T: Serialize>(&mut self, bucket: &str, name: &str, value: &T) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        let bucket = tx.get_or_create_bucket(bucket)?;
+
+        bucket.put(name.to_string(), rmp_serde::to_vec(value)?)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn delete_entry(&mut self, bucket: &str, name: &str) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        let bucket = tx.get_or_create_bucket(bucket)?;
+
+        match bucket.delete(name) {
+            Ok(_) | Err(jammdb::Error::KeyValueMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Appends the upstream release of each installed formula, given with its installed version,
+    /// unless it matches the last recorded one. Generations `brewer changes` no longer needs
+    /// are pruned along the way
+    pub fn record_history(
+        &mut self,
+        formulae: &models::formula::Store,
+        installed: &HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let tx = self.db.tx(true)?;
+
+        // older versions kept every cached formula in full on each change
+        match tx.delete_bucket(Self::LEGACY_HISTORY_BUCKET) {
+            Ok(_) | Err(jammdb::Error::BucketMissing) => {}
+            Err(e) => return Err(anyhow::anyhow!(e))
+        }
+
+        let bucket = tx.get_or_create_bucket(Self::HISTORY_BUCKET)?;
+
+        let now = Utc::now().naive_utc();
+
+        for (name, version) in installed {
+            let Some(formula) = formulae.get(name) else {
+                continue;
+            };
+
+            let release = Release::from(&formula.base);
+
+            let mut generations: Vec<Generation> = match bucket.get(name) {
+                Some(data) => rmp_serde::from_slice(data.kv().value())?,
+                None => Vec::new(),
+            };
+
+            if generations.last().is_some_and(|g| g.release == release) {
+                continue;
+            }
+
+            generations.push(Generation {
+                recorded_at: now,
+                release,
+            });
+
+            prune(&mut generations, version);
+
+            bucket.put(name.clone(), rmp_serde::to_vec(&generations)?)?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+/// Drops generations older than the first one of the installed version,
+/// unless they are among the latest [`Store::KEPT_GENERATIONS`]
+fn prune(generations: &mut Vec<Generation>, installed: &str) {
+    let latest = generations.len().saturating_sub(Store::KEPT_GENERATIONS);
+
+    let from = generations
+        .iter()
+        .position(|g| g.release.version == installed)
+        .map_or(latest, |first| first.min(latest));
+
+    generations.drain(..from);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generations(versions: &[&str]) -> Vec<Generation> {
+        versions
+            .iter()
+            .map(|version| Generation {
+                recorded_at: NaiveDateTime::default(),
+                release: Release {
+                    version: version.to_string(),
+                    dependencies: Vec::new(),
+                    build_dependencies: Vec::new(),
+                    caveats: None,
+                    deprecated: false,
+                    disabled: false,
+                },
+            })
+            .collect()
+    }
+
+    fn versions(generations: &[Generation]) -> Vec<&str> {
+        generations.iter().map(|g| g.release.version.as_str()).collect()
+    }
+
+    /// Generations of versions 1 to 15, oldest first
+    fn fifteen() -> Vec<Generation> {
+        let versions: Vec<String> = (1..=15).map(|n| n.to_string()).collect();
+
+        generations(&versions.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn prune_keeps_the_installed_version_and_newer() {
+        let mut history = fifteen();
+
+        prune(&mut history, "3");
+
+        assert_eq!(history.len(), 13);
+        assert_eq!(versions(&history)[0], "3");
+    }
+
+    #[test]
+    fn prune_keeps_the_latest_generations() {
+        let mut history = fifteen();
+
+        prune(&mut history, "12");
+
+        assert_eq!(history.len(), Store::KEPT_GENERATIONS);
+        assert_eq!(versions(&history)[0], "6");
+
+        let mut history = fifteen();
+
+        prune(&mut history, "16");
+
+        assert_eq!(versions(&history)[0], "6");
+    }
+
+    #[test]
+    fn prune_leaves_short_histories_alone() {
+        let mut history = generations(&["1.0", "1.1"]);
+
+        prune(&mut history, "1.1");
+
+        assert_eq!(versions(&history), ["1.0", "1.1"]);
+    }
+}
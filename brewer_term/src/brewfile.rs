@@ -0,0 +1,165 @@
+use std::fmt::Write;
+use std::path::Path;
+
+pub const BREWFILE: &str = "Brewfile";
+
+/// Taps, formulae and casks listed in a Homebrew Brewfile
+#[derive(Default)]
+pub struct Brewfile {
+    pub taps: Vec<String>,
+    pub formulae: Vec<String>,
+    pub casks: Vec<String>,
+
+    /// Formulae written as `brew "name", pin: true`, brew bundle ignores the option
+    pub pinned: Vec<String>,
+}
+
+impl Brewfile {
+    /// Parses `tap`, `brew` and `cask` entries. Options other than `pin` and other entries,
+    /// e.g. `mas`, are ignored
+    pub fn parse(text: &str) -> Brewfile {
+        let mut brewfile = Brewfile::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((kind, rest)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+
+            let Some(name) = quoted(rest.trim_start()) else {
+                continue;
+            };
+
+            match kind {
+                "tap" => brewfile.taps.push(name),
+                "brew" => {
+                    if rest.contains("pin: true") {
+                        brewfile.pinned.push(name.clone());
+                    }
+
+                    brewfile.formulae.push(name)
+                }
+                "cask" => brewfile.casks.push(name),
+                _ => {}
+            }
+        }
+
+        brewfile
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Brewfile> {
+        let text = std::fs::read_to_string(path)?;
+
+        Ok(Brewfile::parse(&text))
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        let entries = self
+            .taps
+            .iter()
+            .map(|n| ("tap", n))
+            .chain(self.formulae.iter().map(|n| ("brew", n)))
+            .chain(self.casks.iter().map(|n| ("cask", n)));
+
+        for (kind, name) in entries {
+            let pin = if kind == "brew" && self.pinned.contains(name) {
+                ", pin: true"
+            } else {
+                ""
+            };
+
+            // writing to a string never fails
+            let _ = writeln!(text, "{kind} \"{name}\"{pin}");
+        }
+
+        text
+    }
+}
+
+/// First single- or double-quoted string, e.g. `"wget", args: [...]` gives wget
+fn quoted(s: &str) -> Option<String> {
+    let quote = s.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let rest = &s[1..];
+    let end = rest.find(quote)?;
+
+    Some(rest[..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+tap "homebrew/bundle"
+tap "homebrew/services"
+
+# tools
+brew "git"
+brew "postgresql@16", restart_service: :changed
+brew "wget", pin: true
+brew	'jq' # single quotes and a tab
+cask_args appdir: "~/Applications"
+cask "firefox", args: { appdir: "~/Applications" }
+mas "Xcode", id: 497799835
+vscode "rust-lang.rust-analyzer"
+"#;
+
+    #[test]
+    fn parses_taps_formulae_and_casks() {
+        let brewfile = Brewfile::parse(SAMPLE);
+
+        assert_eq!(brewfile.taps, ["homebrew/bundle", "homebrew/services"]);
+        assert_eq!(brewfile.formulae, ["git", "postgresql@16", "wget", "jq"]);
+        assert_eq!(brewfile.casks, ["firefox"]);
+        assert_eq!(brewfile.pinned, ["wget"]);
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let brewfile = Brewfile::parse("brew\nbrew wget\nbrew \"unterminated\ncask firefox\n");
+
+        assert!(brewfile.formulae.is_empty());
+        assert!(brewfile.casks.is_empty());
+    }
+
+    #[test]
+    fn empty() {
+        let brewfile = Brewfile::parse("");
+
+        assert!(brewfile.taps.is_empty());
+        assert!(brewfile.formulae.is_empty());
+        assert!(brewfile.casks.is_empty());
+        assert_eq!(brewfile.render(), "");
+    }
+
+    #[test]
+    fn render_round_trips() {
+        let brewfile = Brewfile::parse(SAMPLE);
+        let text = brewfile.render();
+
+        assert_eq!(
+            text,
+            "tap \"homebrew/bundle\"\n\
+             tap \"homebrew/services\"\n\
+             brew \"git\"\n\
+             brew \"postgresql@16\"\n\
+             brew \"wget\", pin: true\n\
+             brew \"jq\"\n\
+             cask \"firefox\"\n"
+        );
+
+        let parsed = Brewfile::parse(&text);
+
+        assert_eq!(parsed.taps, brewfile.taps);
+        assert_eq!(parsed.formulae, brewfile.formulae);
+        assert_eq!(parsed.casks, brewfile.casks);
+        assert_eq!(parsed.pinned, brewfile.pinned);
+    }
+}
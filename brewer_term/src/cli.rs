@@ -1,27 +1,69 @@
-use std::io::{BufWriter, Write};
+use std::collections::BTreeMap;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_verbosity::Verbosity;
 use colored::Colorize;
-use skim::prelude::{unbounded, SkimOptionsBuilder};
+use nucleo_matcher::pattern::{Atom, AtomKind, CaseMatching, Normalization};
+use skim::prelude::{unbounded, Key, SkimOptionsBuilder};
 use skim::{Skim, SkimItem, SkimItemReceiver, SkimItemSender};
 use terminal_size::{terminal_size, Width};
 
 use brewer_core::models;
-use brewer_engine::{Engine, State};
+use brewer_core::progress::{Phase, Progress};
+use brewer_core::Sections;
+use brewer_engine::index::Indexes;
+use brewer_engine::{outdated, Engine, InstalledDelta, Operation, Resolution, State};
 
-use crate::pretty;
+use crate::error::{Cancelled, NotFound};
 use crate::pretty::header;
+use crate::{examples, pretty, progress};
 
 #[derive(Parser)]
 #[command(version, about)]
 pub struct Cli {
+    /// Runs `default_command` from the settings when omitted
     #[command(subcommand)]
-    pub command: Commands,
+    pub command: Option<Commands>,
 
     #[command(flatten)]
     pub verbose: Verbosity,
+
+    /// Emit machine-readable output where supported, including errors
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Describe exit codes and exit
+    #[arg(long)]
+    pub explain_exit_codes: bool,
+
+    /// Use the given config file instead of the default one
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    pub config: Option<PathBuf>,
+
+    /// Use the given database file instead of the default one
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    pub db: Option<PathBuf>,
+
+    /// Never update the cache or fetch anything from the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Write the brew commands run, the output and the outcome to the given file as JSON
+    #[arg(long, global = true, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub record: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Same as [`Parser::parse`], with examples attached to the long help of subcommands
+    pub fn parse_with_examples() -> Cli {
+        let matches = examples::attach(Cli::command()).get_matches();
+
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
 }
 
 #[derive(Subcommand)]
@@ -39,6 +81,15 @@ pub enum Commands {
     /// Show information about formula or cask
     Info(Info),
 
+    /// Open the homepage of a formula or cask using default browser
+    Home(Home),
+
+    /// Print the Ruby source of a formula or cask
+    Cat(Cat),
+
+    /// Print "name: description" lines of formulae or casks
+    Desc(Desc),
+
     /// Search for formulae and casks
     #[clap(alias = "s")]
     Search(search::Search),
@@ -46,6 +97,9 @@ pub enum Commands {
     /// Show paths that brewer uses
     Paths(paths::Paths),
 
+    /// Show or edit settings
+    Config(config::Config),
+
     /// Indicate if the given formula or cask exists by exit code.
     Exists(Exists),
 
@@ -56,137 +110,458 @@ pub enum Commands {
     /// Uninstall the given formula or cask.
     #[clap(aliases = & ["r", "remove"])]
     Uninstall(uninstall::Uninstall),
+
+    /// Uninstall formulae installed as dependencies which are no longer required.
+    Autoremove(uninstall::Autoremove),
+
+    /// Symlink installed formulae into the prefix.
+    Link(link::Link),
+
+    /// Remove symlinks of installed formulae from the prefix.
+    Unlink(link::Unlink),
+
+    /// Print shell commands setting up the environment, `eval` them in the shell profile.
+    Shellenv(link::Shellenv),
+
+    /// Upgrade the given formulae or casks.
+    #[clap(alias = "u")]
+    Upgrade(upgrade::Upgrade),
+
+    /// Print the brew commands a mutating command would run, without running them
+    Explain(explain::Explain),
+
+    /// Show how the upstream formula changed since the installed version
+    Changes(changes::Changes),
+
+    /// Inspect installs, upgrades and uninstalls performed through brewer
+    History(history::History),
+
+    /// Revert the most recent install or uninstall performed through brewer
+    Rollback(history::Rollback),
+
+    /// Save installed formulae and casks and restore them later
+    Snapshot(snapshot::Snapshot),
+
+    /// Rescan installed kegs and show what was added, removed or changed since the last run
+    Diff(Diff),
+
+    /// Add or remove user-defined tags of a formula or cask
+    Tag(tag::Tag),
+
+    /// Remember why a formula or cask is installed
+    Note(note::Note),
+
+    /// Star formulae or casks you care about
+    Star(star::Star),
+
+    /// Remove stars from formulae or casks
+    Unstar(star::Unstar),
+
+    /// Check or install formulae and casks required by the project manifest
+    Project(project::Project),
+
+    /// Print shell integration snippets
+    Hook(hook::Hook),
+
+    /// Print a completion script for the given shell
+    Completions(completions::Completions),
+
+    #[command(name = "__complete", hide = true)]
+    Complete(completions::Complete),
+
+    /// Inspect taps
+    Tap(tap::Tap),
+
+    /// List names provided by both a formula and a cask or by several taps
+    Collisions(collisions::Collisions),
+
+    /// List installed formulae and casks with newer upstream versions
+    Outdated(Outdated),
+
+    /// Show dependencies of the given formula
+    Deps(deps::Deps),
+
+    /// Show formulae that depend on the given formula
+    Uses(deps::Uses),
+
+    /// List installed formulae that are not dependencies of other installed formulae
+    Leaves(deps::Leaves),
+
+    /// List installed formulae with runtime dependencies that are not installed
+    Missing(deps::Missing),
+
+    /// Check the system for potential problems using brew doctor
+    Doctor(Doctor),
+
+    /// Remove old kegs and cached downloads
+    Cleanup(Cleanup),
+
+    /// Write or install a Brewfile
+    Bundle(bundle::Bundle),
+
+    /// Install from an existing Brewfile or add its entries to the project manifest
+    ImportBrewfile(bundle::ImportBrewfile),
+
+    /// Install from `brew leaves` output or add it to the project manifest
+    ImportLeaves(bundle::ImportLeaves),
+
+    /// Print installed taps, formulae, casks and pins as TOML
+    Export(bundle::Export),
+
+    /// Tap, install and pin everything listed in a file written by export
+    Import(bundle::Import),
+
+    /// Show equivalent packages of a formula in nixpkgs and apt
+    Xref(Xref),
+
+    /// Find formulae providing an executable, library or pkg-config file,
+    /// or list the executables of a formula
+    Provides(Provides),
+
+    /// Show which installed formula or cask owns the given file
+    Owns(Owns),
+
+    /// Print runnable examples of a subcommand
+    Examples(Examples),
+
+    /// Show disk usage of installed formulae and casks
+    Size(size::Size),
 }
 
 pub mod which {
     use std::borrow::Cow;
-    use std::collections::HashMap;
     use std::io::{BufWriter, IsTerminal, Write};
+    use std::time::Duration;
 
-    use clap::Args;
+    use clap::{Args, ValueEnum, ValueHint};
     use colored::Colorize;
+    use nucleo_matcher::pattern::{Atom, AtomKind, CaseMatching, Normalization};
+    use serde::Serialize;
+    use skim::prelude::Key;
     use skim::{ItemPreview, PreviewContext, SkimItem};
 
     use brewer_core::models;
-    use brewer_engine::State;
+    use brewer_engine::index::Indexes;
+    use brewer_engine::{Engine, State};
+
+    use crate::cli::install::Install;
+    use crate::cli::{info_formula, not_found, select_skim_with};
+    use crate::pretty::header;
 
-    use crate::cli::{info_formula, select_skim};
+    /// Accepts the picked executable and installs its provider
+    const INSTALL_KEY: &str = "alt-i";
 
-    #[derive(Args)]
+    #[derive(Args, Clone)]
     pub struct Which {
-        pub name: Option<String>,
+        /// Executables to look up, picked interactively if none are given
+        #[clap(value_hint = ValueHint::CommandName)]
+        pub names: Vec<String>,
 
         /// Show all matched formulae instead of the most popular one.
         #[clap(long, short, action)]
         pub all: bool,
+
+        /// Print executable, formula, version, tap and installed columns
+        #[clap(long, short, value_enum)]
+        pub output: Option<Output>,
+
+        /// Use the closest executable name when nothing provides the given one
+        #[clap(long, short, action)]
+        pub fuzzy: bool,
+
+        /// Install the most popular provider of each executable, alt-i does it in the picker
+        #[clap(long, short, action, conflicts_with = "output")]
+        pub install: bool,
+    }
+
+    #[derive(Clone, Copy, ValueEnum)]
+    pub enum Output {
+        /// Tab-separated columns
+        Plain,
+
+        /// JSON array of objects
+        Json,
+    }
+
+    #[derive(Serialize)]
+    struct Row<'a> {
+        executable: &'a str,
+        formula: &'a str,
+        version: &'a str,
+        tap: &'a str,
+        installed: bool,
     }
 
     impl Which {
-        pub fn run(&self, state: State) -> anyhow::Result<bool> {
-            let name = if let Some(name) = &self.name {
-                name.to_string()
+        pub fn run(
+            &self,
+            engine: Engine,
+            state: State,
+            cache_age: Option<Duration>,
+        ) -> anyhow::Result<bool> {
+            let (names, install) = if self.names.is_empty() {
+                let (name, install) = self.run_skim(&state, &Indexes::new(&state))?;
+
+                (vec![name], install || self.install)
             } else {
-                self.run_skim(&state)?
+                (self.names.clone(), self.install)
             };
 
-            let mut formulae: Vec<_> = state
-                .formulae
-                .all
-                .into_iter()
-                .filter_map(|(_, f)| {
-                    if f.executables.contains(&name) {
-                        Some(f)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+            let (found, all_found) = self.show(&state, &names, cache_age)?;
 
-            if formulae.is_empty() {
-                return Ok(false);
-            }
+            if install && !found.is_empty() {
+                let names = found
+                    .iter()
+                    .map(|(_, formulae)| formulae[0].base.name.clone())
+                    .collect();
 
-            formulae.sort_unstable_by_key(|f| {
-                f.analytics.as_ref().map(|a| a.number).unwrap_or_default()
-            });
+                let install = Install {
+                    names,
+                    formula: true,
+                    cask: false,
+                    yes: false,
+                    summary: false,
+                };
 
-            let mut buf = BufWriter::new(std::io::stdout());
+                install.run(engine)?;
+            }
 
-            if std::io::stdout().is_terminal() {
-                if self.all {
-                    for (i, f) in formulae.iter().enumerate() {
-                        info_formula(&mut buf, f, None)?;
+            Ok(all_found)
+        }
 
-                        if i != formulae.len() - 1 {
-                            writeln!(buf)?;
-                        }
+        /// Prints the formulae providing the executables, returning them along with whether
+        /// every executable is provided by one. Installed kegs are only read with --output
+        pub fn show(
+            &self,
+            state: &State,
+            names: &[String],
+            cache_age: Option<Duration>,
+        ) -> anyhow::Result<(Vec<(String, Vec<models::formula::Formula>)>, bool)> {
+            let indexes = Indexes::new(state);
+
+            let mut found = Vec::with_capacity(names.len());
+            let mut missing = false;
+
+            for name in names {
+                match self.resolve(state, &indexes, name) {
+                    Some(resolved) => found.push(resolved),
+                    None => {
+                        not_found(
+                            &format!("No formula provides {name}"),
+                            name,
+                            indexes.executables().keys().copied(),
+                            cache_age,
+                        )?;
+
+                        missing = true;
                     }
-                } else {
-                    // we return early if formulae is empty, so we have at least 1 element
-                    let first = formulae.first().unwrap();
+                }
+            }
 
-                    info_formula(&mut buf, first, None)?;
+            if found.is_empty() {
+                return Ok((found, false));
+            }
 
-                    let rest: Vec<_> = formulae.into_iter().skip(1).collect();
+            let several = names.len() > 1;
+            let mut buf = BufWriter::new(std::io::stdout());
 
-                    if !rest.is_empty() {
-                        write!(buf, "Command {} is also provided by", name.purple().bold())?;
+            if let Some(output) = self.output {
+                let installed = &state.formulae.installed;
+
+                let rows: Vec<_> = found
+                    .iter()
+                    .flat_map(|(name, formulae)| {
+                        self.shown(formulae).iter().map(move |f| Row {
+                            executable: name,
+                            formula: &f.base.name,
+                            version: &f.base.versions.stable,
+                            tap: &f.base.tap,
+                            installed: installed.contains_key(&f.base.name),
+                        })
+                    })
+                    .collect();
 
-                        for f in rest {
-                            write!(buf, " {}", f.base.name.cyan().bold())?;
+                match output {
+                    Output::Plain => {
+                        for row in rows {
+                            writeln!(
+                                buf,
+                                "{}\t{}\t{}\t{}\t{}",
+                                row.executable, row.formula, row.version, row.tap, row.installed
+                            )?;
                         }
-
+                    }
+                    Output::Json => {
+                        serde_json::to_writer(&mut buf, &rows)?;
                         writeln!(buf)?;
                     }
                 }
-            } else {
-                let formulae = if self.all {
-                    formulae
-                } else {
-                    formulae.into_iter().take(1).collect()
-                };
+            } else if std::io::stdout().is_terminal() {
+                for (i, (name, formulae)) in found.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(buf)?;
+                    }
 
-                for f in formulae {
-                    writeln!(buf, "{}", f.base.name)?;
+                    if several {
+                        writeln!(buf, "{}", header::primary!("{name}"))?;
+                    }
+
+                    self.describe(&mut buf, name, formulae)?;
+                }
+            } else {
+                // executables prefix the names when there are several, so lines can be told apart
+                for (name, formulae) in &found {
+                    for f in self.shown(formulae) {
+                        if several {
+                            writeln!(buf, "{name}\t{}", f.base.name)?;
+                        } else {
+                            writeln!(buf, "{}", f.base.name)?;
+                        }
+                    }
                 }
             }
 
             buf.flush()?;
 
-            Ok(true)
+            Ok((found, !missing))
         }
 
-        fn run_skim(&self, state: &State) -> anyhow::Result<String> {
-            let mut executables: HashMap<String, models::formula::Store> = HashMap::new();
+        /// Executable, or the closest one with --fuzzy, and the formulae providing it,
+        /// the most popular first
+        fn resolve(
+            &self,
+            state: &State,
+            indexes: &Indexes,
+            name: &str,
+        ) -> Option<(String, Vec<models::formula::Formula>)> {
+            let mut name = name.to_string();
+
+            if self.fuzzy && indexes.providers(&name).is_empty() {
+                if let Some(closest) = closest(&name, indexes.executables().keys().copied()) {
+                    eprintln!(
+                        "{}",
+                        header::warning!(
+                            "No formula provides {name}, using the closest match {closest}"
+                        )
+                    );
 
-            for f in state.formulae.all.values() {
-                for e in f.executables.iter() {
-                    match executables.get_mut(e) {
-                        Some(store) => {
-                            store.insert(f.base.name.clone(), f.clone());
-                        }
-                        None => {
-                            let mut store = HashMap::new();
+                    name = closest.to_string();
+                }
+            }
 
-                            store.insert(f.base.name.clone(), f.clone());
+            let mut formulae: Vec<_> = indexes
+                .providers(&name)
+                .iter()
+                .map(|key| state.formulae.all[*key].clone())
+                .collect();
 
-                            executables.insert(e.clone(), store);
-                        }
+            if formulae.is_empty() {
+                return None;
+            }
+
+            formulae.sort_by(|a, b| {
+                b.installs()
+                    .cmp(&a.installs())
+                    .then_with(|| a.base.name.cmp(&b.base.name))
+            });
+
+            Some((name, formulae))
+        }
+
+        /// All providers with --all, the most popular one otherwise
+        fn shown<'a>(
+            &self,
+            formulae: &'a [models::formula::Formula],
+        ) -> &'a [models::formula::Formula] {
+            if self.all {
+                formulae
+            } else {
+                &formulae[..1]
+            }
+        }
+
+        fn describe(
+            &self,
+            buf: &mut impl Write,
+            name: &str,
+            formulae: &[models::formula::Formula],
+        ) -> anyhow::Result<()> {
+            if self.all {
+                for (i, f) in formulae.iter().enumerate() {
+                    info_formula(&mut *buf, f, None)?;
+
+                    if i != formulae.len() - 1 {
+                        writeln!(buf)?;
                     }
                 }
+
+                return Ok(());
             }
 
-            let executables = executables
-                .into_iter()
-                .map(|(name, provided_by)| Executable { name, provided_by });
+            // resolved executables have at least one provider
+            let (first, rest) = formulae.split_first().unwrap();
+
+            info_formula(&mut *buf, first, None)?;
+
+            if !rest.is_empty() {
+                write!(buf, "Command {} is also provided by", name.purple().bold())?;
+
+                for f in rest {
+                    write!(buf, " {}", f.base.name.cyan().bold())?;
+                }
+
+                writeln!(buf)?;
+            }
+
+            Ok(())
+        }
+
+        /// Picked executable and whether it was accepted with the install key
+        fn run_skim(&self, state: &State, indexes: &Indexes) -> anyhow::Result<(String, bool)> {
+            let executables = indexes.executables().iter().map(|(name, keys)| {
+                let provided_by = keys
+                    .iter()
+                    .map(|key| &state.formulae.all[*key])
+                    .map(|f| (f.base.name.clone(), f.clone()))
+                    .collect();
 
-            let selected = select_skim(executables, "Executables", false)?;
+                Executable {
+                    name: name.to_string(),
+                    provided_by,
+                }
+            });
+
+            let (selected, key) =
+                select_skim_with(executables, "Executables", false, Some(INSTALL_KEY))?;
             let selected = selected.into_iter().map(|e| e.name).take(1).collect();
 
-            Ok(selected)
+            Ok((selected, key == Some(Key::Alt('i'))))
         }
     }
 
+    /// Best fuzzy match of the query, shorter names win ties as they are closer to it
+    fn closest<'a>(query: &str, executables: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        let mut matcher = nucleo_matcher::Matcher::new(nucleo_matcher::Config::DEFAULT);
+
+        let atom = Atom::new(
+            query,
+            CaseMatching::Ignore,
+            Normalization::Smart,
+            AtomKind::Fuzzy,
+            false,
+        );
+
+        atom.match_list(executables, &mut matcher)
+            .into_iter()
+            .min_by(|(a, a_score), (b, b_score)| {
+                b_score
+                    .cmp(a_score)
+                    .then(a.len().cmp(&b.len()))
+                    .then(a.cmp(b))
+            })
+            .map(|(name, _)| name)
+    }
+
     #[derive(Clone)]
     struct Executable {
         pub name: String,
@@ -221,15 +596,96 @@ pub mod which {
 }
 
 #[derive(Args)]
-pub struct Update {}
+pub struct Diff {}
+
+impl Diff {
+    pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+        let Some(changes) = engine.installed_diff()? else {
+            println!("Installed kegs were never recorded, nothing to compare with");
+
+            return Ok(false);
+        };
+
+        if changes.is_empty() {
+            println!(
+                "{}",
+                "No installed kegs changed since the last run".italic()
+            );
+
+            return Ok(true);
+        }
+
+        let mut w = BufWriter::new(std::io::stdout());
+
+        for c in changes {
+            let kind = if c.cask { "Cask" } else { "Formula" };
+
+            match (c.before, c.after) {
+                (None, Some(after)) => {
+                    writeln!(w, "{} {} {after} ({kind})", "+".green(), c.name.cyan())?
+                }
+                (Some(before), None) => {
+                    writeln!(w, "{} {} {before} ({kind})", "-".red(), c.name.cyan())?
+                }
+                (Some(before), Some(after)) => writeln!(
+                    w,
+                    "{} {} {} -> {} ({kind})",
+                    "~".yellow(),
+                    c.name.cyan(),
+                    before.red(),
+                    after.green()
+                )?,
+                (None, None) => {}
+            }
+        }
+
+        w.flush()?;
+
+        Ok(true)
+    }
+}
+
+#[derive(Args)]
+pub struct Update {
+    /// Only report whether the cache needs an update, exit with 1 if it does
+    #[clap(long, action)]
+    pub check: bool,
+
+    /// Refresh formulae, combine with other sections, everything is refreshed by default
+    #[clap(long, action)]
+    pub formulae: bool,
+
+    /// Refresh casks
+    #[clap(long, action)]
+    pub casks: bool,
+
+    /// Refresh the executables database
+    #[clap(long, action)]
+    pub executables: bool,
+}
 
 impl Update {
-    pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
-        println!("Updating the database, this will take some time");
+    pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+        if self.check {
+            return self.check(&engine);
+        }
+
+        let sections = self.sections(&engine)?;
+
+        let bar = progress::Bar::new()?;
 
-        let state = engine.fetch_latest()?;
+        let state = timed(&mut engine, Operation::Update, |engine| {
+            let state = engine.fetch_sections_with(&bar, sections)?;
 
-        engine.update_cache(&state)?;
+            bar.phase(Phase::Store);
+            engine.update_cache_of(&state, sections)?;
+
+            Ok(state)
+        });
+
+        bar.finish();
+
+        let state = state?;
 
         println!(
             "Database updated, found {} formulae and {} casks",
@@ -237,7 +693,48 @@ impl Update {
             state.casks.all.len()
         );
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Requested sections, everything if none is given or there is no cache to keep from
+    fn sections(&self, engine: &Engine) -> anyhow::Result<Sections> {
+        if !(self.formulae || self.casks || self.executables) || engine.cache_age()?.is_none() {
+            return Ok(Sections::ALL);
+        }
+
+        Ok(Sections {
+            formulae: self.formulae,
+            casks: self.casks,
+            executables: self.executables,
+        })
+    }
+
+    fn check(&self, engine: &Engine) -> anyhow::Result<bool> {
+        let Some(age) = engine.cache_age()? else {
+            println!("Cache was never updated, update is needed");
+
+            return Ok(false);
+        };
+
+        let age = pretty::duration(age);
+
+        let up_to_date = if engine.cache_expired()? {
+            println!("Cache is expired ({age} old), update is needed");
+
+            false
+        } else {
+            println!("Cache is up to date ({age} old)");
+
+            true
+        };
+
+        for section in Sections::ALL.names() {
+            if let Some(age) = engine.section_age(section)? {
+                println!("{section} refreshed {} ago", pretty::duration(age));
+            }
+        }
+
+        Ok(up_to_date)
     }
 }
 
@@ -258,12 +755,61 @@ pub struct List {
     /// List the formulae installed as dependencies.
     #[clap(short = 'd', long, action, group = "installed")]
     pub installed_as_dependency: bool,
+
+    /// List formulae and casks with the given tag
+    #[clap(short, long)]
+    pub tag: Option<String>,
+
+    /// List formulae and casks with notes along with the notes
+    #[clap(short, long, action)]
+    pub notes: bool,
+
+    /// List starred formulae and casks only
+    #[clap(short, long, action)]
+    pub starred: bool,
+
+    /// List installed formulae whose taps are no longer tapped
+    #[clap(short, long, action)]
+    pub untapped: bool,
+
+    /// Print tap-qualified names, e.g. homebrew/core/wget
+    #[clap(long, action)]
+    pub full_names: bool,
 }
 
 impl List {
-    pub fn run(&self, state: State) -> anyhow::Result<()> {
+    pub fn run(&self, engine: &Engine, mut state: State) -> anyhow::Result<()> {
+        if self.untapped {
+            return self.list_untapped(engine);
+        }
+
+        if let Some(tag) = &self.tag {
+            let tag = tag.to_lowercase();
+
+            state
+                .formulae
+                .installed
+                .retain(|_, f| f.upstream.tags.contains(&tag));
+            state
+                .casks
+                .installed
+                .retain(|_, c| c.upstream.tags.contains(&tag));
+        }
+
+        if self.starred {
+            state.formulae.installed.retain(|_, f| f.upstream.starred);
+            state.casks.installed.retain(|_, c| c.upstream.starred);
+        }
+
         let mut buf = BufWriter::new(std::io::stdout());
 
+        if self.notes {
+            self.list_notes(&mut buf, state)?;
+            buf.flush()?;
+
+            return Ok(());
+        }
+
         let max_width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
 
         if self.formulae {
@@ -294,7 +840,19 @@ impl List {
         let mut installed: Vec<_> = formulae
             .into_values()
             .filter_map(|f| {
-                let name = f.upstream.base.name;
+                let mut name = if self.full_names {
+                    f.upstream.base.tap_qualified_name()
+                } else {
+                    f.upstream.base.name.clone()
+                };
+
+                if f.upstream.starred {
+                    name = format!("{name} {}", pretty::star());
+                }
+
+                if outdated::formula(&f).is_some() {
+                    name = format!("{name} {}", pretty::outdated());
+                }
 
                 if self.installed_as_dependency {
                     return if f.receipt.installed_as_dependency {
@@ -325,947 +883,5856 @@ impl List {
         Ok(())
     }
 
-    fn list_casks(
-        &self,
-        w: &mut impl Write,
-        max_width: u16,
-        casks: models::cask::installed::Store,
-    ) -> anyhow::Result<()> {
-        writeln!(w, "{}", header::primary!("Casks"))?;
-
-        let mut installed: Vec<_> = casks.into_values().map(|v| v.upstream.base.token).collect();
-
-        installed.sort_unstable();
+    fn list_untapped(&self, engine: &Engine) -> anyhow::Result<()> {
+        let mut buf = BufWriter::new(std::io::stdout());
 
-        let table = pretty::table(&installed, max_width);
+        for (name, tap) in engine.untapped()? {
+            writeln!(buf, "{} {}", name.cyan(), tap.yellow())?;
+        }
 
-        table.print(w)?;
+        buf.flush()?;
 
         Ok(())
     }
-}
 
-#[derive(Args)]
-pub struct Info {
-    pub name: String,
+    fn list_notes(&self, w: &mut impl Write, state: State) -> anyhow::Result<()> {
+        let mut notes: Vec<(String, String)> = Vec::new();
 
-    /// Treat the given name as cask
-    #[clap(long, short, action, group = "type")]
-    pub cask: bool,
+        if !self.casks {
+            for f in state.formulae.installed.into_values() {
+                if let Some(note) = f.upstream.note {
+                    notes.push((f.upstream.base.name, note));
+                }
+            }
+        }
 
-    /// Treat the given name as formula
-    #[clap(long, short, action, group = "type")]
-    pub formula: bool,
+        if !self.formulae {
+            for c in state.casks.installed.into_values() {
+                if let Some(note) = c.upstream.note {
+                    notes.push((c.upstream.base.token, note));
+                }
+            }
+        }
 
-    /// Open the homepage using default browser
+        notes.sort_unstable();
+
+        for (name, note) in notes {
+            writeln!(w, "{} {}", name.cyan(), note.italic())?;
+        }
+
+        Ok(())
+    }
+
+    fn list_casks(
+        &self,
+        w: &mut impl Write,
+        max_width: u16,
+        casks: models::cask::installed::Store,
+    ) -> anyhow::Result<()> {
+        writeln!(w, "{}", header::primary!("Casks"))?;
+
+        let mut installed: Vec<_> = casks
+            .into_values()
+            .map(|v| {
+                let mut name = if self.full_names {
+                    v.upstream.base.tap_qualified_token()
+                } else {
+                    v.upstream.base.token.clone()
+                };
+
+                if v.upstream.starred {
+                    name = format!("{name} {}", pretty::star());
+                }
+
+                if v.locally_newer() {
+                    name = format!("{name} {}", pretty::locally_newer());
+                } else if outdated::cask(&v).is_some() {
+                    name = format!("{name} {}", pretty::outdated());
+                }
+
+                name
+            })
+            .collect();
+
+        installed.sort_unstable();
+
+        let table = pretty::table(&installed, max_width);
+
+        table.print(w)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct Provides {
+    /// Formula to list the executables of, or a file name or a part of it to search for.
+    /// `*` matches any characters, e.g. ripgrep, libssl.3.dylib or "*.pc"
+    pub pattern: String,
+
+    /// Search for files even when a formula has the given name
     #[clap(long, short, action)]
-    pub open_homepage: bool,
+    pub search: bool,
 }
 
-impl Info {
-    pub fn run(&self, state: State) -> anyhow::Result<bool> {
-        if self.cask {
-            let Some(cask) = state.casks.all.get(&self.name) else {
-                return Ok(false);
-            };
+impl Provides {
+    pub fn run(&self, engine: &Engine, state: State) -> anyhow::Result<bool> {
+        if !self.search && !self.pattern.contains('*') {
+            if let Some(formula) = state.formulae.all.get(&self.pattern) {
+                return self.run_formula(engine, &state, formula);
+            }
+        }
 
-            self.handle_cask(cask, state.casks.installed.get(&self.name))?;
+        let mut found: Vec<(&str, &str, String)> = Vec::new();
 
-            return Ok(true);
+        for f in state.formulae.all.values() {
+            for executable in &f.executables {
+                if matches_pattern(&self.pattern, executable) {
+                    found.push((f.base.name.as_str(), "executable", executable.clone()));
+                }
+            }
         }
 
-        if self.formula {
-            let Some(formula) = state.formulae.all.get(&self.name) else {
-                return Ok(false);
-            };
+        // libraries and pkg-config files are only known for installed kegs
+        for name in state.formulae.installed.keys() {
+            let files = engine.keg_files(name);
 
-            self.handle_formula(formula, state.formulae.installed.get(&self.name))?;
+            let libraries = files.libraries.into_iter().map(|l| ("library", l));
+            let pkgconfig = files.pkgconfig.into_iter().map(|p| ("pkg-config", p));
 
-            return Ok(true);
+            for (kind, file) in libraries.chain(pkgconfig) {
+                if matches_pattern(&self.pattern, &file) {
+                    found.push((name.as_str(), kind, file));
+                }
+            }
+        }
+
+        if found.is_empty() {
+            eprintln!(
+                "{}",
+                header::warning!(
+                    "Nothing provides {}, libraries and pkg-config files are only known for installed formulae",
+                    self.pattern
+                )
+            );
+
+            return Ok(false);
         }
 
-        match state.formulae.all.get(&self.name) {
-            Some(formula) => {
-                self.handle_formula(formula, state.formulae.installed.get(&self.name))?
+        found.sort_unstable();
+        found.dedup();
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        for (formula, kind, file) in found {
+            if std::io::stdout().is_terminal() {
+                writeln!(buf, "{} {} {}", formula.cyan(), kind.dimmed(), file)?;
+            } else {
+                writeln!(buf, "{formula}\t{kind}\t{file}")?;
             }
-            None => match state.casks.all.get(&self.name) {
-                Some(cask) => self.handle_cask(cask, state.casks.installed.get(&self.name))?,
-                None => return Ok(false),
-            },
-        };
+        }
+
+        buf.flush()?;
 
         Ok(true)
     }
 
-    pub fn handle_formula(
+    /// Executables of the formula known to the registry, along with the ones
+    /// actually present in the keg when it is installed
+    fn run_formula(
         &self,
+        engine: &Engine,
+        state: &State,
         formula: &models::formula::Formula,
-        installed: Option<&models::formula::installed::Formula>,
-    ) -> anyhow::Result<()> {
-        if self.open_homepage {
-            if let Some(homepage) = &formula.base.homepage {
-                open::that_detached(homepage)?;
-                return Ok(());
+    ) -> anyhow::Result<bool> {
+        let name = &formula.base.name;
+
+        let files = if state.formulae.installed.contains_key(name) {
+            engine.keg_files(name)
+        } else {
+            Default::default()
+        };
+
+        let mut executables: BTreeMap<&str, &str> = formula
+            .executables
+            .iter()
+            .map(|e| (e.as_str(), "registry"))
+            .collect();
+
+        for executable in &files.executables {
+            executables
+                .entry(executable)
+                .and_modify(|source| *source = "registry, keg")
+                .or_insert("keg");
+        }
+
+        if executables.is_empty() {
+            eprintln!("{}", header::warning!("No executables of {name} are known"));
+
+            return Ok(false);
+        }
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        for (executable, source) in executables {
+            if std::io::stdout().is_terminal() {
+                writeln!(buf, "{} {}", executable.cyan(), source.dimmed())?;
+            } else {
+                writeln!(buf, "{executable}\t{source}")?;
             }
         }
 
+        buf.flush()?;
+
+        Ok(true)
+    }
+}
+
+#[derive(Args)]
+pub struct Owns {
+    /// File to look up, symlinks are followed, e.g. /opt/homebrew/bin/rg
+    #[clap(value_hint = ValueHint::AnyPath)]
+    pub path: PathBuf,
+}
+
+impl Owns {
+    pub fn run(&self, engine: &Engine, state: State) -> anyhow::Result<bool> {
+        if !self.path.exists() {
+            return Err(NotFound(format!("{} does not exist", self.path.display())).into());
+        }
+
+        let Some(keg) = engine.owner(&state, &self.path)? else {
+            eprintln!(
+                "{}",
+                header::warning!(
+                    "{} is not owned by any installed formula or cask",
+                    self.path.display()
+                )
+            );
+
+            return Ok(false);
+        };
+
+        let kind = match keg {
+            models::Keg::Formula(_) => "formula",
+            models::Keg::Cask(_) => "cask",
+        };
+
+        if std::io::stdout().is_terminal() {
+            println!("{} {}", keg.name().cyan(), kind.dimmed());
+        } else {
+            println!("{}\t{kind}", keg.name());
+        }
+
+        Ok(true)
+    }
+}
+
+#[derive(Args)]
+pub struct Examples {
+    /// Subcommand to show examples of, all of them by default
+    pub command: Option<String>,
+}
+
+impl Examples {
+    pub fn run(&self, json: bool) -> anyhow::Result<()> {
+        let entries: Vec<&examples::Entry> = match &self.command {
+            Some(command) => match examples::of(command) {
+                Some(entry) => vec![entry],
+                None => return Err(NotFound(format!("No examples of {command}")).into()),
+            },
+            None => examples::REGISTRY.iter().collect(),
+        };
+
         let mut buf = BufWriter::new(std::io::stdout());
 
-        info_formula(&mut buf, formula, installed)?;
+        if json {
+            serde_json::to_writer_pretty(&mut buf, &entries)?;
+            writeln!(buf)?;
+        } else {
+            let headers = entries.len() > 1 && std::io::stdout().is_terminal();
+
+            for (i, entry) in entries.iter().enumerate() {
+                if i > 0 {
+                    writeln!(buf)?;
+                }
+
+                if headers {
+                    writeln!(buf, "{}", header::primary!("{}", entry.command))?;
+                }
+
+                writeln!(buf, "{}", examples::render(entry.examples))?;
+            }
+        }
 
         buf.flush()?;
 
         Ok(())
     }
+}
 
-    pub fn handle_cask(
-        &self,
-        cask: &models::cask::Cask,
-        installed: Option<&models::cask::installed::Cask>,
-    ) -> anyhow::Result<()> {
-        if self.open_homepage {
-            if let Some(homepage) = &cask.base.homepage {
-                open::that_detached(homepage)?;
-                return Ok(());
-            }
+/// Whether the name contains the pattern, or matches it entirely when it has `*` wildcards
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    if !pattern.contains('*') {
+        return name.contains(pattern);
+    }
+
+    let parts: Vec<_> = pattern.split('*').collect();
+    let last = parts.len() - 1;
+
+    let mut rest = name;
+
+    for (i, part) in parts.into_iter().enumerate() {
+        if i == 0 {
+            let Some(stripped) = rest.strip_prefix(part) else {
+                return false;
+            };
+
+            rest = stripped;
+        } else if i == last {
+            return rest.ends_with(part);
+        } else {
+            let Some(at) = rest.find(part) else {
+                return false;
+            };
+
+            rest = &rest[at + part.len()..];
+        }
+    }
+
+    true
+}
+
+#[derive(Args)]
+pub struct Xref {
+    pub name: String,
+
+    /// Use the bundled table only, never query repology.org
+    #[clap(long, action)]
+    pub bundled: bool,
+}
+
+impl Xref {
+    pub fn run(&self, offline: bool) -> anyhow::Result<bool> {
+        let equivalents = match brewer_core::xref::bundled(&self.name) {
+            Some(equivalents) => equivalents,
+            None if self.bundled || offline => Default::default(),
+            None => brewer_core::xref::repology(&self.name)?,
+        };
+
+        if equivalents.is_empty() {
+            eprintln!(
+                "{}",
+                header::warning!("No equivalents of {} are known", self.name)
+            );
+
+            return Ok(false);
         }
 
         let mut buf = BufWriter::new(std::io::stdout());
 
-        info_cask(&mut buf, cask, installed)?;
+        for (manager, names) in [("nixpkgs", &equivalents.nix), ("apt", &equivalents.apt)] {
+            if names.is_empty() {
+                writeln!(buf, "{} {}", manager.bold(), "unknown".italic())?;
+            } else {
+                writeln!(buf, "{} {}", manager.bold(), names.join(", ").cyan())?;
+            }
+        }
 
         buf.flush()?;
 
+        Ok(true)
+    }
+}
+
+#[derive(Args)]
+pub struct Cleanup {
+    /// Only show what would be removed
+    #[clap(short = 'n', long, action)]
+    pub dry_run: bool,
+
+    /// Confirm
+    #[clap(short, long, action)]
+    pub yes: bool,
+}
+
+impl Cleanup {
+    pub fn run(&self, engine: Engine) -> anyhow::Result<()> {
+        let plan = engine.cleanup_plan()?;
+
+        if plan.entries.is_empty() {
+            println!("{}", header::primary!("Nothing to clean up"));
+            return Ok(());
+        }
+
+        let mut w = BufWriter::new(std::io::stderr());
+
+        let (kegs, downloads): (Vec<_>, Vec<_>) = plan.entries.iter().partition(|e| e.is_keg());
+
+        for (title, entries) in [("Old versions", kegs), ("Cached downloads", downloads)] {
+            if entries.is_empty() {
+                continue;
+            }
+
+            writeln!(w, "{}", header::primary!("{title}"))?;
+
+            for entry in entries {
+                match entry.size {
+                    Some(size) => writeln!(
+                        w,
+                        "{} {}",
+                        entry.path.to_string_lossy(),
+                        pretty::bytes(size).cyan()
+                    )?,
+                    None => writeln!(w, "{}", entry.path.to_string_lossy())?,
+                }
+            }
+
+            writeln!(w)?;
+        }
+
+        writeln!(w, "Would free ~{}", pretty::bytes(plan.size()))?;
+        writeln!(w)?;
+        w.flush()?;
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let proceed = self.yes
+            || inquire::Confirm::new("Proceed?")
+                .with_default(false)
+                .prompt()?;
+
+        if !proceed {
+            return Err(Cancelled.into());
+        }
+
+        engine.cleanup()?;
+
         Ok(())
     }
 }
 
-fn info_formula(
-    mut buf: impl Write,
-    formula: &models::formula::Formula,
-    installed: Option<&models::formula::installed::Formula>,
-) -> anyhow::Result<()> {
-    writeln!(
-        buf,
-        "{}",
-        header::primary!(
-            "{} {} (Cask)",
-            &formula.base.name,
-            formula.base.versions.stable
-        )
-    )?;
-    writeln!(buf, "From {}", formula.base.tap.yellow())?;
+#[derive(Args)]
+pub struct Doctor {}
+
+impl Doctor {
+    /// Returns false if any error-level issue was found
+    pub fn run(&self, engine: Engine) -> anyhow::Result<bool> {
+        let diagnostics = engine.doctor()?;
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        if diagnostics
+            .iter()
+            .all(|d| d.level == models::doctor::Level::Note)
+        {
+            writeln!(buf, "{}", header::primary!("No issues found"))?;
+        }
+
+        for diagnostic in &diagnostics {
+            let title = match diagnostic.level {
+                models::doctor::Level::Note => header::primary!("{}", diagnostic.title),
+                models::doctor::Level::Warning => header::warning!("{}", diagnostic.title),
+                models::doctor::Level::Error => header::error!("{}", diagnostic.title),
+            };
+
+            writeln!(buf, "{title}")?;
+
+            for line in &diagnostic.details {
+                writeln!(buf, "{line}")?;
+            }
+
+            writeln!(buf)?;
+        }
+
+        buf.flush()?;
+
+        Ok(!diagnostics
+            .iter()
+            .any(|d| d.level == models::doctor::Level::Error))
+    }
+}
+
+#[derive(Args)]
+pub struct Outdated {
+    /// List outdated casks only
+    #[clap(short, long, action, group = "type")]
+    pub casks: bool,
+
+    /// List outdated formulae only
+    #[clap(short, long, action, group = "type")]
+    pub formulae: bool,
+
+    /// Only list kegs from the given taps, e.g. homebrew/core
+    #[clap(long = "tap", value_name = "TAP")]
+    pub taps: Vec<String>,
+}
+
+impl Outdated {
+    pub fn run(&self, state: State) -> anyhow::Result<()> {
+        let mut outdated = outdated::all(&state);
+
+        if !self.taps.is_empty() {
+            outdated.formulae.retain(|k| in_taps(&k.tap, &self.taps));
+            outdated.casks.retain(|k| in_taps(&k.tap, &self.taps));
+        }
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        if !self.casks {
+            writeln!(buf, "{}", header::primary!("Formulae"))?;
+            Self::write(&mut buf, &outdated.formulae)?;
+        }
+
+        if !self.formulae {
+            writeln!(buf, "{}", header::primary!("Casks"))?;
+            Self::write(&mut buf, &outdated.casks)?;
+        }
+
+        buf.flush()?;
+
+        Ok(())
+    }
+
+    fn write(w: &mut impl Write, kegs: &[outdated::Outdated]) -> anyhow::Result<()> {
+        if kegs.is_empty() {
+            writeln!(w, "{}", "Everything is up to date".italic())?;
+        }
+
+        for keg in kegs {
+            write!(
+                w,
+                "{} {} -> {}",
+                keg.name.cyan(),
+                keg.installed.red(),
+                keg.latest.green()
+            )?;
+
+            if keg.pinned {
+                write!(w, " {}", "pinned".dimmed())?;
+            }
+
+            match &keg.locally_newer {
+                Some(app) => writeln!(
+                    w,
+                    " {} locally newer, the app is at {app}",
+                    pretty::locally_newer()
+                )?,
+                None => writeln!(w)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct Info {
+    /// Formulae or casks to show, the state is loaded once for all of them
+    #[clap(required = true)]
+    pub names: Vec<String>,
+
+    /// Treat the given name as cask
+    #[clap(long, short, action, group = "type")]
+    pub cask: bool,
+
+    /// Treat the given name as formula
+    #[clap(long, short, action, group = "type")]
+    pub formula: bool,
+
+    /// Open the homepage using default browser
+    #[clap(long, short, action)]
+    pub open_homepage: bool,
+
+    /// Show where cask artifacts were placed and whether they still exist
+    #[clap(long, action)]
+    pub installed_files_tree: bool,
+
+    /// Print one line per formula or cask: name, version, tap and description
+    #[clap(long, action, conflicts_with_all = ["open_homepage", "installed_files_tree"])]
+    pub short: bool,
+
+    /// Leave out the given sections of formula info, e.g. caveats,analytics
+    #[clap(long, value_enum, value_delimiter = ',', value_name = "SECTION")]
+    pub hide: Vec<Section>,
+}
+
+/// Optional parts of formula info
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Section {
+    /// Runtime and build dependencies
+    Dependencies,
+
+    /// Notes from the formula on using it after installing
+    Caveats,
+
+    /// Install counts over the last 30 days
+    Analytics,
+
+    /// Formulae which can't be linked alongside
+    Conflicts,
+}
+
+impl Info {
+    /// Returns false only if none of the names were found
+    pub fn run(
+        &self,
+        engine: &Engine,
+        state: State,
+        cache_age: Option<Duration>,
+    ) -> anyhow::Result<bool> {
+        let mut found = 0;
+
+        for name in &self.names {
+            if self.run_name(engine, &state, name, found > 0, cache_age)? {
+                found += 1;
+            }
+        }
+
+        Ok(found > 0)
+    }
+
+    /// `separate` puts a blank line before the block, so that blocks of found names
+    /// are separated the same way regardless of names missing in between
+    fn run_name(
+        &self,
+        engine: &Engine,
+        state: &State,
+        name: &str,
+        separate: bool,
+        cache_age: Option<Duration>,
+    ) -> anyhow::Result<bool> {
+        let found = self.run_found(engine, state, name, separate)?;
+
+        let ambiguous = !found
+            && (brewer_engine::resolve_formula(state, name).is_ambiguous()
+                || brewer_engine::resolve_cask(state, name).is_ambiguous());
+
+        if !found && !ambiguous {
+            let formulae = state.formulae.all.keys().filter(|_| !self.cask);
+            let casks = state.casks.all.keys().filter(|_| !self.formula);
+
+            let what = if self.cask {
+                "cask"
+            } else if self.formula {
+                "formula"
+            } else {
+                "formula or cask"
+            };
+
+            not_found(
+                &format!("No {what} named {name}"),
+                name,
+                formulae.chain(casks).map(|n| n.as_str()),
+                cache_age,
+            )?;
+        }
+
+        Ok(found)
+    }
+
+    fn run_found(
+        &self,
+        engine: &Engine,
+        state: &State,
+        name: &str,
+        separate: bool,
+    ) -> anyhow::Result<bool> {
+        let separator = || {
+            if separate && !self.short {
+                println!();
+            }
+        };
+
+        let formula = if self.cask {
+            Resolution::Missing
+        } else {
+            brewer_engine::resolve_formula(state, name)
+        };
+
+        let cask = if self.formula {
+            Resolution::Missing
+        } else {
+            brewer_engine::resolve_cask(state, name)
+        };
+
+        match (formula, cask) {
+            (Resolution::Found(key), _) => {
+                let formula = &state.formulae.all[&key];
+                let installed = state.formulae.installed.get(&formula.base.name);
+
+                separator();
+
+                if self.short {
+                    short(
+                        &formula.base.name,
+                        &formula.base.versions.stable,
+                        &formula.base.tap,
+                        formula.base.desc.as_deref(),
+                        installed.is_some(),
+                    );
+                } else {
+                    self.handle_formula(state, formula, installed)?;
+                }
+            }
+            (Resolution::Ambiguous(candidates), _) => {
+                ambiguous(name, &candidates);
+                return Ok(false);
+            }
+            (_, Resolution::Found(key)) => {
+                let cask = &state.casks.all[&key];
+                let installed = state.casks.installed.get(&cask.base.token);
+
+                separator();
+
+                if self.short {
+                    short(
+                        &cask.base.token,
+                        &cask.base.version,
+                        &cask.base.tap,
+                        cask.base.desc.as_deref(),
+                        installed.is_some(),
+                    );
+                } else {
+                    self.handle_cask(engine, cask, installed)?;
+                }
+            }
+            (_, Resolution::Ambiguous(candidates)) => {
+                ambiguous(name, &candidates);
+                return Ok(false);
+            }
+            (Resolution::Missing, Resolution::Missing) => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    pub fn handle_formula(
+        &self,
+        state: &State,
+        formula: &models::formula::Formula,
+        installed: Option<&models::formula::installed::Formula>,
+    ) -> anyhow::Result<()> {
+        if self.open_homepage {
+            if let Some(homepage) = &formula.base.homepage {
+                open::that_detached(homepage)?;
+                return Ok(());
+            }
+        }
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        info_formula_with(&mut buf, formula, installed, &self.hide)?;
+
+        if installed.is_none() {
+            let missing = brewer_engine::missing_closure(state, &formula.base.name);
+
+            let executables: usize = missing
+                .iter()
+                .filter_map(|name| state.formulae.all.get(name))
+                .map(|f| f.executables.len())
+                .sum();
+
+            writeln!(buf)?;
+            writeln!(
+                buf,
+                "Installing would add {} new formulae (~{} executables)",
+                missing.len().to_string().bold(),
+                executables.to_string().bold()
+            )?;
+        }
+
+        buf.flush()?;
+
+        Ok(())
+    }
+
+    pub fn handle_cask(
+        &self,
+        engine: &Engine,
+        cask: &models::cask::Cask,
+        installed: Option<&models::cask::installed::Cask>,
+    ) -> anyhow::Result<()> {
+        if self.open_homepage {
+            if let Some(homepage) = &cask.base.homepage {
+                open::that_detached(homepage)?;
+                return Ok(());
+            }
+        }
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        info_cask(&mut buf, cask, installed)?;
+
+        if self.installed_files_tree {
+            writeln!(buf)?;
+            files_tree(&mut buf, &engine.cask_artifacts(cask))?;
+        }
+
+        buf.flush()?;
+
+        Ok(())
+    }
+}
+
+/// One line summary of a formula or cask, e.g. "wget 1.24.5 homebrew/core — Internet file retriever ✔"
+fn short(name: &str, version: &str, tap: &str, desc: Option<&str>, installed: bool) {
+    let mut line = format!("{} {} {}", name.cyan(), version, tap.dimmed());
+
+    if let Some(desc) = desc {
+        line = format!("{line} — {desc}");
+    }
+
+    if installed {
+        line = format!("{line} {}", pretty::bool(true));
+    }
+
+    println!("{line}");
+}
+
+#[derive(Args)]
+pub struct Home {
+    /// Formula or cask, picked interactively if not given
+    pub name: Option<String>,
+
+    /// Treat the given name as cask
+    #[clap(long, short, action, group = "type")]
+    pub cask: bool,
+
+    /// Treat the given name as formula
+    #[clap(long, short, action, group = "type")]
+    pub formula: bool,
+}
+
+impl Home {
+    pub fn run(&self, state: State) -> anyhow::Result<bool> {
+        let keg = match &self.name {
+            Some(name) => resolve_keg(&state, name, self.formula, self.cask),
+            None => self.select(state)?,
+        };
+
+        let Some(keg) = keg else {
+            return Ok(false);
+        };
+
+        let (name, homepage) = match &keg {
+            models::Keg::Formula(f) => (&f.base.name, &f.base.homepage),
+            models::Keg::Cask(c) => (&c.base.token, &c.base.homepage),
+        };
+
+        let Some(homepage) = homepage else {
+            eprintln!("{}", header::warning!("{name} has no homepage"));
+            return Ok(false);
+        };
+
+        open::that_detached(homepage)?;
+
+        Ok(true)
+    }
+
+    fn select(&self, state: State) -> anyhow::Result<Option<models::Keg>> {
+        let mut kegs: Vec<install::Keg> = Vec::new();
+
+        if !self.cask {
+            kegs.extend(state.formulae.all.into_values().map(install::Keg::from));
+        }
+
+        if !self.formula {
+            kegs.extend(state.casks.all.into_values().map(install::Keg::from));
+        }
+
+        let selected = select_skim(kegs, "Home", false)?;
+
+        Ok(selected.into_iter().next().map(|k| k.0))
+    }
+}
+
+#[derive(Args)]
+pub struct Cat {
+    pub name: String,
+
+    /// Treat the given name as cask
+    #[clap(long, short, action, group = "type")]
+    pub cask: bool,
+
+    /// Treat the given name as formula
+    #[clap(long, short, action, group = "type")]
+    pub formula: bool,
+
+    /// Do not highlight the source even when printing to a terminal
+    #[clap(long, action)]
+    pub plain: bool,
+}
+
+impl Cat {
+    pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+        let state = engine.cache_or_latest()?;
+
+        let Some(keg) = resolve_keg(&state, &self.name, self.formula, self.cask) else {
+            return Ok(false);
+        };
+
+        let source = engine.cat(&keg)?;
+
+        if self.plain || !std::io::stdout().is_terminal() {
+            print!("{source}");
+        } else {
+            print!("{}", pretty::ruby(&source));
+        }
+
+        Ok(true)
+    }
+}
+
+#[derive(Args)]
+pub struct Desc {
+    #[clap(required = true)]
+    pub names: Vec<String>,
+
+    /// Treat the given names as casks
+    #[clap(long, short, action, group = "type")]
+    pub cask: bool,
+
+    /// Treat the given names as formulae
+    #[clap(long, short, action, group = "type")]
+    pub formula: bool,
+}
+
+impl Desc {
+    pub fn run(&self, state: State) -> anyhow::Result<bool> {
+        let mut buf = BufWriter::new(std::io::stdout());
+        let mut found = true;
+
+        for name in &self.names {
+            let Some(keg) = resolve_keg(&state, name, self.formula, self.cask) else {
+                found = false;
+                continue;
+            };
+
+            let (name, desc) = match &keg {
+                models::Keg::Formula(f) => (&f.base.name, &f.base.desc),
+                models::Keg::Cask(c) => (&c.base.token, &c.base.desc),
+            };
+
+            writeln!(
+                buf,
+                "{name}: {}",
+                desc.as_deref().unwrap_or("No description")
+            )?;
+        }
+
+        buf.flush()?;
+
+        Ok(found)
+    }
+}
+
+/// Resolves the name to a formula, then to a cask, unless the kind is given.
+/// Explains on stderr why nothing was resolved
+fn resolve_keg(state: &State, name: &str, formula: bool, cask: bool) -> Option<models::Keg> {
+    let formula_resolution = if cask {
+        Resolution::Missing
+    } else {
+        brewer_engine::resolve_formula(state, name)
+    };
+
+    let cask_resolution = if formula {
+        Resolution::Missing
+    } else {
+        brewer_engine::resolve_cask(state, name)
+    };
+
+    match (formula_resolution, cask_resolution) {
+        (Resolution::Found(key), _) => Some(state.formulae.all[&key].clone().into()),
+        (Resolution::Ambiguous(candidates), _) | (_, Resolution::Ambiguous(candidates)) => {
+            ambiguous(name, &candidates);
+            None
+        }
+        (_, Resolution::Found(key)) => Some(state.casks.all[&key].clone().into()),
+        (Resolution::Missing, Resolution::Missing) => {
+            let alias = if cask {
+                None
+            } else {
+                Indexes::new(state).alias(name)
+            };
+
+            match alias {
+                Some(key) => Some(state.formulae.all[key].clone().into()),
+                None => {
+                    eprintln!("{}", header::warning!("No formula or cask named {name}"));
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Cask artifacts grouped by stanza, marking the ones present on disk
+fn files_tree(
+    w: &mut impl Write,
+    artifacts: &[(models::cask::artifact::Artifact, bool)],
+) -> anyhow::Result<()> {
+    writeln!(w, "{}", header::primary!("Installed files"))?;
+
+    if artifacts.is_empty() {
+        writeln!(w, "{}", "No known artifacts".italic())?;
+        return Ok(());
+    }
+
+    let mut kinds: Vec<&str> = Vec::new();
+
+    for (artifact, _) in artifacts {
+        if !kinds.contains(&artifact.kind.as_str()) {
+            kinds.push(&artifact.kind);
+        }
+    }
+
+    for kind in kinds {
+        writeln!(w, "{}", kind.bold())?;
+
+        let paths: Vec<_> = artifacts.iter().filter(|(a, _)| a.kind == kind).collect();
+        let last = paths.len().saturating_sub(1);
+
+        for (i, (artifact, exists)) in paths.into_iter().enumerate() {
+            let branch = if i == last {
+                "└── "
+            } else {
+                "├── "
+            };
+            let path = artifact.path.to_string_lossy();
+
+            if *exists {
+                writeln!(w, "{branch}{path} {}", pretty::bool(true))?;
+            } else {
+                writeln!(w, "{branch}{} {}", path.dimmed(), pretty::bool(false))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn info_formula(
+    buf: impl Write,
+    formula: &models::formula::Formula,
+    installed: Option<&models::formula::installed::Formula>,
+) -> anyhow::Result<()> {
+    info_formula_with(buf, formula, installed, &[])
+}
+
+/// Same as `info_formula`, leaving out the hidden sections
+fn info_formula_with(
+    mut buf: impl Write,
+    formula: &models::formula::Formula,
+    installed: Option<&models::formula::installed::Formula>,
+    hide: &[Section],
+) -> anyhow::Result<()> {
+    let shown = |section: Section| !hide.contains(&section);
+
+    writeln!(
+        buf,
+        "{}",
+        header::primary!(
+            "{} {} (Cask)",
+            &formula.base.name,
+            formula.base.versions.stable
+        )
+    )?;
+    writeln!(buf, "From {}", formula.base.tap.yellow())?;
+
+    if let Some(installed) = installed {
+        writeln!(buf)?;
+        writeln!(
+            buf,
+            "Installed {} {}",
+            installed.receipt.source.version(),
+            pretty::bool(true)
+        )?;
+
+        if let Some(outdated) = outdated::formula(installed) {
+            writeln!(buf, "Outdated, {} is available", outdated.latest.green())?;
+        }
+
+        if formula.base.keg_only {
+            writeln!(buf, "Linked {} (keg-only)", pretty::bool(installed.linked))?;
+        } else {
+            writeln!(buf, "Linked {}", pretty::bool(installed.linked))?;
+        }
+
+        provenance(&mut buf, &installed.receipt)?;
+    }
+
+    if let Some(homepage) = &formula.base.homepage {
+        writeln!(buf)?;
+        writeln!(buf, "{}", homepage.underline().blue())?;
+    }
+
+    if let Some(repo) = &formula.github {
+        github(&mut buf, repo)?;
+    }
+
+    if let Some(desc) = &formula.base.desc {
+        writeln!(buf)?;
+        writeln!(buf, "{}", desc.italic())?;
+    }
+
+    if formula.base.disabled || formula.base.deprecated {
+        let (status, reason) = if formula.base.disabled {
+            ("Disabled", &formula.base.disable_reason)
+        } else {
+            ("Deprecated", &formula.base.deprecation_reason)
+        };
+
+        writeln!(buf)?;
+
+        match reason {
+            Some(reason) => writeln!(buf, "{}, {reason}", status.red().bold())?,
+            None => writeln!(buf, "{}", status.red().bold())?,
+        }
+    }
+
+    if let Some(note) = &formula.note {
+        writeln!(buf)?;
+        writeln!(buf, "Note: {}", note.yellow())?;
+    }
+
+    if shown(Section::Analytics) {
+        if let Some(analytics) = &formula.analytics {
+            writeln!(buf)?;
+            installs(&mut buf, analytics.count, analytics.number)?;
+        }
+    }
+
+    if shown(Section::Dependencies) {
+        let dependencies = [
+            ("Depends on", &formula.base.dependencies),
+            ("Builds with", &formula.base.build_dependencies),
+        ];
+
+        if dependencies.iter().any(|(_, names)| !names.is_empty()) {
+            writeln!(buf)?;
+        }
+
+        for (title, names) in dependencies {
+            if !names.is_empty() {
+                let names: Vec<_> = names.iter().map(|n| n.cyan().to_string()).collect();
+
+                writeln!(buf, "{title} {}", names.join(", "))?;
+            }
+        }
+    }
+
+    if shown(Section::Conflicts) && !formula.base.conflicts_with.is_empty() {
+        let names: Vec<_> = formula
+            .base
+            .conflicts_with
+            .iter()
+            .map(|n| n.yellow().to_string())
+            .collect();
+
+        writeln!(buf)?;
+        writeln!(buf, "Conflicts with {}", names.join(", "))?;
+    }
+
+    match (formula.base.download_size(), formula.base.installed_size()) {
+        (Some(download), Some(installed)) => {
+            writeln!(buf)?;
+            writeln!(
+                buf,
+                "Bottle {} download, {} installed",
+                pretty::bytes(download),
+                pretty::bytes(installed)
+            )?;
+        }
+        (Some(download), None) => {
+            writeln!(buf)?;
+            writeln!(buf, "Bottle {} download", pretty::bytes(download))?;
+        }
+        (None, Some(installed)) => {
+            writeln!(buf)?;
+            writeln!(buf, "Bottle {} installed", pretty::bytes(installed))?;
+        }
+        (None, None) => {}
+    }
+
+    if !formula.executables.is_empty() {
+        writeln!(buf)?;
+        write!(buf, "Provides")?;
+
+        const LIMIT: usize = 5;
+
+        if formula.executables.len() > LIMIT {
+            for e in formula.executables.iter().take(LIMIT) {
+                write!(buf, " {}", e.bold().purple())?;
+            }
+
+            write!(buf, " and {} more", formula.executables.len() - LIMIT)?;
+        } else {
+            for e in formula.executables.iter() {
+                write!(buf, " {}", e.bold().purple())?;
+            }
+        }
+
+        writeln!(buf)?;
+    }
+
+    if shown(Section::Caveats) {
+        if let Some(caveats) = &formula.base.caveats {
+            writeln!(buf)?;
+            writeln!(buf, "{}", "Caveats".bold())?;
+            writeln!(buf, "{}", caveats.trim_end())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn provenance(
+    mut buf: impl Write,
+    receipt: &models::formula::receipt::Receipt,
+) -> anyhow::Result<()> {
+    writeln!(buf)?;
+    writeln!(buf, "{}", "Provenance".bold())?;
+
+    match receipt.poured_from_bottle {
+        Some(true) => writeln!(buf, "Poured from bottle")?,
+        Some(false) => writeln!(buf, "Built from source")?,
+        None => {}
+    }
+
+    let spec = match receipt.source.spec {
+        models::formula::receipt::Spec::Stable => "stable",
+        models::formula::receipt::Spec::Head => "HEAD",
+    };
+
+    writeln!(buf, "Spec {spec}")?;
+
+    if let Some(tap) = &receipt.source.tap {
+        writeln!(buf, "Tap {}", tap.yellow())?;
+    }
+
+    let reason = if receipt.installed_on_request {
+        "on request"
+    } else if receipt.installed_as_dependency {
+        "as dependency"
+    } else {
+        "for unknown reason"
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    match receipt.time {
+        Some(time) if time >= 0 => {
+            let ago = Duration::from_secs(now.saturating_sub(time as u64));
+
+            writeln!(buf, "Installed {reason} {} ago", pretty::duration(ago))?
+        }
+        _ => writeln!(buf, "Installed {reason}")?,
+    }
+
+    Ok(())
+}
+
+fn github(mut buf: impl Write, repo: &models::github::Repository) -> anyhow::Result<()> {
+    write!(buf, "{} {}", pretty::star(), repo.stars)?;
+
+    if repo.archived {
+        write!(buf, " {}", "Archived".red().bold())?;
+    }
+
+    writeln!(buf)?;
+
+    Ok(())
+}
+
+fn info_cask(
+    buf: &mut impl Write,
+    cask: &models::cask::Cask,
+    installed: Option<&models::cask::installed::Cask>,
+) -> anyhow::Result<()> {
+    writeln!(
+        buf,
+        "{}",
+        header::primary!("{} {} (Formula)", &cask.base.token, cask.base.version)
+    )?;
+    writeln!(buf, "From {}", cask.base.tap.yellow())?;
+    writeln!(buf)?;
+
+    if let Some(installed) = installed {
+        let versions: Vec<_> = installed.versions.iter().cloned().collect();
+        let versions = versions.join(", ");
+
+        writeln!(buf, "Installed {versions} {}", pretty::bool(true))?;
+
+        if let Some(outdated) = outdated::cask(installed) {
+            writeln!(buf, "Outdated, {} is available", outdated.latest.green())?;
+
+            if let Some(app) = outdated.locally_newer {
+                writeln!(
+                    buf,
+                    "Locally newer, the app updated itself to {}",
+                    app.blue()
+                )?;
+            }
+        }
+
+        writeln!(buf)?;
+    }
+
+    if let Some(homepage) = &cask.base.homepage {
+        writeln!(buf, "{}", homepage.underline().blue())?;
+
+        if let Some(repo) = &cask.github {
+            github(&mut *buf, repo)?;
+        }
+
+        writeln!(buf)?;
+    }
+
+    let desc = if let Some(desc) = &cask.base.desc {
+        desc
+    } else {
+        "No description"
+    };
+
+    writeln!(buf, "{}", desc.italic())?;
+
+    if let Some(note) = &cask.note {
+        writeln!(buf)?;
+        writeln!(buf, "Note: {}", note.yellow())?;
+    }
+
+    if let Some(size) = cask.base.download_size {
+        writeln!(buf)?;
+        writeln!(buf, "Download {}", pretty::bytes(size))?;
+    }
+
+    if let Some(analytics) = &cask.analytics {
+        writeln!(buf)?;
+        installs(&mut *buf, analytics.count, analytics.number)?;
+    }
+
+    Ok(())
+}
+
+fn installs(w: &mut impl Write, count: u64, rank: i64) -> anyhow::Result<()> {
+    writeln!(
+        w,
+        "Installs {} in 30 days (#{rank})",
+        pretty::count(count).bold()
+    )?;
+
+    Ok(())
+}
+
+pub mod search {
+    use std::borrow::Cow;
+    use std::io::{BufWriter, IsTerminal, Write};
+    use std::time::Duration;
+
+    use clap::Args;
+    use colored::Colorize;
+    use nucleo_matcher::pattern::{Atom, AtomKind, CaseMatching, Normalization};
+    use nucleo_matcher::Utf32Str;
+    use skim::{ItemPreview, PreviewContext, SkimItem};
+    use terminal_size::{terminal_size, Width};
+
+    use brewer_core::models;
+    use brewer_engine::search::{Entry, Index, Kind, Snippet};
+    use brewer_engine::{Engine, State};
+
+    use crate::cli::{info_cask, info_formula, not_found, select_skim};
+    use crate::pretty;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Search {
+        pub name: Option<String>,
+
+        /// Search casks only
+        #[clap(long, action, group = "type")]
+        pub casks_only: bool,
+
+        /// Search formulae only
+        #[clap(long, action, group = "type")]
+        pub formulae_only: bool,
+
+        /// Only show formulae and casks with the given tag
+        #[clap(long, short)]
+        pub tag: Option<String>,
+
+        /// Only show installed formulae and casks
+        #[clap(long, action, group = "installation")]
+        pub installed: bool,
+
+        /// Only show formulae and casks which are not installed
+        #[clap(long, action, group = "installation")]
+        pub not_installed: bool,
+
+        /// Only show deprecated or disabled formulae and casks
+        #[clap(long, action)]
+        pub deprecated: bool,
+
+        /// Only show formulae and casks from the given tap, e.g. homebrew/core
+        #[clap(long)]
+        pub tap: Option<String>,
+
+        /// Print tap-qualified names, e.g. homebrew/core/wget
+        #[clap(long, action)]
+        pub full_names: bool,
+
+        /// Match descriptions and caveats as well as names
+        #[clap(long, action, requires = "name")]
+        pub desc: bool,
+    }
+
+    /// Formula or cask found by a description search
+    struct Described {
+        name: String,
+        installed: bool,
+        installs: u64,
+        named: bool,
+        snippet: Option<Snippet>,
+    }
+
+    impl Search {
+        pub fn run(&self, engine: &mut Engine) -> anyhow::Result<bool> {
+            match &self.name {
+                Some(name) if !self.scoped() => self.run_indexed(engine, name),
+                _ => {
+                    let state = crate::load_state(engine)?;
+
+                    self.run_state(state, engine.cache_age()?)
+                }
+            }
+        }
+
+        /// Matches against the persisted index, loading the state only to render the table
+        fn run_indexed(&self, engine: &mut Engine, name: &str) -> anyhow::Result<bool> {
+            let index = engine.search_index()?;
+            let ranked = self.rank(&index, name);
+
+            if ranked.is_empty() {
+                not_found(
+                    &format!("Nothing matches {name}"),
+                    name,
+                    index.entries.iter().map(|e| e.name.as_str()),
+                    engine.cache_age()?,
+                )?;
+
+                return Ok(false);
+            }
+
+            if !std::io::stdout().is_terminal() {
+                for entry in ranked {
+                    if self.full_names {
+                        println!("{}", entry.full_name);
+                    } else {
+                        println!("{}", entry.name);
+                    }
+                }
+
+                return Ok(true);
+            }
+
+            let state = crate::load_state(engine)?;
+
+            let kegs = ranked
+                .into_iter()
+                .filter_map(|entry| match entry.kind {
+                    Kind::Formula => state.formulae.all.get(&entry.name).map(|f| {
+                        let installed = state.formulae.installed.get(&entry.name).cloned();
+
+                        Keg::Formula(f.clone(), Box::new(installed))
+                    }),
+                    Kind::Cask => state.casks.all.get(&entry.name).map(|c| {
+                        let installed = state.casks.installed.get(&entry.name).cloned();
+
+                        Keg::Cask(c.clone(), installed)
+                    }),
+                })
+                .collect();
+
+            self.print(kegs)
+        }
+
+        /// Entries matching by name or alias first, then by keyword or description,
+        /// equally good matches ranked by popularity, then by name
+        fn rank<'a>(&self, index: &'a Index, query: &str) -> Vec<&'a Entry> {
+            let mut matcher = nucleo_matcher::Matcher::new(nucleo_matcher::Config::DEFAULT);
+
+            let atom = Atom::new(
+                query,
+                CaseMatching::Ignore,
+                Normalization::Smart,
+                AtomKind::Substring,
+                false,
+            );
+
+            let lowercase = query.to_lowercase();
+            let mut buf = Vec::new();
+
+            let mut matches: Vec<(&Entry, (u16, u64))> = index
+                .entries
+                .iter()
+                .filter(|e| match e.kind {
+                    Kind::Formula => !self.casks_only,
+                    Kind::Cask => !self.formulae_only,
+                })
+                .filter_map(|e| {
+                    let score = std::iter::once(&e.name)
+                        .chain(&e.aliases)
+                        .filter_map(|s| atom.score(Utf32Str::new(s, &mut buf), &mut matcher))
+                        .max()
+                        // name matches always outrank description ones
+                        .map(|score| score.saturating_add(1))
+                        .or_else(|| {
+                            let described = e.tokens.contains(&lowercase) || e.desc_matches(query);
+
+                            described.then_some(0)
+                        })?;
+
+                    Some((e, (score, e.installs)))
+                })
+                .collect();
+
+            matches.sort_by(|(a, a_rank), (b, b_rank)| {
+                b_rank.cmp(a_rank).then_with(|| a.name.cmp(&b.name))
+            });
+
+            matches.into_iter().map(|(e, _)| e).collect()
+        }
+
+        /// Whether the search needs the whole state, the index only knows names and descriptions
+        fn scoped(&self) -> bool {
+            self.desc
+                || self.tag.is_some()
+                || self.installed
+                || self.not_installed
+                || self.deprecated
+                || self.tap.is_some()
+        }
+
+        /// Whether a formula or cask passes the installation, deprecation and tap filters
+        fn keeps(&self, tap: &str, deprecated: bool, installed: bool) -> bool {
+            (!self.installed || installed)
+                && (!self.not_installed || !installed)
+                && (!self.deprecated || deprecated)
+                && self
+                    .tap
+                    .as_ref()
+                    .is_none_or(|filter| brewer_engine::tap_matches(tap, filter))
+        }
+
+        fn run_state(&self, mut state: State, cache_age: Option<Duration>) -> anyhow::Result<bool> {
+            if let Some(tag) = &self.tag {
+                let tag = tag.to_lowercase();
+
+                state.formulae.all.retain(|_, f| f.tags.contains(&tag));
+                state.casks.all.retain(|_, c| c.tags.contains(&tag));
+            }
+
+            state.formulae.all.retain(|_, f| {
+                self.keeps(
+                    &f.base.tap,
+                    f.base.deprecated || f.base.disabled,
+                    state.formulae.installed.contains_key(&f.base.name),
+                )
+            });
+
+            state.casks.all.retain(|_, c| {
+                self.keeps(
+                    &c.base.tap,
+                    c.base.deprecated || c.base.disabled,
+                    state.casks.installed.contains_key(&c.base.token),
+                )
+            });
+
+            if let (true, Some(name)) = (self.desc, &self.name) {
+                return self.run_desc(state, name, cache_age);
+            }
+
+            let names: Vec<String> = if self.name.is_some() {
+                let formulae = state.formulae.all.keys().filter(|_| !self.casks_only);
+                let casks = state.casks.all.keys().filter(|_| !self.formulae_only);
+
+                formulae.chain(casks).cloned().collect()
+            } else {
+                Vec::new()
+            };
+
+            let kegs = match &self.name {
+                Some(name) => {
+                    let mut matcher = nucleo_matcher::Matcher::new(nucleo_matcher::Config::DEFAULT);
+
+                    let atom = Atom::new(
+                        name,
+                        CaseMatching::Ignore,
+                        Normalization::Smart,
+                        AtomKind::Substring,
+                        false,
+                    );
+
+                    let mut formulae: Vec<_> = if self.casks_only {
+                        Vec::new()
+                    } else {
+                        let mut matches =
+                            atom.match_list(state.formulae.all.into_values(), &mut matcher);
+
+                        // equally good matches are ranked by popularity, then by name
+                        matches.sort_by(|(a, a_score), (b, b_score)| {
+                            (b_score, b.installs())
+                                .cmp(&(a_score, a.installs()))
+                                .then_with(|| a.base.name.cmp(&b.base.name))
+                        });
+
+                        matches
+                            .into_iter()
+                            .map(|(formula, _)| {
+                                let installed = state.formulae.installed.get(&formula.base.name);
+
+                                Keg::Formula(formula, Box::new(installed.cloned()))
+                            })
+                            .collect()
+                    };
+
+                    let mut casks: Vec<_> = if self.formulae_only {
+                        Vec::new()
+                    } else {
+                        let mut matches =
+                            atom.match_list(state.casks.all.into_values(), &mut matcher);
+
+                        matches.sort_by(|(a, a_score), (b, b_score)| {
+                            (b_score, b.installs())
+                                .cmp(&(a_score, a.installs()))
+                                .then_with(|| a.base.token.cmp(&b.base.token))
+                        });
+
+                        matches
+                            .into_iter()
+                            .map(|(cask, _)| {
+                                let installed = state.casks.installed.get(&cask.base.token);
+
+                                Keg::Cask(cask, installed.cloned())
+                            })
+                            .collect()
+                    };
+
+                    formulae.append(&mut casks);
+
+                    formulae
+                }
+                None => self.run_skim(state)?,
+            };
+
+            if kegs.is_empty() {
+                if let Some(name) = &self.name {
+                    not_found(
+                        &format!("Nothing matches {name}"),
+                        name,
+                        names.iter().map(|n| n.as_str()),
+                        cache_age,
+                    )?;
+                }
+
+                return Ok(false);
+            }
+
+            self.print(kegs)
+        }
+
+        /// Matches names, descriptions and caveats, showing where the query was found
+        fn run_desc(
+            &self,
+            state: State,
+            query: &str,
+            cache_age: Option<Duration>,
+        ) -> anyhow::Result<bool> {
+            let formulae: Vec<_> = if self.casks_only {
+                Vec::new()
+            } else {
+                let found = state.formulae.all.values().filter_map(|f| {
+                    let name = if self.full_names {
+                        f.base.tap_qualified_name()
+                    } else {
+                        f.base.name.clone()
+                    };
+
+                    Self::describe(
+                        query,
+                        name,
+                        [&f.base.desc, &f.base.caveats],
+                        f.installs(),
+                        state.formulae.installed.contains_key(&f.base.name),
+                    )
+                });
+
+                Self::ranked(found)
+            };
+
+            let casks: Vec<_> = if self.formulae_only {
+                Vec::new()
+            } else {
+                let found = state.casks.all.values().filter_map(|c| {
+                    let name = if self.full_names {
+                        c.base.tap_qualified_token()
+                    } else {
+                        c.base.token.clone()
+                    };
+
+                    Self::describe(
+                        query,
+                        name,
+                        [&c.base.desc, &c.base.caveats],
+                        c.installs(),
+                        state.casks.installed.contains_key(&c.base.token),
+                    )
+                });
+
+                Self::ranked(found)
+            };
+
+            if formulae.is_empty() && casks.is_empty() {
+                let names = state.formulae.all.keys().chain(state.casks.all.keys());
+
+                not_found(
+                    &format!("Nothing matches {query}"),
+                    query,
+                    names.map(|n| n.as_str()),
+                    cache_age,
+                )?;
+
+                return Ok(false);
+            }
+
+            let mut buf = BufWriter::new(std::io::stdout());
+
+            if !std::io::stdout().is_terminal() {
+                for found in formulae.iter().chain(&casks) {
+                    writeln!(buf, "{}", found.name)?;
+                }
+
+                buf.flush()?;
+
+                return Ok(true);
+            }
+
+            let width = formulae
+                .iter()
+                .chain(&casks)
+                .map(|f| f.name.chars().count())
+                .max()
+                .unwrap_or_default();
+
+            let sections = [("Formulae", &formulae), ("Casks", &casks)];
+
+            for (i, (title, found)) in sections
+                .into_iter()
+                .filter(|(_, f)| !f.is_empty())
+                .enumerate()
+            {
+                if i > 0 {
+                    writeln!(buf)?;
+                }
+
+                writeln!(buf, "{}", header::primary!("{title}"))?;
+
+                for found in found {
+                    let mark = if found.installed {
+                        pretty::bool(true)
+                    } else {
+                        " ".to_string()
+                    };
+
+                    let snippet = match &found.snippet {
+                        Some(s) => format!("{}{}{}", s.before, s.matched.bold(), s.after),
+                        None => String::new(),
+                    };
+
+                    writeln!(
+                        buf,
+                        "{} {} {}",
+                        format!("{:<width$}", found.name).cyan(),
+                        mark,
+                        snippet
+                    )?;
+                }
+            }
+
+            buf.flush()?;
+
+            Ok(true)
+        }
+
+        /// Name and description or caveats match, if any
+        fn describe(
+            query: &str,
+            name: String,
+            texts: [&Option<String>; 2],
+            installs: u64,
+            installed: bool,
+        ) -> Option<Described> {
+            const RADIUS: usize = 30;
+
+            let named = name.to_lowercase().contains(&query.to_lowercase());
+
+            let snippet = texts
+                .into_iter()
+                .flatten()
+                .find_map(|text| brewer_engine::search::snippet(text, query, RADIUS));
+
+            if !named && snippet.is_none() {
+                return None;
+            }
+
+            Some(Described {
+                name,
+                installed,
+                installs,
+                named,
+                snippet,
+            })
+        }
+
+        /// Name matches first, then description ones, each ranked by popularity, then by name
+        fn ranked(found: impl Iterator<Item = Described>) -> Vec<Described> {
+            let mut found: Vec<_> = found.collect();
+
+            found.sort_by(|a, b| {
+                (b.named, b.installs)
+                    .cmp(&(a.named, a.installs))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+
+            found
+        }
+
+        fn print(&self, kegs: Vec<Keg>) -> anyhow::Result<bool> {
+            if !std::io::stdout().is_terminal() {
+                for keg in kegs {
+                    match keg {
+                        Keg::Formula(formula, _) if self.full_names => {
+                            println!("{}", formula.base.tap_qualified_name())
+                        }
+                        Keg::Formula(formula, _) => println!("{}", formula.base.name),
+                        Keg::Cask(cask, _) if self.full_names => {
+                            println!("{}", cask.base.tap_qualified_token())
+                        }
+                        Keg::Cask(cask, _) => println!("{}", cask.base.token),
+                    };
+                }
+
+                return Ok(true);
+            }
+
+            let width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+
+            let mut formulae = Vec::new();
+            let mut casks = Vec::new();
+
+            for keg in kegs {
+                match keg {
+                    Keg::Formula(formula, installed) => {
+                        let mut name = if self.full_names {
+                            formula.base.tap_qualified_name()
+                        } else {
+                            formula.base.name.clone()
+                        };
+
+                        if installed.is_some() {
+                            name = format!("{name} {}", pretty::bool(true));
+                        }
+
+                        if formula.starred {
+                            name = format!("{name} {}", pretty::star());
+                        }
+
+                        formulae.push(name)
+                    }
+                    Keg::Cask(cask, installed) => {
+                        let mut name = if self.full_names {
+                            cask.base.tap_qualified_token()
+                        } else {
+                            cask.base.token.clone()
+                        };
+
+                        if installed.is_some() {
+                            name = format!("{name} {}", pretty::bool(true));
+                        }
+
+                        if cask.starred {
+                            name = format!("{name} {}", pretty::star());
+                        }
+
+                        casks.push(name)
+                    }
+                }
+            }
+
+            let formulae = pretty::table(&formulae, width);
+            let casks = pretty::table(&casks, width);
+
+            let mut buf = BufWriter::new(std::io::stdout());
+
+            if !self.casks_only {
+                writeln!(buf, "{}", header::primary!("Formulae"))?;
+                formulae.print(&mut buf)?;
+            }
+
+            if !self.casks_only && !self.formulae_only {
+                writeln!(buf)?;
+            }
+
+            if !self.formulae_only {
+                writeln!(buf, "{}", header::primary!("Casks"))?;
+                casks.print(&mut buf)?;
+            }
+
+            Ok(true)
+        }
+
+        fn run_skim(&self, state: State) -> anyhow::Result<Vec<Keg>> {
+            let mut kegs: Vec<Keg> = Vec::new();
+
+            if !self.casks_only {
+                for formula in state.formulae.all.into_values() {
+                    let name = formula.base.name.clone();
+                    let keg = Keg::Formula(
+                        formula,
+                        Box::new(state.formulae.installed.get(&name).cloned()),
+                    );
+
+                    kegs.push(keg);
+                }
+            }
+
+            if !self.formulae_only {
+                for cask in state.casks.all.into_values() {
+                    let token = cask.base.token.clone();
+                    let keg = Keg::Cask(cask, state.casks.installed.get(&token).cloned());
+
+                    kegs.push(keg);
+                }
+            }
+
+            let selected = select_skim(kegs, "Search", true)?;
+
+            Ok(selected)
+        }
+    }
+
+    #[derive(Clone)]
+    enum Keg {
+        Formula(
+            models::formula::Formula,
+            Box<Option<models::formula::installed::Formula>>,
+        ),
+        Cask(models::cask::Cask, Option<models::cask::installed::Cask>),
+    }
+
+    impl SkimItem for Keg {
+        fn text(&self) -> Cow<str> {
+            match self {
+                Keg::Formula(formula, _) => Cow::Borrowed(&formula.base.name),
+                Keg::Cask(cask, _) => Cow::Borrowed(&cask.base.token),
+            }
+        }
+
+        fn preview(&self, _context: PreviewContext) -> ItemPreview {
+            let mut w = Vec::new();
+
+            match self {
+                Keg::Formula(formula, installed) => {
+                    info_formula(&mut w, formula, installed.as_ref().as_ref()).unwrap()
+                }
+                Keg::Cask(cask, installed) => info_cask(&mut w, cask, installed.as_ref()).unwrap(),
+            };
+
+            let preview = String::from_utf8(w).unwrap();
+            let preview = textwrap::wrap(&preview, _context.width).join("\n");
+
+            ItemPreview::AnsiText(preview)
+        }
+    }
+}
+
+pub mod paths {
+    use clap::{Parser, Subcommand};
+
+    use crate::context::Context;
+
+    #[derive(Parser)]
+    pub struct Paths {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Show config path
+        Config,
+
+        /// Show where brew stores downloads
+        Cache,
+
+        /// Show where brew stores temporary files
+        Temp,
+    }
+
+    impl Paths {
+        pub fn run(&self, ctx: &mut Context) -> anyhow::Result<()> {
+            match self.command {
+                Commands::Config => println!("{}", ctx.config_path().to_string_lossy()),
+                Commands::Cache => println!("{}", ctx.brew()?.cache_dir()?.to_string_lossy()),
+                Commands::Temp => println!("{}", ctx.brew()?.temp_dir().to_string_lossy()),
+            }
+
+            Ok(())
+        }
+    }
+}
+
+pub mod config {
+    use std::path::{Path, PathBuf};
+
+    use clap::{Parser, Subcommand, ValueHint};
+
+    use crate::context::Context;
+    use crate::error::NotFound;
+    use crate::pretty::header;
+    use crate::settings::{Settings, TEMPLATE};
+
+    #[derive(Parser)]
+    pub struct Config {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Print effective settings, merged from the config file and environment variables
+        Show,
+
+        /// Open the config file in $VISUAL or $EDITOR, creating it from a template if missing
+        Edit,
+
+        /// Check a config file, reporting unknown keys and invalid values
+        Validate {
+            /// Config file to check, the one in use by default
+            #[clap(value_hint = ValueHint::FilePath)]
+            path: Option<PathBuf>,
+        },
+    }
+
+    impl Config {
+        pub fn run(&self, ctx: &mut Context, json: bool) -> anyhow::Result<bool> {
+            match &self.command {
+                Commands::Show => {
+                    let settings = ctx.settings()?;
+
+                    if json {
+                        println!("{}", serde_json::to_string_pretty(&settings.to_json()?)?);
+                    } else {
+                        print!("{}", settings.to_toml()?);
+                    }
+
+                    Ok(true)
+                }
+                Commands::Edit => edit(ctx).map(|_| true),
+                Commands::Validate { path } => {
+                    let path = path.clone().unwrap_or_else(|| ctx.config_path());
+
+                    validate(&path, json)
+                }
+            }
+        }
+    }
+
+    fn validate(path: &Path, json: bool) -> anyhow::Result<bool> {
+        if !path.is_file() {
+            return Err(NotFound(format!("{} does not exist", path.display())).into());
+        }
+
+        let problems = Settings::validate(path, true)?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&problems)?);
+        } else if problems.is_empty() {
+            println!("{} is valid", path.display());
+        } else {
+            for problem in &problems {
+                eprintln!("{}", header::warning!("{}", problem.located(path)));
+            }
+        }
+
+        Ok(problems.is_empty())
+    }
+
+    fn edit(ctx: &Context) -> anyhow::Result<()> {
+        let path = ctx.config_path();
+
+        if !path.exists() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+
+            std::fs::write(&path, TEMPLATE)?;
+        }
+
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".into());
+
+        // editors are often configured with arguments, e.g. "code --wait"
+        let mut args = editor.split_whitespace();
+        let program = args.next().unwrap_or("vi");
+
+        let status = std::process::Command::new(program)
+            .args(args)
+            .arg(&path)
+            .status()?;
+
+        if !status.success() {
+            return Err(anyhow::anyhow!("{editor} exited with {status}"));
+        }
+
+        match Settings::validate(&path, true) {
+            Ok(problems) => {
+                for problem in problems {
+                    eprintln!("{}", header::warning!("{}", problem.located(&path)));
+                }
+            }
+            Err(e) => eprintln!("{}", header::warning!("Settings are invalid: {e}")),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct Exists {
+    pub name: String,
+
+    /// Treat given name as formula
+    #[clap(short, long, action)]
+    pub formula: bool,
+
+    /// Treat given name as cask
+    #[clap(short, long, action)]
+    pub cask: bool,
+}
+
+impl Exists {
+    pub fn run(&self, state: State) -> bool {
+        let formulae = state.formulae.all;
+        let casks = state.casks.all;
+
+        if self.cask {
+            return casks.contains_key(&self.name);
+        }
+
+        if self.formula {
+            return formulae.contains_key(&self.name);
+        }
+
+        formulae.contains_key(&self.name) || casks.contains_key(&self.name)
+    }
+}
+
+pub mod install {
+    use std::borrow::Cow;
+    use std::io::{BufWriter, IsTerminal, Write};
+    use std::ops::Deref;
+
+    use clap::Args;
+    use colored::Colorize;
+    use inquire::{Confirm, Select};
+    use skim::{ItemPreview, PreviewContext, SkimItem};
+
+    use brewer_core::models;
+    use brewer_engine::{Engine, Operation, Resolution, State};
+
+    use crate::cli::{
+        ambiguous, info_cask, info_formula, select_skim, summary, timed, wait_for_brew,
+    };
+    use crate::error::Cancelled;
+    use crate::pretty;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Install {
+        pub names: Vec<String>,
+
+        #[clap(short, long, action, group = "type")]
+        pub formula: bool,
+
+        #[clap(short, long, action, group = "type")]
+        pub cask: bool,
+
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+
+        /// Show installed kegs changes after completion
+        #[clap(short, long, action)]
+        pub summary: bool,
+    }
+
+    impl Install {
+        /// Installs the kegs, false if some of the given names are unknown
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let (kegs, found) = self.get_kegs(state, true)?;
+
+            if kegs.is_empty() {
+                return Ok(found);
+            }
+
+            if !self.yes && !plan(&kegs, "installed")? {
+                return Err(Cancelled.into());
+            }
+
+            wait_for_brew(&engine)?;
+
+            let delta = timed(&mut engine, Operation::Install, |engine| {
+                engine.install(kegs)
+            })?;
+
+            for drift in &delta.drift {
+                eprintln!(
+                    "{}",
+                    header::warning!(
+                        "Planned {} {}, but {} was installed",
+                        drift.name,
+                        drift.planned,
+                        drift.installed
+                    )
+                );
+            }
+
+            if self.summary {
+                summary(&delta)?;
+            }
+
+            Ok(found)
+        }
+
+        /// Kegs to install and whether every given name resolved to a formula or cask.
+        /// Unless interactive, names are required and a name that is both a formula
+        /// and a cask is an error
+        pub fn get_kegs(
+            &self,
+            state: State,
+            interactive: bool,
+        ) -> anyhow::Result<(Vec<models::Keg>, bool)> {
+            if !self.names.is_empty() {
+                self.get_kegs_from_args(state, interactive)
+            } else if interactive {
+                Ok((self.get_kegs_from_skim(state)?, true))
+            } else {
+                anyhow::bail!("no formulae or casks given")
+            }
+        }
+
+        fn get_kegs_from_args(
+            &self,
+            mut state: State,
+            interactive: bool,
+        ) -> anyhow::Result<(Vec<models::Keg>, bool)> {
+            let mut kegs = Vec::new();
+            let mut found = true;
+
+            for name in &self.names {
+                let Some(keg) = self.resolve(&mut state, name, interactive)? else {
+                    found = false;
+                    continue;
+                };
+
+                let installed = match &keg {
+                    models::Keg::Formula(f) => state.formulae.installed.contains_key(&f.base.name),
+                    models::Keg::Cask(c) => state.casks.installed.contains_key(&c.base.token),
+                };
+
+                if installed {
+                    let kind = match &keg {
+                        models::Keg::Formula(_) => "Formula",
+                        models::Keg::Cask(_) => "Cask",
+                    };
+
+                    eprintln!(
+                        "{}",
+                        header::warning!("{kind} {name} is already installed, skipping")
+                    );
+                    continue;
+                }
+
+                kegs.push(keg);
+            }
+
+            Ok((kegs, found))
+        }
+
+        /// Takes the formula or cask with the given name out of the state,
+        /// asking which one is meant when both exist unless the type is specified
+        fn resolve(
+            &self,
+            state: &mut State,
+            name: &str,
+            interactive: bool,
+        ) -> anyhow::Result<Option<models::Keg>> {
+            let formula = if self.cask {
+                Resolution::Missing
+            } else {
+                brewer_engine::resolve_formula(state, name)
+            };
+
+            let cask = if self.formula {
+                Resolution::Missing
+            } else {
+                brewer_engine::resolve_cask(state, name)
+            };
+
+            let keg = match (formula, cask) {
+                (Resolution::Found(formula), Resolution::Found(cask)) => {
+                    match pick(state, name, &formula, &cask, interactive)? {
+                        Kind::Formula => state
+                            .formulae
+                            .all
+                            .remove(&formula)
+                            .map(models::Keg::Formula),
+                        Kind::Cask => state.casks.all.remove(&cask).map(models::Keg::Cask),
+                    }
+                }
+                (Resolution::Found(key), _) => {
+                    state.formulae.all.remove(&key).map(models::Keg::Formula)
+                }
+                (Resolution::Ambiguous(candidates), _) => {
+                    ambiguous(name, &candidates);
+                    None
+                }
+                (_, Resolution::Found(key)) => state.casks.all.remove(&key).map(models::Keg::Cask),
+                (_, Resolution::Ambiguous(candidates)) => {
+                    ambiguous(name, &candidates);
+                    None
+                }
+                (Resolution::Missing, Resolution::Missing) => {
+                    eprintln!(
+                        "{}",
+                        header::warning!("Unknown formula or cask {name}, skipping")
+                    );
+                    None
+                }
+            };
+
+            Ok(keg)
+        }
+
+        fn get_kegs_from_skim(&self, state: State) -> anyhow::Result<Vec<models::Keg>> {
+            let mut non_installed: Vec<Keg> =
+                Vec::with_capacity(state.formulae.all.len() + state.casks.all.len());
+
+            for formula in state.formulae.all.into_values() {
+                if !state.formulae.installed.contains_key(&formula.base.name) {
+                    non_installed.push(formula.into());
+                }
+            }
+
+            for cask in state.casks.all.into_values() {
+                if !state.casks.installed.contains_key(&cask.base.token) {
+                    non_installed.push(cask.into());
+                }
+            }
+
+            let selected = select_skim(non_installed, "Install", true)?
+                .into_iter()
+                .map(|k| k.0)
+                .collect();
+
+            Ok(selected)
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Kind {
+        Formula,
+        Cask,
+    }
+
+    /// Shows the formula and the cask sharing the name side by side and asks which one is meant.
+    /// Without a terminal to ask in, or when not interactive, the user has to tell
+    /// with --formula or --cask
+    fn pick(
+        state: &State,
+        name: &str,
+        formula: &str,
+        cask: &str,
+        interactive: bool,
+    ) -> anyhow::Result<Kind> {
+        const LABEL: usize = 12;
+        const COLUMN: usize = 36;
+
+        if !interactive || !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            anyhow::bail!(
+                "{name} is both a formula and a cask, pass --formula or --cask to choose"
+            );
+        }
+
+        let formula = &state.formulae.all[formula];
+        let cask = &state.casks.all[cask];
+
+        let rows = [
+            (
+                "Version",
+                formula.base.versions.stable.clone(),
+                cask.base.version.clone(),
+            ),
+            ("Tap", formula.base.tap.clone(), cask.base.tap.clone()),
+            (
+                "Installs",
+                pretty::count(formula.installs()),
+                pretty::count(cask.installs()),
+            ),
+            (
+                "Description",
+                formula.base.desc.clone().unwrap_or_default(),
+                cask.base.desc.clone().unwrap_or_default(),
+            ),
+        ];
+
+        let fit = |s: &str| -> String {
+            if s.chars().count() > COLUMN {
+                let mut s: String = s.chars().take(COLUMN - 1).collect();
+                s.push('…');
+                s
+            } else {
+                format!("{s:<COLUMN$}")
+            }
+        };
+
+        let mut w = BufWriter::new(std::io::stderr());
+
+        writeln!(
+            w,
+            "{}",
+            header::primary!("{name} is both a formula and a cask")
+        )?;
+
+        writeln!(
+            w,
+            "{:LABEL$}{}  {}",
+            "",
+            fit("Formula").bold(),
+            "Cask".bold()
+        )?;
+
+        for (label, formula, cask) in rows {
+            writeln!(
+                w,
+                "{}{}  {cask}",
+                format!("{label:<LABEL$}").dimmed(),
+                fit(&formula)
+            )?;
+        }
+
+        writeln!(w)?;
+        w.flush()?;
+
+        let options = vec!["Formula", "Cask"];
+
+        match Select::new("Install which one?", options).prompt()? {
+            "Formula" => Ok(Kind::Formula),
+            _ => Ok(Kind::Cask),
+        }
+    }
+
+    /// Prints what is going to be done and asks for confirmation, false if the user declined.
+    /// `action` completes "The following kegs will be ...", e.g. "installed"
+    pub fn plan(kegs: &Vec<models::Keg>, action: &str) -> anyhow::Result<bool> {
+        let mut w = BufWriter::new(std::io::stderr());
+
+        writeln!(
+            w,
+            "{}",
+            header::primary!("The following kegs will be {action}")
+        )?;
+
+        let mut kegs: Vec<_> = kegs.iter().collect();
+        kegs.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+
+        for keg in &kegs {
+            match &keg {
+                models::Keg::Formula(f) => writeln!(
+                    w,
+                    "{} {} (Formula)",
+                    f.base.name.cyan(),
+                    f.base.versions.stable
+                )?,
+                models::Keg::Cask(c) => {
+                    writeln!(w, "{} {} (Cask)", c.base.token.cyan(), c.base.version)?
+                }
+            }
+        }
+
+        writeln!(w)?;
+
+        let unmet: Vec<_> = kegs
+            .iter()
+            .filter_map(|k| match k {
+                models::Keg::Formula(f) => Some(f),
+                models::Keg::Cask(_) => None,
+            })
+            .flat_map(|f| {
+                brewer_core::requirements::unmet(&f.base)
+                    .into_iter()
+                    .map(|reason| (f.base.name.as_str(), reason))
+            })
+            .collect();
+
+        if !unmet.is_empty() {
+            writeln!(
+                w,
+                "{}",
+                header::warning!("Requirements are not met, brew will likely fail")
+            )?;
+
+            for (name, reason) in unmet {
+                writeln!(w, "{} {reason}", name.cyan())?;
+            }
+
+            writeln!(w)?;
+        }
+
+        let mut executables: Vec<String> = Vec::new();
+
+        for k in kegs {
+            if let models::Keg::Formula(f) = &k {
+                for e in &f.executables {
+                    executables.push(e.purple().to_string());
+                }
+            }
+        }
+
+        if !executables.is_empty() {
+            writeln!(
+                w,
+                "{}",
+                header::primary!("The following executables will be provided")
+            )?;
+            writeln!(w, "{}", executables.join(" "))?;
+            writeln!(w)?;
+        }
+
+        let mut download: u64 = 0;
+        let mut installed: u64 = 0;
+
+        for k in kegs {
+            match &k {
+                models::Keg::Formula(f) => {
+                    download += f.base.download_size().unwrap_or_default();
+                    installed += f.base.installed_size().unwrap_or_default();
+                }
+                models::Keg::Cask(c) => download += c.base.download_size.unwrap_or_default(),
+            }
+        }
+
+        if download > 0 {
+            write!(w, "Need to get ~{}", pretty::bytes(download))?;
+
+            if installed > 0 {
+                write!(w, ", ~{} will be used", pretty::bytes(installed))?;
+            }
+
+            writeln!(w)?;
+            writeln!(w)?;
+        }
+
+        w.flush()?;
+
+        Ok(Confirm::new("Proceed?").with_default(false).prompt()?)
+    }
+
+    #[derive(Clone)]
+    pub struct Keg(pub models::Keg);
+
+    impl From<models::formula::Formula> for Keg {
+        fn from(value: models::formula::Formula) -> Self {
+            Keg(value.into())
+        }
+    }
+
+    impl From<models::cask::Cask> for Keg {
+        fn from(value: models::cask::Cask) -> Self {
+            Keg(value.into())
+        }
+    }
+
+    impl Deref for Keg {
+        type Target = models::Keg;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl SkimItem for Keg {
+        fn text(&self) -> Cow<str> {
+            match &self.0 {
+                models::Keg::Formula(formula) => Cow::Borrowed(&formula.base.name),
+                models::Keg::Cask(cask) => Cow::Borrowed(&cask.base.token),
+            }
+        }
+
+        fn preview(&self, _context: PreviewContext) -> ItemPreview {
+            let mut buf = Vec::new();
+
+            match &self.0 {
+                models::Keg::Formula(formula) => info_formula(&mut buf, formula, None).unwrap(),
+                models::Keg::Cask(cask) => info_cask(&mut buf, cask, None).unwrap(),
+            };
+
+            let preview = String::from_utf8(buf).unwrap();
+
+            ItemPreview::AnsiText(preview)
+        }
+    }
+}
+
+pub mod upgrade {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, ValueEnum};
+    use colored::{ColoredString, Colorize};
+    use skim::SkimItem;
+
+    use brewer_core::models;
+    use brewer_core::models::version::Bump;
+    use brewer_engine::outdated::{self, Skip, Skipped};
+    use brewer_engine::{Engine, State};
+
+    use crate::cli::install::plan;
+    use crate::cli::uninstall::Keg;
+    use crate::cli::{select_skim, wait_for_brew};
+    use crate::error::Cancelled;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Upgrade {
+        pub names: Vec<String>,
+
+        #[clap(short, long, action, group = "type")]
+        pub formula: bool,
+
+        #[clap(short, long, action, group = "type")]
+        pub cask: bool,
+
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+
+        /// Include casks which apps updated themselves past the installed version
+        #[clap(long, action)]
+        pub locally_newer: bool,
+
+        /// Only upgrade kegs from the given taps, e.g. homebrew/core
+        #[clap(long = "tap", value_name = "TAP")]
+        pub taps: Vec<String>,
+
+        /// Only apply upgrades of the given sizes, e.g. patch,minor
+        #[clap(long, value_enum, value_delimiter = ',', value_name = "BUMP")]
+        pub only: Vec<Risk>,
+    }
+
+    /// Size of an upgrade judged by the version change
+    #[derive(Clone, Copy, PartialEq, ValueEnum)]
+    pub enum Risk {
+        /// Only the last version component changes, e.g. 1.2.3 -> 1.2.4
+        Patch,
+
+        /// The middle component changes, e.g. 1.2.3 -> 1.3.0
+        Minor,
+
+        /// The leading component changes, e.g. 1.2.3 -> 2.0.0, or 0.1 -> 0.2
+        Major,
+    }
+
+    impl From<Risk> for Bump {
+        fn from(value: Risk) -> Self {
+            match value {
+                Risk::Patch => Bump::Patch,
+                Risk::Minor => Bump::Minor,
+                Risk::Major => Bump::Major,
+            }
+        }
+    }
+
+    impl Upgrade {
+        /// Upgrades the kegs, false if some of the given names are not installed
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+            let (upgrades, found) = self.upgrades(&state, true)?;
+
+            if upgrades.is_empty() {
+                return Ok(found);
+            }
+
+            let kegs: Vec<_> = upgrades.iter().map(|u| u.keg.clone()).collect();
+
+            release_notes(&kegs)?;
+            version_bumps(&upgrades)?;
+
+            if !self.yes && !plan(&kegs, "upgraded")? {
+                return Err(Cancelled.into());
+            }
+
+            wait_for_brew(&engine)?;
+
+            engine.upgrade(kegs)?;
+
+            Ok(found)
+        }
+
+        /// Planned upgrades, picked interactively when no names are given, and whether every
+        /// given name is installed. Kegs left out are reported. Unless interactive, names are required
+        pub fn upgrades(
+            &self,
+            state: &State,
+            interactive: bool,
+        ) -> anyhow::Result<(Vec<outdated::Upgrade>, bool)> {
+            if self.names.is_empty() && !interactive {
+                anyhow::bail!("no formulae or casks given");
+            }
+
+            let planned = outdated::plan(state, &self.options());
+
+            for skipped in &planned.skipped {
+                self.warn(skipped);
+            }
+
+            let found = !planned
+                .skipped
+                .iter()
+                .any(|s| matches!(s.reason, Skip::NotInstalled));
+
+            if self.names.is_empty() {
+                Ok((self.select(state, planned.upgrades)?, found))
+            } else {
+                Ok((planned.upgrades, found))
+            }
+        }
+
+        fn options(&self) -> outdated::Options {
+            outdated::Options {
+                names: self.names.clone(),
+                formulae_only: self.formula,
+                casks_only: self.cask,
+                locally_newer: self.locally_newer,
+                taps: self.taps.clone(),
+                bumps: self.only.iter().map(|&r| r.into()).collect(),
+            }
+        }
+
+        /// Explains why a keg is left out. Filters of a full upgrade are applied silently,
+        /// only sizes not given in --only are reported
+        fn warn(&self, skipped: &Skipped) {
+            let name = &skipped.name;
+
+            let message = match (skipped.reason, &skipped.outdated) {
+                (Skip::Bump(bump), Some(o)) => format!(
+                    "{name} {} -> {} is a {} upgrade, skipping",
+                    o.installed,
+                    o.latest,
+                    bump.name()
+                ),
+                _ if self.names.is_empty() => return,
+                (Skip::NotInstalled, _) => {
+                    format!("Formula or cask {name} is not installed, skipping")
+                }
+                (Skip::UpToDate, _) => format!("{name} is up to date, skipping"),
+                (Skip::Pinned, _) => format!("{name} is pinned, skipping"),
+                (Skip::LocallyNewer, _) => format!(
+                    "Cask {name} updated itself past the installed version, skipping. Use --locally-newer to upgrade anyway"
+                ),
+                (Skip::Tap, Some(o)) => format!("{name} is from {}, skipping", o.tap),
+                (Skip::Tap | Skip::Bump(_), None) => return,
+            };
+
+            eprintln!("{}", header::warning!("{message}"));
+        }
+
+        /// Lets the user pick among the planned upgrades
+        fn select(
+            &self,
+            state: &State,
+            upgrades: Vec<outdated::Upgrade>,
+        ) -> anyhow::Result<Vec<outdated::Upgrade>> {
+            let candidates: Vec<Keg> = upgrades
+                .iter()
+                .filter_map(|u| match u.keg {
+                    models::Keg::Formula(_) => state
+                        .formulae
+                        .installed
+                        .get(&u.outdated.name)
+                        .cloned()
+                        .map(Keg::Formula),
+                    models::Keg::Cask(_) => state
+                        .casks
+                        .installed
+                        .get(&u.outdated.name)
+                        .cloned()
+                        .map(Keg::Cask),
+                })
+                .collect();
+
+            let selected: Vec<String> = select_skim(candidates, "Upgrade", true)?
+                .into_iter()
+                .map(|k| k.text().to_string())
+                .collect();
+
+            Ok(upgrades
+                .into_iter()
+                .filter(|u| selected.contains(&u.outdated.name))
+                .collect())
+        }
+    }
+
+    /// Prints installed and upcoming versions colored by the size of the upgrade
+    fn version_bumps(upgrades: &[outdated::Upgrade]) -> anyhow::Result<()> {
+        let mut w = BufWriter::new(std::io::stderr());
+
+        writeln!(w, "{}", header::primary!("Version changes"))?;
+
+        for outdated::Upgrade { outdated: o, .. } in upgrades {
+            let bump = o.bump();
+
+            writeln!(
+                w,
+                "{} {} -> {} ({})",
+                o.name.cyan(),
+                o.installed,
+                paint(bump, &o.latest),
+                paint(bump, bump.name())
+            )?;
+        }
+
+        writeln!(w)?;
+        w.flush()?;
+
+        Ok(())
+    }
+
+    fn paint(bump: Bump, s: &str) -> ColoredString {
+        match bump {
+            Bump::Patch => s.green(),
+            Bump::Minor => s.yellow(),
+            Bump::Major => s.red().bold(),
+        }
+    }
+
+    /// Prints where to read about changes of each keg
+    fn release_notes(kegs: &[models::Keg]) -> anyhow::Result<()> {
+        let mut w = BufWriter::new(std::io::stderr());
+
+        writeln!(w, "{}", header::primary!("Release notes"))?;
+
+        for keg in kegs {
+            let (name, link) = match keg {
+                models::Keg::Formula(f) => (&f.base.name, f.base.release_notes()),
+                models::Keg::Cask(c) => (&c.base.token, c.base.release_notes()),
+            };
+
+            match link {
+                Some(link) => writeln!(w, "{} {}", name.cyan(), link.underline().blue())?,
+                None => writeln!(w, "{} {}", name.cyan(), "unknown".italic())?,
+            }
+        }
+
+        writeln!(w)?;
+        w.flush()?;
+
+        Ok(())
+    }
+}
+
+pub mod explain {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, Subcommand};
+
+    use brewer_core::explain::{Action, Invocation};
+    use brewer_engine::Engine;
+
+    use crate::cli::install::Install;
+    use crate::cli::uninstall::Uninstall;
+    use crate::cli::upgrade::Upgrade;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Explain {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Explain installing the given formulae or casks
+        Install(Install),
+
+        /// Explain uninstalling the given formulae or casks
+        Uninstall(Uninstall),
+
+        /// Explain upgrading the given formulae or casks
+        Upgrade(Upgrade),
+
+        /// Explain removing old kegs and cached downloads
+        Cleanup,
+    }
+
+    impl Explain {
+        pub fn run(&self, mut engine: Engine, json: bool) -> anyhow::Result<()> {
+            let invocations = match &self.command {
+                Commands::Install(cmd) => {
+                    let state = engine.cache_or_latest()?;
+
+                    engine.explain(Action::Install, cmd.get_kegs(state, false)?.0)
+                }
+                Commands::Uninstall(cmd) => {
+                    let state = engine.cache_or_latest()?;
+
+                    engine.explain(Action::Uninstall, cmd.upstream_kegs(state, false)?.0)
+                }
+                Commands::Upgrade(cmd) => {
+                    let state = engine.cache_or_latest()?;
+                    let kegs = cmd
+                        .upgrades(&state, false)?
+                        .0
+                        .into_iter()
+                        .map(|u| u.keg)
+                        .collect();
+
+                    engine.explain(Action::Upgrade, kegs)
+                }
+                Commands::Cleanup => engine.explain(Action::Cleanup, Vec::new()),
+            };
+
+            print(&invocations, json)
+        }
+    }
+
+    fn print(invocations: &[Invocation], json: bool) -> anyhow::Result<()> {
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        if json {
+            serde_json::to_writer_pretty(&mut buf, invocations)?;
+            writeln!(buf)?;
+        } else if invocations.is_empty() {
+            eprintln!("{}", header::primary!("Nothing would be run"));
+        } else {
+            for invocation in invocations {
+                writeln!(buf, "{invocation}")?;
+            }
+        }
+
+        buf.flush()?;
+
+        Ok(())
+    }
+}
+
+pub mod uninstall {
+    use std::borrow::Cow;
+    use std::io::{BufWriter, Write};
+
+    use clap::Args;
+    use colored::Colorize;
+    use inquire::Confirm;
+    use skim::{ItemPreview, PreviewContext, SkimItem};
+
+    use brewer_core::models;
+    use brewer_engine::index::Indexes;
+    use brewer_engine::{Engine, State};
+
+    use crate::cli::{info_cask, info_formula, select_skim, summary};
+    use crate::error::Cancelled;
+    use crate::pretty;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Uninstall {
+        pub names: Vec<String>,
+
+        #[clap(short, long, action, group = "type")]
+        pub formula: bool,
+
+        #[clap(short, long, action, group = "type")]
+        pub cask: bool,
+
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+
+        /// Show installed kegs changes after completion
+        #[clap(short, long, action)]
+        pub summary: bool,
+    }
+
+    impl Uninstall {
+        /// Uninstalls the kegs, false if some of the given names are not installed
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let (kegs, found) = self.upstream_kegs(state, true)?;
+
+            if kegs.is_empty() {
+                return Ok(found);
+            }
+
+            if !self.yes && !plan(&kegs)? {
+                return Err(Cancelled.into());
+            }
+
+            let delta = engine.uninstall(kegs)?;
+
+            if self.summary {
+                summary(&delta)?;
+            }
+
+            Ok(found)
+        }
+
+        /// Kegs to be uninstalled, as known upstream, and whether every given name is installed.
+        /// Unless interactive, names are required
+        pub fn upstream_kegs(
+            &self,
+            state: State,
+            interactive: bool,
+        ) -> anyhow::Result<(Vec<models::Keg>, bool)> {
+            let (kegs, found) = self.get_kegs(state, interactive)?;
+
+            let kegs = kegs
+                .into_iter()
+                .map(|k| match k {
+                    Keg::Formula(formula) => formula.upstream.into(),
+                    Keg::Cask(cask) => cask.upstream.into(),
+                })
+                .collect();
+
+            Ok((kegs, found))
+        }
+
+        fn get_kegs(&self, state: State, interactive: bool) -> anyhow::Result<(Vec<Keg>, bool)> {
+            if !self.names.is_empty() {
+                self.get_kegs_from_args(state)
+            } else if interactive {
+                Ok((self.get_kegs_from_skim(state)?, true))
+            } else {
+                anyhow::bail!("no formulae or casks given")
+            }
+        }
+
+        fn get_kegs_from_args(&self, mut state: State) -> anyhow::Result<(Vec<Keg>, bool)> {
+            let mut kegs = Vec::new();
+            let mut found = true;
+
+            for name in &self.names {
+                let keg = if self.formula {
+                    if !state.formulae.installed.contains_key(name) {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Formula {name} is not installed, skipping")
+                        );
+                        found = false;
+                        continue;
+                    }
+
+                    state.formulae.installed.remove(name).map(Keg::Formula)
+                } else if self.cask {
+                    if !state.casks.installed.contains_key(name) {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Cask {name} is not installed, skipping")
+                        );
+                        found = false;
+                        continue;
+                    }
+
+                    state.casks.installed.remove(name).map(Keg::Cask)
+                } else {
+                    state
+                        .formulae
+                        .installed
+                        .remove(name)
+                        .map(Keg::Formula)
+                        .or_else(|| state.casks.installed.remove(name).map(Keg::Cask))
+                };
+
+                let Some(keg) = keg else {
+                    eprintln!(
+                        "{}",
+                        header::warning!("Formula or cask {name} is not installed skipping")
+                    );
+                    found = false;
+                    continue;
+                };
+
+                kegs.push(keg);
+            }
+
+            Ok((kegs, found))
+        }
+
+        fn get_kegs_from_skim(&self, state: State) -> anyhow::Result<Vec<Keg>> {
+            let mut installed: Vec<Candidate> =
+                Vec::with_capacity(state.formulae.installed.len() + state.casks.installed.len());
+            let indexes = Indexes::new(&state);
+
+            for formula in state
+                .formulae
+                .installed
+                .values()
+                .filter(|f| f.receipt.installed_on_request)
+            {
+                installed.push(Candidate {
+                    dependents: indexes.installed_dependents(&formula.upstream.base.name),
+                    keg: formula.clone().into(),
+                });
+            }
+
+            for cask in state.casks.installed.values() {
+                installed.push(Candidate {
+                    keg: cask.clone().into(),
+                    dependents: Vec::new(),
+                });
+            }
+
+            let selected = select_skim(installed, "Uninstall", true)?
+                .into_iter()
+                .map(|c| c.keg)
+                .collect();
+
+            Ok(selected)
+        }
+    }
+
+    pub fn plan(kegs: &Vec<models::Keg>) -> anyhow::Result<bool> {
+        let mut w = BufWriter::new(std::io::stderr());
+
+        writeln!(
+            w,
+            "{}",
+            header::primary!("The following kegs will be uninstalled")
+        )?;
+
+        let mut kegs: Vec<_> = kegs.iter().collect();
+        kegs.sort_unstable_by(|a, b| a.name().cmp(b.name()));
+
+        for keg in &kegs {
+            match &keg {
+                models::Keg::Formula(f) => writeln!(
+                    w,
+                    "{} {} (Formula)",
+                    f.base.name.cyan(),
+                    f.base.versions.stable
+                )?,
+                models::Keg::Cask(c) => {
+                    writeln!(w, "{} {} (Cask)", c.base.token.cyan(), c.base.version)?
+                }
+            }
+        }
+
+        writeln!(w)?;
+
+        let mut executables: Vec<String> = Vec::new();
+
+        for k in kegs {
+            if let models::Keg::Formula(f) = &k {
+                for e in &f.executables {
+                    executables.push(e.purple().to_string());
+                }
+            }
+        }
+
+        if !executables.is_empty() {
+            writeln!(
+                w,
+                "{}",
+                header::primary!("The following executables will be removed")
+            )?;
+            writeln!(w, "{}", executables.join(" "))?;
+            writeln!(w)?;
+        }
+
+        w.flush()?;
+
+        Ok(Confirm::new("Proceed?").with_default(false).prompt()?)
+    }
+
+    #[derive(Args)]
+    pub struct Autoremove {
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+
+        /// Show installed kegs changes after completion
+        #[clap(short, long, action)]
+        pub summary: bool,
+    }
+
+    impl Autoremove {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
+            let mut state = engine.cache_or_latest()?;
+
+            let kegs: Vec<models::Keg> = brewer_engine::orphans(&state)
+                .into_iter()
+                .filter_map(|name| state.formulae.installed.remove(&name))
+                .map(|formula| formula.upstream.into())
+                .collect();
+
+            if kegs.is_empty() {
+                println!("{}", header::primary!("No orphaned dependencies"));
+
+                return Ok(());
+            }
+
+            if !self.yes && !plan(&kegs)? {
+                return Err(Cancelled.into());
+            }
+
+            let delta = engine.uninstall(kegs)?;
+
+            if self.summary {
+                summary(&delta)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[derive(Clone)]
+    pub enum Keg {
+        Formula(models::formula::installed::Formula),
+        Cask(models::cask::installed::Cask),
+    }
+
+    impl From<models::formula::installed::Formula> for Keg {
+        fn from(value: models::formula::installed::Formula) -> Self {
+            Keg::Formula(value)
+        }
+    }
+
+    impl From<models::cask::installed::Cask> for Keg {
+        fn from(value: models::cask::installed::Cask) -> Self {
+            Keg::Cask(value)
+        }
+    }
+
+    impl From<Keg> for models::Keg {
+        fn from(value: Keg) -> Self {
+            match value {
+                Keg::Formula(formula) => formula.upstream.into(),
+                Keg::Cask(cask) => cask.upstream.into(),
+            }
+        }
+    }
+
+    impl SkimItem for Keg {
+        fn text(&self) -> Cow<str> {
+            match &self {
+                Keg::Formula(formula) => Cow::Borrowed(&formula.upstream.base.name),
+                Keg::Cask(cask) => Cow::Borrowed(&cask.upstream.base.token),
+            }
+        }
+
+        fn preview(&self, _context: PreviewContext) -> ItemPreview {
+            let mut buf = Vec::new();
+
+            match &self {
+                Keg::Formula(formula) => {
+                    info_formula(&mut buf, &formula.upstream, Some(formula)).unwrap()
+                }
+                Keg::Cask(cask) => info_cask(&mut buf, &cask.upstream, Some(cask)).unwrap(),
+            };
+
+            let preview = String::from_utf8(buf).unwrap();
+
+            ItemPreview::AnsiText(preview)
+        }
+    }
+
+    /// Keg offered in the picker along with the consequences of uninstalling it
+    #[derive(Clone)]
+    struct Candidate {
+        keg: Keg,
+
+        /// Installed formulae depending on this keg
+        dependents: Vec<String>,
+    }
+
+    impl SkimItem for Candidate {
+        fn text(&self) -> Cow<str> {
+            self.keg.text()
+        }
+
+        fn preview(&self, _context: PreviewContext) -> ItemPreview {
+            let mut buf = Vec::new();
+
+            match &self.keg {
+                Keg::Formula(formula) => {
+                    impact(
+                        &mut buf,
+                        &self.dependents,
+                        formula.upstream.base.installed_size(),
+                    )
+                    .unwrap();
+                    info_formula(&mut buf, &formula.upstream, Some(formula)).unwrap()
+                }
+                Keg::Cask(cask) => {
+                    impact(&mut buf, &self.dependents, None).unwrap();
+                    info_cask(&mut buf, &cask.upstream, Some(cask)).unwrap()
+                }
+            };
+
+            let preview = String::from_utf8(buf).unwrap();
+
+            ItemPreview::AnsiText(preview)
+        }
+    }
+
+    /// Dependents left broken and space freed by uninstalling
+    fn impact(w: &mut impl Write, dependents: &[String], freed: Option<u64>) -> anyhow::Result<()> {
+        if dependents.is_empty() {
+            writeln!(w, "No installed dependents {}", pretty::bool(true))?;
+        } else {
+            writeln!(w, "{} {}", "Required by".red(), dependents.join(", ").red())?;
+        }
+
+        match freed {
+            Some(size) => writeln!(w, "Frees about {}", pretty::bytes(size))?,
+            None => writeln!(w, "Frees {}", "unknown".italic())?,
+        }
+
+        writeln!(w)?;
+
+        Ok(())
+    }
+}
+
+pub mod history {
+    use std::io::{BufWriter, Write};
+
+    use chrono::{NaiveDate, NaiveDateTime};
+    use clap::{Args, Subcommand, ValueEnum};
+    use colored::Colorize;
+    use serde::Serialize;
+
+    use brewer_core::models;
+    use brewer_engine::store::{Action, Outcome};
+    use brewer_engine::Engine;
+
+    use crate::cli::{install, uninstall, wait_for_brew};
+    use crate::error::Cancelled;
+    use crate::pretty;
+    use crate::pretty::header;
+
+    /// Lists the most recent events when no subcommand is given
+    #[derive(Args)]
+    pub struct History {
+        #[command(subcommand)]
+        pub command: Option<Commands>,
+
+        /// Only events of the given formula or cask
+        #[clap(long)]
+        pub name: Option<String>,
+
+        /// How many of the most recent events to list
+        #[clap(long, short = 'n', default_value_t = 20)]
+        pub limit: usize,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Dump recorded events with timestamps, versions and initiating user
+        Export {
+            #[clap(long, short, value_enum, default_value = "json")]
+            format: Format,
+
+            /// Only events since the given date, e.g. 2024-05-01 or 2024-05-01T12:00:00
+            #[clap(long, value_parser = parse_since)]
+            since: Option<NaiveDateTime>,
+        },
+    }
+
+    #[derive(Clone, Copy, ValueEnum)]
+    pub enum Format {
+        /// JSON array of objects
+        Json,
+
+        /// Comma-separated values with a header
+        Csv,
+    }
+
+    /// Exported event, timestamps are RFC 3339 in UTC
+    #[derive(Serialize)]
+    struct Row<'a> {
+        at: String,
+        action: &'static str,
+        name: &'a str,
+        version: &'a str,
+        previous: Option<&'a str>,
+        user: &'a str,
+        outcome: &'static str,
+    }
+
+    impl History {
+        pub fn run(&self, engine: Engine) -> anyhow::Result<()> {
+            match &self.command {
+                Some(Commands::Export { format, since }) => export(&engine, *format, *since),
+                None => self.list(&engine),
+            }
+        }
+
+        fn list(&self, engine: &Engine) -> anyhow::Result<()> {
+            let mut events = engine.events(None)?;
+
+            if let Some(name) = &self.name {
+                events.retain(|e| &e.name == name);
+            }
+
+            if events.is_empty() {
+                let message = match &self.name {
+                    Some(name) => format!("No history for {name}"),
+                    None => "Nothing was installed through brewer yet".to_string(),
+                };
+
+                println!("{}", message.italic());
+                return Ok(());
+            }
+
+            let skip = events.len().saturating_sub(self.limit);
+
+            let mut buf = BufWriter::new(std::io::stdout());
+
+            for e in events.into_iter().skip(skip) {
+                let version = match &e.previous {
+                    Some(previous) => format!("{} -> {}", previous.red(), e.version.green()),
+                    None => e.version.clone(),
+                };
+
+                write!(
+                    buf,
+                    "{} {} {} {version} by {}",
+                    e.at.format("%Y-%m-%d %H:%M").to_string().dimmed(),
+                    e.action.name().bold(),
+                    e.name.cyan(),
+                    e.user
+                )?;
+
+                if e.outcome == Outcome::Failed {
+                    write!(buf, " {} failed", pretty::bool(false))?;
+                }
+
+                writeln!(buf)?;
+            }
+
+            buf.flush()?;
+
+            Ok(())
+        }
+    }
+
+    #[derive(Args)]
+    pub struct Rollback {
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+    }
+
+    impl Rollback {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let batch = engine.last_batch()?;
+
+            let Some(action) = batch.first().map(|e| e.action) else {
+                println!("{}", "Nothing to roll back".italic());
+                return Ok(false);
+            };
+
+            let mut state = engine.cache_or_latest()?;
+
+            match action {
+                Action::Upgrade => {
+                    let names: Vec<_> = batch.iter().map(|e| e.name.as_str()).collect();
+
+                    eprintln!(
+                        "{}",
+                        header::warning!(
+                            "brew can't downgrade, {} stay at the upgraded versions",
+                            names.join(", ")
+                        )
+                    );
+
+                    Ok(false)
+                }
+                Action::Install => {
+                    let kegs: Vec<models::Keg> = batch
+                        .iter()
+                        .filter_map(|e| {
+                            let formula = state.formulae.installed.remove(&e.name);
+                            let cask = || state.casks.installed.remove(&e.name);
+
+                            formula
+                                .map(|f| f.upstream.into())
+                                .or_else(|| cask().map(|c| c.upstream.into()))
+                        })
+                        .collect();
+
+                    if kegs.is_empty() {
+                        println!("{}", "Everything is uninstalled already".italic());
+                        return Ok(true);
+                    }
+
+                    if !self.yes && !uninstall::plan(&kegs)? {
+                        return Err(Cancelled.into());
+                    }
+
+                    wait_for_brew(&engine)?;
+
+                    engine.uninstall(kegs)?;
+
+                    Ok(true)
+                }
+                Action::Uninstall => {
+                    let mut kegs: Vec<models::Keg> = Vec::new();
+
+                    for e in &batch {
+                        if state.formulae.installed.contains_key(&e.name)
+                            || state.casks.installed.contains_key(&e.name)
+                        {
+                            continue;
+                        }
+
+                        let keg: Option<models::Keg> = match state.formulae.all.remove(&e.name) {
+                            Some(f) => Some(f.into()),
+                            None => state.casks.all.remove(&e.name).map(|c| c.into()),
+                        };
+
+                        let Some(keg) = keg else {
+                            eprintln!(
+                                "{}",
+                                header::warning!("{} is no longer available, skipping", e.name)
+                            );
+                            continue;
+                        };
+
+                        let available = match &keg {
+                            models::Keg::Formula(f) => &f.base.versions.stable,
+                            models::Keg::Cask(c) => &c.base.version,
+                        };
+
+                        // brew only installs the current version
+                        if !e.version.is_empty() && available != &e.version {
+                            eprintln!(
+                                "{}",
+                                header::warning!(
+                                    "{} {} was removed, {available} will be installed",
+                                    e.name,
+                                    e.version
+                                )
+                            );
+                        }
+
+                        kegs.push(keg);
+                    }
+
+                    if kegs.is_empty() {
+                        println!("{}", "Everything is installed already".italic());
+                        return Ok(true);
+                    }
+
+                    if !self.yes && !install::plan(&kegs, "installed")? {
+                        return Err(Cancelled.into());
+                    }
+
+                    wait_for_brew(&engine)?;
+
+                    engine.install(kegs)?;
+
+                    Ok(true)
+                }
+            }
+        }
+    }
+
+    fn export(engine: &Engine, format: Format, since: Option<NaiveDateTime>) -> anyhow::Result<()> {
+        let events = engine.events(since)?;
+
+        let rows: Vec<_> = events
+            .iter()
+            .map(|e| Row {
+                at: e.at.and_utc().to_rfc3339(),
+                action: e.action.name(),
+                name: &e.name,
+                version: &e.version,
+                previous: e.previous.as_deref(),
+                user: &e.user,
+                outcome: e.outcome.name(),
+            })
+            .collect();
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut buf, &rows)?;
+                writeln!(buf)?;
+            }
+            Format::Csv => {
+                writeln!(buf, "at,action,name,version,previous,user,outcome")?;
+
+                for row in rows {
+                    let fields = [
+                        row.at.as_str(),
+                        row.action,
+                        row.name,
+                        row.version,
+                        row.previous.unwrap_or_default(),
+                        row.user,
+                        row.outcome,
+                    ];
+
+                    let fields: Vec<_> = fields.into_iter().map(csv_field).collect();
+
+                    writeln!(buf, "{}", fields.join(","))?;
+                }
+            }
+        }
+
+        buf.flush()?;
+
+        Ok(())
+    }
+
+    /// Quotes the field when it contains a separator, quote or line break
+    fn csv_field(field: &str) -> String {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Dates are taken as midnight UTC
+    fn parse_since(s: &str) -> Result<NaiveDateTime, String> {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default());
+        }
+
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+            .map_err(|_| format!("expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS, got {s:?}"))
+    }
+}
+
+pub mod changes {
+    use std::collections::HashSet;
+    use std::io::{BufWriter, Write};
+
+    use chrono::DateTime;
+    use clap::Args;
+    use colored::Colorize;
+
+    use brewer_engine::{store, Engine};
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Changes {
+        /// Name of the installed formula
+        pub name: String,
+    }
+
+    impl Changes {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let Some(installed) = state.formulae.installed.get(&self.name) else {
+                eprintln!(
+                    "{}",
+                    header::warning!("Formula {} is not installed", self.name)
+                );
+                return Ok(false);
+            };
+
+            let Some(latest) = state.formulae.all.get(&self.name) else {
+                return Ok(false);
+            };
+
+            let installed_version = &installed.receipt.source.versions.stable;
+
+            let history = engine.history(&self.name)?;
+
+            // the same version is recorded again when its dependencies or caveats change,
+            // the entry current at the time of the install is the one installed
+            let installed_at = installed
+                .receipt
+                .time
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .map(|t| t.naive_utc());
+
+            let mut generations = history
+                .iter()
+                .filter(|g| &g.release.version == installed_version);
+
+            let generation = match installed_at {
+                Some(at) => generations
+                    .clone()
+                    .filter(|g| g.recorded_at <= at)
+                    .last()
+                    .or_else(|| generations.next()),
+                None => generations.next(),
+            };
+
+            let Some(generation) = generation else {
+                eprintln!(
+                    "{}",
+                    header::warning!(
+                        "No recorded entry for {} {installed_version}, changes are unknown",
+                        self.name
+                    )
+                );
+                return Ok(false);
+            };
+
+            let mut buf = BufWriter::new(std::io::stdout());
+
+            changes(
+                &mut buf,
+                &self.name,
+                &generation.release,
+                &store::Release::from(&latest.base),
+            )?;
+
+            buf.flush()?;
+
+            Ok(true)
+        }
+    }
+
+    fn changes(
+        buf: &mut impl Write,
+        name: &str,
+        old: &store::Release,
+        new: &store::Release,
+    ) -> anyhow::Result<()> {
+        if old.version == new.version {
+            writeln!(
+                buf,
+                "{}",
+                header::primary!("{name} {} (up to date)", new.version)
+            )?;
+        } else {
+            writeln!(
+                buf,
+                "{}",
+                header::primary!("{name} {} -> {}", old.version, new.version.green())
+            )?;
+        }
+
+        diff_list(buf, "Dependencies", &old.dependencies, &new.dependencies)?;
+        diff_list(
+            buf,
+            "Build dependencies",
+            &old.build_dependencies,
+            &new.build_dependencies,
+        )?;
+
+        if old.caveats != new.caveats {
+            writeln!(buf)?;
+            writeln!(buf, "Caveats changed")?;
+
+            match &new.caveats {
+                Some(caveats) => writeln!(buf, "{}", caveats.italic())?,
+                None => writeln!(buf, "{}", "Caveats removed".italic())?,
+            }
+        }
+
+        if new.deprecated && !old.deprecated {
+            writeln!(buf)?;
+            writeln!(buf, "{}", "Formula was deprecated".yellow())?;
+        }
+
+        if new.disabled && !old.disabled {
+            writeln!(buf)?;
+            writeln!(buf, "{}", "Formula was disabled".red())?;
+        }
+
+        Ok(())
+    }
+
+    fn diff_list(
+        buf: &mut impl Write,
+        title: &str,
+        old: &[String],
+        new: &[String],
+    ) -> anyhow::Result<()> {
+        let old: HashSet<_> = old.iter().collect();
+        let new: HashSet<_> = new.iter().collect();
+
+        let mut added: Vec<_> = new.difference(&old).collect();
+        let mut removed: Vec<_> = old.difference(&new).collect();
+
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        added.sort_unstable();
+        removed.sort_unstable();
+
+        writeln!(buf)?;
+        writeln!(buf, "{title}")?;
+
+        for name in added {
+            writeln!(buf, "{} {}", "+".green(), name.cyan())?;
+        }
+
+        for name in removed {
+            writeln!(buf, "{} {}", "-".red(), name.cyan())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the operation recording its duration, prints a hint if it was unusually slow
+pub fn timed<T>(
+    engine: &mut Engine,
+    operation: Operation,
+    f: impl FnOnce(&mut Engine) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let start = Instant::now();
+
+    let result = f(engine)?;
+
+    let took = start.elapsed();
+
+    if let Some(typical) = engine.record_timing(operation, took)? {
+        if std::io::stderr().is_terminal() {
+            let hint = match operation {
+                Operation::Update => {
+                    "fetching analytics can be skipped with homebrew.analytics = false"
+                }
+                Operation::Install => "brew may be building from source or downloading slowly",
+                Operation::Load => "cache.verify_installed = true makes loading slower",
+            };
+
+            eprintln!(
+                "{}",
+                header::warning!(
+                    "{} took {}, usually {}; {hint}",
+                    operation.name(),
+                    pretty::duration(took),
+                    pretty::duration(typical)
+                )
+            );
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether the tap matches any of the given ones, all taps match when none are given
+pub fn in_taps(tap: &str, taps: &[String]) -> bool {
+    taps.is_empty() || taps.iter().any(|t| brewer_engine::tap_matches(tap, t))
+}
+
+/// Blocks until no other brew process holds Homebrew's locks,
+/// so a multi-keg plan doesn't fail halfway through on brew's lock error
+pub fn wait_for_brew(engine: &Engine) -> anyhow::Result<()> {
+    const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+    const POLL: Duration = Duration::from_millis(250);
+
+    let mut held = engine.held_locks()?;
+
+    if held.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!(
+        "{}",
+        header::warning!("Another brew process is running, waiting for it to finish")
+    );
+
+    let spinner = std::io::stderr().is_terminal();
+    let start = Instant::now();
+    let mut frame = 0;
+
+    while !held.is_empty() {
+        if spinner {
+            let mut stderr = std::io::stderr();
+
+            write!(
+                stderr,
+                "\r\x1b[2K{} {} locked for {}",
+                FRAMES[frame % FRAMES.len()].to_string().cyan(),
+                held.join(", "),
+                pretty::duration(start.elapsed())
+            )?;
+            stderr.flush()?;
+
+            frame += 1;
+        }
+
+        std::thread::sleep(POLL);
+
+        held = engine.held_locks()?;
+    }
+
+    if spinner {
+        eprint!("\r\x1b[2K");
+    }
+
+    Ok(())
+}
+
+/// Lists candidates of a name provided by several taps
+fn ambiguous(name: &str, candidates: &[String]) {
+    eprintln!(
+        "{}",
+        header::warning!("{name} is provided by several taps, use a full name")
+    );
+
+    for candidate in candidates {
+        eprintln!("{}", candidate.cyan());
+    }
+
+    eprintln!("Set homebrew.tap_priority in the config to prefer one of the taps");
+}
+
+/// Explains that nothing matched the query. Silent when stdout is not a terminal
+fn not_found<'a>(
+    message: &str,
+    query: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+    cache_age: Option<Duration>,
+) -> anyhow::Result<()> {
+    const SUGGESTIONS: usize = 5;
+    const STALE_CACHE_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+    if !std::io::stdout().is_terminal() {
+        return Ok(());
+    }
+
+    let mut w = BufWriter::new(std::io::stderr());
+
+    writeln!(w, "{}", header::warning!("{message}"))?;
+
+    let mut matcher = nucleo_matcher::Matcher::new(nucleo_matcher::Config::DEFAULT);
+
+    let atom = Atom::new(
+        query,
+        CaseMatching::Ignore,
+        Normalization::Smart,
+        AtomKind::Fuzzy,
+        false,
+    );
+
+    let mut matches = atom.match_list(candidates, &mut matcher);
+
+    matches.sort_by(|(a, a_score), (b, b_score)| b_score.cmp(a_score).then(a.cmp(b)));
+
+    let suggestions: Vec<_> = matches
+        .into_iter()
+        .take(SUGGESTIONS)
+        .map(|(name, _)| name.cyan().to_string())
+        .collect();
+
+    if !suggestions.is_empty() {
+        writeln!(w, "Did you mean {}?", suggestions.join(", "))?;
+    }
+
+    match cache_age {
+        Some(age) if age >= STALE_CACHE_AGE => writeln!(
+            w,
+            "The cache is {} old, run {} to refresh it",
+            pretty::duration(age),
+            "brewer update".bold()
+        )?,
+        None => writeln!(
+            w,
+            "The cache was never updated, run {} to fetch it",
+            "brewer update".bold()
+        )?,
+        _ => {}
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+fn summary(delta: &InstalledDelta) -> anyhow::Result<()> {
+    let mut w = BufWriter::new(std::io::stderr());
+
+    if delta.is_empty() {
+        writeln!(w, "{}", header::primary!("No installed kegs changed"))?;
+        w.flush()?;
+
+        return Ok(());
+    }
+
+    let sections = [
+        ("Installed formulae", &delta.added.formulae),
+        ("Installed casks", &delta.added.casks),
+        ("Removed formulae", &delta.removed.formulae),
+        ("Removed casks", &delta.removed.casks),
+    ];
+
+    for (title, names) in sections {
+        if names.is_empty() {
+            continue;
+        }
+
+        let names: Vec<_> = names.iter().map(|n| n.cyan().to_string()).collect();
+
+        writeln!(w, "{}", header::primary!("{title}"))?;
+        writeln!(w, "{}", names.join(" "))?;
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+pub mod size {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, ValueEnum};
+    use colored::Colorize;
+    use serde::Serialize;
+
+    use brewer_engine::{Engine, State};
+
+    use crate::pretty;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Size {
+        /// Installed formulae or casks, all of them if empty
+        pub names: Vec<String>,
+
+        #[clap(long, short, value_enum, default_value = "size")]
+        pub sort: Sort,
+    }
+
+    #[derive(Clone, Copy, ValueEnum)]
+    pub enum Sort {
+        /// Largest first
+        Size,
+
+        /// Alphabetically
+        Name,
+    }
+
+    #[derive(Serialize)]
+    struct Report {
+        kegs: Vec<Row>,
+        total: u64,
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        name: String,
+        kind: &'static str,
+        bytes: u64,
+    }
+
+    impl Size {
+        pub fn run(&self, engine: &mut Engine, state: State, json: bool) -> anyhow::Result<bool> {
+            let sizes = engine.installed_sizes(&state)?;
+
+            let formulae = sizes.formulae.into_iter().map(|(name, bytes)| Row {
+                name,
+                kind: "formula",
+                bytes,
+            });
+            let casks = sizes.casks.into_iter().map(|(name, bytes)| Row {
+                name,
+                kind: "cask",
+                bytes,
+            });
+
+            let mut kegs: Vec<Row> = formulae.chain(casks).collect();
+
+            let mut found = true;
+
+            if !self.names.is_empty() {
+                for name in &self.names {
+                    if !kegs.iter().any(|r| &r.name == name) {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Formula or cask {name} is not installed, skipping")
+                        );
+                        found = false;
+                    }
+                }
+
+                kegs.retain(|r| self.names.contains(&r.name));
+            }
+
+            match self.sort {
+                Sort::Size => kegs.sort_unstable_by(|a, b| {
+                    b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name))
+                }),
+                Sort::Name => kegs.sort_unstable_by(|a, b| a.name.cmp(&b.name)),
+            }
+
+            let total = kegs.iter().map(|r| r.bytes).sum();
+            let report = Report { kegs, total };
+
+            let mut buf = BufWriter::new(std::io::stdout());
+
+            if json {
+                serde_json::to_writer(&mut buf, &report)?;
+                writeln!(buf)?;
+            } else {
+                for row in &report.kegs {
+                    writeln!(
+                        buf,
+                        "{:>10} {} {}",
+                        pretty::bytes(row.bytes),
+                        row.name.cyan(),
+                        format!("({})", row.kind).dimmed()
+                    )?;
+                }
+
+                writeln!(
+                    buf,
+                    "{:>10} {}",
+                    pretty::bytes(report.total),
+                    "total".bold()
+                )?;
+            }
+
+            buf.flush()?;
+
+            Ok(found)
+        }
+    }
+}
+
+pub mod snapshot {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, Subcommand};
+    use colored::Colorize;
+
+    use brewer_core::models;
+    use brewer_engine::Engine;
+
+    use crate::cli::{install, uninstall, wait_for_brew};
+    use crate::error::Cancelled;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Snapshot {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Save installed formulae and casks with their versions under the given name
+        Create {
+            name: String,
+
+            /// Replace an existing snapshot
+            #[clap(short, long, action)]
+            force: bool,
+        },
+
+        /// Install and uninstall formulae and casks to match the given snapshot
+        Restore {
+            name: String,
+
+            /// Confirm
+            #[clap(short, long, action)]
+            yes: bool,
+        },
+
+        /// List saved snapshots
+        List,
+
+        /// Delete the given snapshot
+        Delete { name: String },
+    }
+
+    impl Snapshot {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            match &self.command {
+                Commands::Create { name, force } => {
+                    if !force && engine.snapshots()?.contains_key(name) {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Snapshot {name} exists, use --force to replace it")
+                        );
+                        return Ok(false);
+                    }
+
+                    let snapshot = engine.create_snapshot(name)?;
+
+                    println!(
+                        "Saved {} formulae and {} casks as {}",
+                        snapshot.formulae.len(),
+                        snapshot.casks.len(),
+                        name.cyan()
+                    );
+
+                    Ok(true)
+                }
+                Commands::Restore { name, yes } => restore(&mut engine, name, *yes),
+                Commands::List => list(&engine),
+                Commands::Delete { name } => {
+                    if !engine.snapshots()?.contains_key(name) {
+                        eprintln!("{}", header::warning!("Unknown snapshot {name}"));
+                        return Ok(false);
+                    }
+
+                    engine.delete_snapshot(name)?;
+
+                    Ok(true)
+                }
+            }
+        }
+    }
+
+    fn list(engine: &Engine) -> anyhow::Result<bool> {
+        let mut snapshots: Vec<_> = engine.snapshots()?.into_iter().collect();
+
+        if snapshots.is_empty() {
+            println!("{}", "No snapshots saved yet".italic());
+            return Ok(true);
+        }
+
+        snapshots.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        for (name, snapshot) in snapshots {
+            writeln!(
+                buf,
+                "{} {} {} formulae, {} casks",
+                name.cyan().bold(),
+                snapshot
+                    .created_at
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+                    .dimmed(),
+                snapshot.formulae.len(),
+                snapshot.casks.len()
+            )?;
+        }
+
+        buf.flush()?;
+
+        Ok(true)
+    }
+
+    fn restore(engine: &mut Engine, name: &str, yes: bool) -> anyhow::Result<bool> {
+        let Some(snapshot) = engine.snapshots()?.remove(name) else {
+            eprintln!("{}", header::warning!("Unknown snapshot {name}"));
+            return Ok(false);
+        };
+
+        let state = engine.cache_or_latest()?;
+
+        // dependencies follow the kegs requiring them, `brewer autoremove` cleans up the rest
+        let extra: Vec<models::Keg> = state
+            .formulae
+            .installed
+            .iter()
+            .filter(|(name, f)| {
+                f.receipt.installed_on_request && !snapshot.formulae.contains_key(*name)
+            })
+            .map(|(_, f)| f.upstream.clone().into())
+            .chain(
+                state
+                    .casks
+                    .installed
+                    .iter()
+                    .filter(|(name, _)| !snapshot.casks.contains_key(*name))
+                    .map(|(_, c)| c.upstream.clone().into()),
+            )
+            .collect();
+
+        let mut missing: Vec<models::Keg> = Vec::new();
+
+        let wanted = snapshot
+            .formulae
+            .iter()
+            .filter(|(name, s)| s.on_request && !state.formulae.installed.contains_key(*name))
+            .map(|(name, s)| {
+                (
+                    name,
+                    s,
+                    state.formulae.all.get(name).cloned().map(models::Keg::from),
+                )
+            })
+            .chain(
+                snapshot
+                    .casks
+                    .iter()
+                    .filter(|(name, _)| !state.casks.installed.contains_key(*name))
+                    .map(|(name, s)| {
+                        (
+                            name,
+                            s,
+                            state.casks.all.get(name).cloned().map(models::Keg::from),
+                        )
+                    }),
+            );
+
+        for (name, saved, keg) in wanted {
+            let Some(keg) = keg else {
+                eprintln!(
+                    "{}",
+                    header::warning!("{name} is no longer available, skipping")
+                );
+                continue;
+            };
+
+            let available = match &keg {
+                models::Keg::Formula(f) => &f.base.versions.stable,
+                models::Keg::Cask(c) => &c.base.version,
+            };
+
+            // brew only installs the current version
+            if !saved.version.is_empty() && available != &saved.version {
+                eprintln!(
+                    "{}",
+                    header::warning!(
+                        "{name} {} was saved, {available} will be installed",
+                        saved.version
+                    )
+                );
+            }
+
+            missing.push(keg);
+        }
+
+        if missing.is_empty() && extra.is_empty() {
+            println!(
+                "{}",
+                "Installed formulae and casks match the snapshot".italic()
+            );
+            return Ok(true);
+        }
+
+        if !missing.is_empty() {
+            if !yes && !install::plan(&missing, "installed")? {
+                return Err(Cancelled.into());
+            }
+
+            wait_for_brew(engine)?;
+
+            engine.install(missing)?;
+        }
+
+        if !extra.is_empty() {
+            if !yes && !uninstall::plan(&extra)? {
+                return Err(Cancelled.into());
+            }
+
+            wait_for_brew(engine)?;
+
+            engine.uninstall(extra)?;
+        }
+
+        Ok(true)
+    }
+}
+
+pub mod tag {
+    use clap::Args;
+    use colored::Colorize;
+
+    use brewer_engine::Engine;
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Tag {
+        /// Name of the formula or cask
+        pub name: String,
+
+        /// Tags to add, shows current tags if empty
+        pub tags: Vec<String>,
+
+        /// Remove the given tags instead
+        #[clap(short, long, action)]
+        pub remove: bool,
+    }
+
+    impl Tag {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let tags = match state.formulae.all.get(&self.name) {
+                Some(f) => &f.tags,
+                None => match state.casks.all.get(&self.name) {
+                    Some(c) => &c.tags,
+                    None => {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Unknown formula or cask {}", self.name)
+                        );
+                        return Ok(false);
+                    }
+                },
+            };
+
+            if self.tags.is_empty() {
+                let user = engine.user_tags()?.remove(&self.name).unwrap_or_default();
+
+                let mut tags: Vec<_> = tags.iter().collect();
+                tags.sort_unstable();
+
+                let tags: Vec<_> = tags
+                    .into_iter()
+                    .map(|t| {
+                        if user.contains(t) {
+                            t.cyan().bold().to_string()
+                        } else {
+                            t.to_string()
+                        }
+                    })
+                    .collect();
+
+                println!("{}", tags.join(" "));
+
+                return Ok(true);
+            }
+
+            if self.remove {
+                engine.untag(&self.name, &self.tags)?;
+            } else {
+                engine.tag(&self.name, &self.tags)?;
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+pub mod note {
+    use clap::Args;
+    use colored::Colorize;
+
+    use brewer_engine::Engine;
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Note {
+        /// Name of the formula or cask
+        pub name: String,
+
+        /// Note text, shows the current note if omitted
+        pub text: Option<String>,
+
+        /// Delete the note
+        #[clap(short, long, action, conflicts_with = "text")]
+        pub delete: bool,
+    }
+
+    impl Note {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let note = match state.formulae.all.get(&self.name) {
+                Some(f) => &f.note,
+                None => match state.casks.all.get(&self.name) {
+                    Some(c) => &c.note,
+                    None => {
+                        eprintln!(
+                            "{}",
+                            header::warning!("Unknown formula or cask {}", self.name)
+                        );
+                        return Ok(false);
+                    }
+                },
+            };
+
+            if self.delete {
+                engine.delete_note(&self.name)?;
+
+                return Ok(true);
+            }
+
+            match &self.text {
+                Some(text) => engine.note(&self.name, text)?,
+                None => match note {
+                    Some(note) => println!("{}", note.italic()),
+                    None => return Ok(false),
+                },
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+pub mod star {
+    use clap::Args;
+
+    use brewer_engine::{Engine, State};
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Star {
+        /// Names of formulae or casks
+        #[clap(required = true)]
+        pub names: Vec<String>,
+    }
+
+    impl Star {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+
+            let mut success = true;
+
+            for name in &self.names {
+                if !exists(&state, name) {
+                    success = false;
+                    continue;
+                }
+
+                engine.star(name)?;
+            }
+
+            Ok(success)
+        }
+    }
+
+    #[derive(Args)]
+    pub struct Unstar {
+        /// Names of formulae or casks
+        #[clap(required = true)]
+        pub names: Vec<String>,
+    }
+
+    impl Unstar {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            for name in &self.names {
+                engine.unstar(name)?;
+            }
+
+            Ok(true)
+        }
+    }
+
+    fn exists(state: &State, name: &str) -> bool {
+        if state.formulae.all.contains_key(name) || state.casks.all.contains_key(name) {
+            return true;
+        }
+
+        eprintln!(
+            "{}",
+            header::warning!("Unknown formula or cask {name}, skipping")
+        );
+
+        false
+    }
+}
+
+pub mod tap {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, Subcommand};
+    use colored::Colorize;
+
+    use brewer_engine::Engine;
+
+    use crate::pretty;
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Tap {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Show formulae and casks counts and local checkout health of a tap
+        Info {
+            /// Tap name, e.g. homebrew/core
+            name: String,
+        },
+    }
+
+    impl Tap {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            match &self.command {
+                Commands::Info { name } => info(&mut engine, name),
+            }
+        }
+    }
+
+    fn info(engine: &mut Engine, name: &str) -> anyhow::Result<bool> {
+        let state = engine.cache_or_latest()?;
+
+        let formulae = state
+            .formulae
+            .all
+            .values()
+            .filter(|f| f.base.tap == name)
+            .count();
+        let casks = state
+            .casks
+            .all
+            .values()
+            .filter(|c| c.base.tap == name)
+            .count();
+
+        if formulae == 0 && casks == 0 {
+            eprintln!("{}", header::warning!("Unknown tap {name}"));
+            return Ok(false);
+        }
+
+        let installed_formulae = state
+            .formulae
+            .installed
+            .values()
+            .filter(|f| f.upstream.base.tap == name)
+            .count();
+
+        let installed_casks = state
+            .casks
+            .installed
+            .values()
+            .filter(|c| c.upstream.base.tap == name)
+            .count();
+
+        let mut buf = BufWriter::new(std::io::stdout());
+
+        writeln!(buf, "{}", header::primary!("{name}"))?;
+        writeln!(buf, "Formulae {formulae} ({installed_formulae} installed)")?;
+        writeln!(buf, "Casks {casks} ({installed_casks} installed)")?;
+
+        if let Some(age) = engine.cache_age()? {
+            writeln!(buf, "Indexed {} ago", pretty::duration(age))?;
+        }
+
+        writeln!(buf)?;
+
+        match engine.tap_checkout(name)? {
+            Some(checkout) => {
+                writeln!(buf, "{}", checkout.path.to_string_lossy().underline())?;
+
+                match checkout.dirty {
+                    Some(dirty) => writeln!(buf, "Clean {}", pretty::bool(!dirty))?,
+                    None => writeln!(buf, "Clean {}", "unknown".italic())?,
+                }
+
+                match checkout.behind {
+                    Some(0) => writeln!(buf, "Up to date {}", pretty::bool(true))?,
+                    Some(behind) => writeln!(
+                        buf,
+                        "Up to date {} ({behind} commits behind)",
+                        pretty::bool(false)
+                    )?,
+                    None => writeln!(buf, "Up to date {}", "unknown".italic())?,
+                }
+            }
+            None => writeln!(
+                buf,
+                "{}",
+                "Not cloned locally, served from the API".italic()
+            )?,
+        }
+
+        buf.flush()?;
+
+        Ok(true)
+    }
+}
+
+pub mod link {
+    use std::io::{BufWriter, Write};
+
+    use clap::{Args, ValueEnum};
+
+    use brewer_engine::{Engine, State};
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Link {
+        #[clap(required = true)]
+        pub names: Vec<String>,
+
+        /// Allow linking keg-only formulae
+        #[clap(short, long, action)]
+        pub force: bool,
+    }
+
+    impl Link {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+            let mut success = true;
+
+            for name in &self.names {
+                let Some(formula) = installed(&state, name) else {
+                    success = false;
+                    continue;
+                };
+
+                if formula.linked {
+                    println!("{}", header::warning!("{name} is already linked, skipping"));
+                    continue;
+                }
+
+                if formula.upstream.base.keg_only && !self.force {
+                    println!(
+                        "{}",
+                        header::warning!("{name} is keg-only, use --force to link it anyway")
+                    );
+                    success = false;
+                    continue;
+                }
+
+                engine.link(&formula.upstream, self.force)?;
+            }
+
+            Ok(success)
+        }
+    }
+
+    #[derive(Args)]
+    pub struct Unlink {
+        #[clap(required = true)]
+        pub names: Vec<String>,
+    }
+
+    impl Unlink {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let state = engine.cache_or_latest()?;
+            let mut success = true;
+
+            for name in &self.names {
+                let Some(formula) = installed(&state, name) else {
+                    success = false;
+                    continue;
+                };
+
+                if !formula.linked {
+                    println!("{}", header::warning!("{name} is not linked, skipping"));
+                    continue;
+                }
+
+                engine.unlink(&formula.upstream)?;
+            }
 
-    if let Some(installed) = installed {
-        writeln!(buf)?;
-        writeln!(
-            buf,
-            "Installed {} {}",
-            installed.receipt.source.version(),
-            pretty::bool(true)
-        )?;
+            Ok(success)
+        }
     }
 
-    if let Some(homepage) = &formula.base.homepage {
-        writeln!(buf)?;
-        writeln!(buf, "{}", homepage.underline().blue())?;
-    }
+    #[derive(Args)]
+    pub struct Shellenv {
+        /// Only expose installed keg-only formulae which are not linked
+        #[clap(long, action)]
+        pub keg_only: bool,
 
-    if let Some(desc) = &formula.base.desc {
-        writeln!(buf)?;
-        writeln!(buf, "{}", desc.italic())?;
+        #[clap(long, value_enum, default_value_t = Shell::Posix)]
+        pub shell: Shell,
     }
 
-    if !formula.executables.is_empty() {
-        writeln!(buf)?;
-        write!(buf, "Provides")?;
+    #[derive(Clone, Copy, ValueEnum)]
+    pub enum Shell {
+        /// bash, zsh and other POSIX shells
+        Posix,
+        Fish,
+    }
 
-        const LIMIT: usize = 5;
+    impl Shellenv {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
+            if !self.keg_only {
+                print!("{}", engine.shellenv()?);
 
-        if formula.executables.len() > LIMIT {
-            for e in formula.executables.iter().take(LIMIT) {
-                write!(buf, " {}", e.bold().purple())?;
+                return Ok(());
             }
 
-            write!(buf, " and {} more", formula.executables.len() - LIMIT)?;
-        } else {
-            for e in formula.executables.iter() {
-                write!(buf, " {}", e.bold().purple())?;
-            }
-        }
+            let state = engine.cache_or_latest()?;
 
-        writeln!(buf)?;
-    }
+            let mut buf = BufWriter::new(std::io::stdout());
 
-    Ok(())
-}
+            let mut kegs: Vec<_> = state
+                .formulae
+                .installed
+                .values()
+                .filter(|f| f.upstream.base.keg_only && !f.linked)
+                .map(|f| f.upstream.base.name.as_str())
+                .collect();
 
-fn info_cask(
-    buf: &mut impl Write,
-    cask: &models::cask::Cask,
-    installed: Option<&models::cask::installed::Cask>,
-) -> anyhow::Result<()> {
-    writeln!(
-        buf,
-        "{}",
-        header::primary!("{} {} (Formula)", &cask.base.token, cask.base.version)
-    )?;
-    writeln!(buf, "From {}", cask.base.tap.yellow())?;
-    writeln!(buf)?;
+            kegs.sort_unstable();
 
-    if let Some(installed) = installed {
-        let versions: Vec<_> = installed.versions.iter().cloned().collect();
-        let versions = versions.join(", ");
+            for name in kegs {
+                writeln!(buf, "# {name}")?;
 
-        writeln!(buf, "Installed {versions} {}", pretty::bool(true))?;
-        writeln!(buf)?;
-    }
+                for (key, value) in engine.keg_env(name) {
+                    // flags are space separated, search paths are colon separated
+                    let separator = if key.ends_with("FLAGS") { " " } else { ":" };
 
-    if let Some(homepage) = &cask.base.homepage {
-        writeln!(buf, "{}", homepage.underline().blue())?;
-        writeln!(buf)?;
+                    match self.shell {
+                        Shell::Posix => writeln!(
+                            buf,
+                            "export {key}=\"{value}${{{key}:+{separator}${key}}}\""
+                        )?,
+                        Shell::Fish if key == "PATH" => {
+                            writeln!(buf, "fish_add_path --global --prepend \"{value}\"")?
+                        }
+                        Shell::Fish => writeln!(
+                            buf,
+                            "set -gx {key} \"{value}\" (string split -n \"{separator}\" -- \"${key}\")"
+                        )?,
+                    }
+                }
+            }
+
+            buf.flush()?;
+
+            Ok(())
+        }
     }
 
-    let desc = if let Some(desc) = &cask.base.desc {
-        desc
-    } else {
-        "No description"
-    };
+    fn installed<'a>(
+        state: &'a State,
+        name: &str,
+    ) -> Option<&'a brewer_core::models::formula::installed::Formula> {
+        let formula = state.formulae.installed.get(name);
 
-    writeln!(buf, "{}", desc.italic())?;
+        if formula.is_none() {
+            println!(
+                "{}",
+                header::warning!("Formula {name} is not installed, skipping")
+            );
+        }
 
-    Ok(())
+        formula
+    }
 }
 
-pub mod search {
-    use std::borrow::Cow;
-    use std::io::{BufWriter, IsTerminal, Write};
+pub mod deps {
+    use std::io::{BufWriter, Write};
 
     use clap::Args;
-    use nucleo_matcher::pattern::{Atom, AtomKind, CaseMatching, Normalization};
-    use skim::{ItemPreview, PreviewContext, SkimItem};
-    use terminal_size::{terminal_size, Width};
+    use colored::Colorize;
 
-    use brewer_core::models;
-    use brewer_engine::State;
+    use brewer_core::models::graph::{Graph, Tree};
+    use brewer_engine::index::Indexes;
+    use brewer_engine::{Resolution, State};
 
-    use crate::cli::{info_cask, info_formula, select_skim};
-    use crate::pretty;
+    use crate::cli::ambiguous;
     use crate::pretty::header;
 
     #[derive(Args)]
-    pub struct Search {
-        pub name: Option<String>,
-    }
+    pub struct Deps {
+        pub name: String,
 
-    impl Search {
-        pub fn run(&self, state: State) -> anyhow::Result<bool> {
-            let kegs = match &self.name {
-                Some(name) => {
-                    let mut matcher = nucleo_matcher::Matcher::new(nucleo_matcher::Config::DEFAULT);
+        /// Show dependencies as a tree
+        #[clap(short, long, action)]
+        pub tree: bool,
 
-                    let atom = Atom::new(
-                        name,
-                        CaseMatching::Ignore,
-                        Normalization::Smart,
-                        AtomKind::Substring,
-                        false,
-                    );
+        /// Include build dependencies
+        #[clap(short, long, action)]
+        pub build: bool,
+    }
 
-                    let formulae = atom.match_list(state.formulae.all.into_values(), &mut matcher);
-                    let mut formulae: Vec<_> = formulae
-                        .into_iter()
-                        .map(|(formula, _)| {
-                            let installed = state.formulae.installed.get(&formula.base.name);
+    impl Deps {
+        pub fn run(&self, state: State) -> anyhow::Result<bool> {
+            let key = match brewer_engine::resolve_formula(&state, &self.name) {
+                Resolution::Found(key) => key,
+                Resolution::Ambiguous(candidates) => {
+                    ambiguous(&self.name, &candidates);
+                    return Ok(false);
+                }
+                Resolution::Missing => {
+                    eprintln!("{}", header::warning!("Unknown formula {}", self.name));
+                    return Ok(false);
+                }
+            };
 
-                            Keg::Formula(formula, Box::new(installed.cloned()))
-                        })
-                        .collect();
+            let graph = Graph::new(&state.formulae.all, self.build);
 
-                    let casks = atom.match_list(state.casks.all.into_values(), &mut matcher);
-                    let mut casks: Vec<_> = casks
-                        .into_iter()
-                        .map(|(cask, _)| {
-                            let installed = state.casks.installed.get(&cask.base.token);
+            let mut buf = BufWriter::new(std::io::stdout());
 
-                            Keg::Cask(cask, installed.cloned())
-                        })
-                        .collect();
+            if self.tree {
+                writeln!(buf, "{}", key.cyan())?;
+                write_tree(&mut buf, &graph.tree(&key), "")?;
+            } else {
+                let direct = graph.direct(&key);
+                let recursive: Vec<_> = graph
+                    .recursive(&key)
+                    .into_iter()
+                    .filter(|dep| !direct.contains(dep))
+                    .collect();
 
-                    formulae.append(&mut casks);
+                let mut direct = direct.to_vec();
+                direct.sort_unstable();
 
-                    formulae
+                writeln!(buf, "{}", header::primary!("Direct"))?;
+                for dep in &direct {
+                    writeln!(buf, "{}", mark(&state, dep))?;
                 }
-                None => self.run_skim(state)?,
-            };
 
-            if kegs.is_empty() {
-                return Ok(false);
+                writeln!(buf)?;
+                writeln!(buf, "{}", header::primary!("Recursive"))?;
+                for dep in &recursive {
+                    writeln!(buf, "{}", mark(&state, dep))?;
+                }
             }
 
-            if !std::io::stdout().is_terminal() {
-                for keg in kegs {
-                    match keg {
-                        Keg::Formula(formula, _) => println!("{}", formula.base.name),
-                        Keg::Cask(cask, _) => println!("{}", cask.base.token),
-                    };
-                }
+            buf.flush()?;
 
-                return Ok(true);
-            }
+            Ok(true)
+        }
+    }
 
-            let width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+    #[derive(Args)]
+    pub struct Uses {
+        pub name: String,
 
-            let mut formulae = Vec::new();
-            let mut casks = Vec::new();
+        /// Show installed dependents only
+        #[clap(short, long, action)]
+        pub installed: bool,
 
-            for keg in kegs {
-                match keg {
-                    Keg::Formula(formula, installed) => {
-                        let name = if installed.is_some() {
-                            format!("{} {}", formula.base.name, pretty::bool(true))
-                        } else {
-                            formula.base.name
-                        };
+        /// Include formulae depending on the given one indirectly
+        #[clap(short, long, action)]
+        pub recursive: bool,
+    }
 
-                        formulae.push(name)
-                    }
-                    Keg::Cask(cask, installed) => {
-                        let name = if installed.is_some() {
-                            format!("{} {}", cask.base.token, pretty::bool(true))
-                        } else {
-                            cask.base.token
-                        };
+    impl Uses {
+        pub fn run(&self, state: State) -> anyhow::Result<bool> {
+            let key = match brewer_engine::resolve_formula(&state, &self.name) {
+                Resolution::Found(key) => key,
+                Resolution::Ambiguous(candidates) => {
+                    ambiguous(&self.name, &candidates);
+                    return Ok(false);
+                }
+                Resolution::Missing => {
+                    eprintln!("{}", header::warning!("Unknown formula {}", self.name));
+                    return Ok(false);
+                }
+            };
 
-                        casks.push(name)
-                    }
+            let mut buf = BufWriter::new(std::io::stdout());
+            let indexes = Indexes::new(&state);
+
+            for dependent in indexes.dependents(&key, self.recursive) {
+                if self.installed && !state.formulae.installed.contains_key(&dependent) {
+                    continue;
                 }
+
+                writeln!(buf, "{}", mark(&state, &dependent))?;
             }
 
-            formulae.sort_unstable();
-            casks.sort_unstable();
+            buf.flush()?;
 
-            let formulae = pretty::table(&formulae, width);
-            let casks = pretty::table(&casks, width);
+            Ok(true)
+        }
+    }
+
+    #[derive(Args)]
+    pub struct Leaves {
+        /// Only list leaves installed on request
+        #[clap(short = 'r', long, action, group = "reason")]
+        pub installed_on_request: bool,
+
+        /// Only list leaves installed as dependencies, they are likely safe to remove
+        #[clap(short = 'd', long, action, group = "reason")]
+        pub installed_as_dependency: bool,
+    }
 
+    impl Leaves {
+        pub fn run(&self, state: State) -> anyhow::Result<()> {
             let mut buf = BufWriter::new(std::io::stdout());
 
-            writeln!(buf, "{}", header::primary!("Formulae"))?;
-            formulae.print(&mut buf)?;
+            for name in brewer_engine::leaves(&state) {
+                let receipt = &state.formulae.installed[&name].receipt;
 
-            writeln!(buf)?;
+                if self.installed_on_request && !receipt.installed_on_request {
+                    continue;
+                }
 
-            writeln!(buf, "{}", header::primary!("Casks"))?;
-            casks.print(&mut buf)?;
+                if self.installed_as_dependency && !receipt.installed_as_dependency {
+                    continue;
+                }
 
-            Ok(true)
+                writeln!(buf, "{name}")?;
+            }
+
+            buf.flush()?;
+
+            Ok(())
         }
+    }
 
-        fn run_skim(&self, state: State) -> anyhow::Result<Vec<Keg>> {
-            let mut kegs: Vec<Keg> = Vec::new();
+    #[derive(Args)]
+    pub struct Missing {
+        /// Only check the given installed formulae
+        pub names: Vec<String>,
+    }
 
-            for formula in state.formulae.all.into_values() {
-                let name = formula.base.name.clone();
-                let keg = Keg::Formula(
-                    formula,
-                    Box::new(state.formulae.installed.get(&name).cloned()),
-                );
+    impl Missing {
+        /// Returns false when some dependencies are missing
+        pub fn run(&self, state: State) -> anyhow::Result<bool> {
+            let mut broken = brewer_engine::missing_dependencies(&state);
 
-                kegs.push(keg);
+            if !self.names.is_empty() {
+                broken.retain(|name, _| self.names.contains(name));
             }
 
-            for cask in state.casks.all.into_values() {
-                let token = cask.base.token.clone();
-                let keg = Keg::Cask(cask, state.casks.installed.get(&token).cloned());
+            let mut buf = BufWriter::new(std::io::stdout());
 
-                kegs.push(keg);
+            for (name, missing) in &broken {
+                writeln!(buf, "{}: {}", name.cyan(), missing.join(" ").red())?;
             }
 
-            let selected = select_skim(kegs, "Search", true)?;
+            buf.flush()?;
 
-            Ok(selected)
+            Ok(broken.is_empty())
         }
     }
 
-    #[derive(Clone)]
-    enum Keg {
-        Formula(
-            models::formula::Formula,
-            Box<Option<models::formula::installed::Formula>>,
-        ),
-        Cask(models::cask::Cask, Option<models::cask::installed::Cask>),
+    /// Highlights installed formulae
+    fn mark(state: &State, name: &str) -> String {
+        if state.formulae.installed.contains_key(name) {
+            name.green().to_string()
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn write_tree(w: &mut impl Write, tree: &Tree, prefix: &str) -> anyhow::Result<()> {
+        let last = tree.children.len().saturating_sub(1);
+
+        for (i, child) in tree.children.iter().enumerate() {
+            let (branch, indent) = if i == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            writeln!(w, "{prefix}{branch}{}", child.name)?;
+            write_tree(w, child, &format!("{prefix}{indent}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub mod collisions {
+    use std::collections::BTreeMap;
+    use std::io::{BufWriter, Write};
+
+    use clap::Args;
+    use colored::Colorize;
+
+    use brewer_engine::{Resolution, State};
+
+    use crate::pretty::header;
+
+    #[derive(Args)]
+    pub struct Collisions {}
+
+    /// Keys of formulae and casks sharing the same bare name
+    #[derive(Default)]
+    struct Group<'a> {
+        formulae: Vec<(&'a str, &'a str)>,
+        casks: Vec<(&'a str, &'a str)>,
     }
 
-    impl SkimItem for Keg {
-        fn text(&self) -> Cow<str> {
-            match self {
-                Keg::Formula(formula, _) => Cow::Borrowed(&formula.base.name),
-                Keg::Cask(cask, _) => Cow::Borrowed(&cask.base.token),
+    impl Collisions {
+        pub fn run(&self, state: State) -> anyhow::Result<bool> {
+            let mut groups: BTreeMap<&str, Group> = BTreeMap::new();
+
+            for (key, formula) in &state.formulae.all {
+                groups
+                    .entry(formula.base.name.as_str())
+                    .or_default()
+                    .formulae
+                    .push((key.as_str(), formula.base.tap.as_str()));
+            }
+
+            for (key, cask) in &state.casks.all {
+                groups
+                    .entry(cask.base.token.as_str())
+                    .or_default()
+                    .casks
+                    .push((key.as_str(), cask.base.tap.as_str()));
+            }
+
+            let mut buf = BufWriter::new(std::io::stdout());
+            let mut found = false;
+
+            for (name, mut group) in groups {
+                if group.formulae.len() + group.casks.len() < 2 {
+                    continue;
+                }
+
+                found = true;
+
+                group.formulae.sort_unstable();
+                group.casks.sort_unstable();
+
+                let picked = picked(&state, name);
+
+                writeln!(buf, "{}", header::primary!("{name}"))?;
+
+                let entries = group
+                    .formulae
+                    .iter()
+                    .map(|e| ("formula", e))
+                    .chain(group.casks.iter().map(|e| ("cask", e)));
+
+                for (kind, (key, tap)) in entries {
+                    let line = format!("{kind} {key} ({tap})");
+
+                    if picked.as_ref().is_some_and(|(k, p)| *k == kind && p == key) {
+                        writeln!(buf, "{} {}", line.bold(), "<- picked".green())?;
+                    } else {
+                        writeln!(buf, "{line}")?;
+                    }
+                }
+
+                if picked.is_none() {
+                    writeln!(buf, "{}", "Ambiguous, a full name is required".yellow())?;
+                }
+
+                writeln!(buf)?;
             }
-        }
 
-        fn preview(&self, _context: PreviewContext) -> ItemPreview {
-            let mut w = Vec::new();
+            buf.flush()?;
 
-            match self {
-                Keg::Formula(formula, installed) => {
-                    info_formula(&mut w, formula, installed.as_ref().as_ref()).unwrap()
-                }
-                Keg::Cask(cask, installed) => info_cask(&mut w, cask, installed.as_ref()).unwrap(),
-            };
+            if !found {
+                println!("No collisions");
+            }
 
-            let preview = String::from_utf8(w).unwrap();
-            let preview = textwrap::wrap(&preview, _context.width).join("\n");
+            Ok(true)
+        }
+    }
 
-            ItemPreview::AnsiText(preview)
+    /// Kind and key of the entry a bare name resolves to, formulae take precedence over casks
+    fn picked(state: &State, name: &str) -> Option<(&'static str, String)> {
+        match brewer_engine::resolve_formula(state, name) {
+            Resolution::Found(key) => Some(("formula", key)),
+            Resolution::Ambiguous(_) => None,
+            Resolution::Missing => match brewer_engine::resolve_cask(state, name) {
+                Resolution::Found(key) => Some(("cask", key)),
+                _ => None,
+            },
         }
     }
 }
 
-pub mod paths {
+pub mod hook {
     use clap::{Parser, Subcommand};
 
-    use crate::settings;
+    const DIRENV: &str = r#"# brewer: add `use brewer` to .envrc of a project with .brewer.toml
+use_brewer() {
+  watch_file .brewer.toml
+
+  if ! brewer project check --cached --missing >&2; then
+    log_status "brewer: missing packages, run \`brewer project install\`"
+  fi
+}"#;
 
     #[derive(Parser)]
-    pub struct Paths {
+    pub struct Hook {
         #[command(subcommand)]
         pub command: Commands,
     }
 
     #[derive(Subcommand)]
     pub enum Commands {
-        /// Show config path
-        Config,
+        /// direnv function checking project requirements, append it to ~/.config/direnv/direnvrc
+        Direnv,
     }
 
-    impl Paths {
+    impl Hook {
         pub fn run(&self) {
             match self.command {
-                Commands::Config => println!(
-                    "{}.toml",
-                    settings::Settings::config_file().to_string_lossy()
-                ),
+                Commands::Direnv => println!("{DIRENV}"),
             }
         }
     }
 }
 
-#[derive(Args)]
-pub struct Exists {
-    pub name: String,
+pub mod completions {
+    use std::collections::BTreeSet;
+    use std::io::{BufWriter, Write};
 
-    /// Treat given name as formula
-    #[clap(short, long, action)]
-    pub formula: bool,
+    use clap::{Args, CommandFactory};
+    use clap_complete::Shell;
 
-    /// Treat given name as cask
-    #[clap(short, long, action)]
-    pub cask: bool,
-}
+    use brewer_engine::search::Kind;
+    use brewer_engine::Engine;
 
-impl Exists {
-    pub fn run(&self, state: State) -> bool {
-        let formulae = state.formulae.all;
-        let casks = state.casks.all;
+    use crate::cli::Cli;
 
-        if self.cask {
-            return casks.contains_key(&self.name);
-        }
+    /// Subcommands taking formula or cask names, including aliases
+    const KEG_COMMANDS: &str = "info home cat desc exists install i uninstall r remove \
+link unlink upgrade u changes tag note star unstar deps uses xref size";
 
-        if self.formula {
-            return formulae.contains_key(&self.name);
-        }
+    /// Subcommands taking formula names only
+    const FORMULA_COMMANDS: &[&str] = &["link", "unlink", "changes", "deps", "uses", "xref"];
 
-        formulae.contains_key(&self.name) || casks.contains_key(&self.name)
-    }
+    const BASH: &str = r#"
+_brewer_names() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+
+    if [[ " __COMMANDS__ " == *" ${COMP_WORDS[1]} "* && "$cur" != -* ]]; then
+        COMPREPLY=($(brewer __complete --command "${COMP_WORDS[1]}" -- "$cur" 2>/dev/null))
+    else
+        _brewer "$@"
+    fi
 }
 
-pub mod install {
-    use std::borrow::Cow;
-    use std::io::{BufWriter, Write};
-    use std::ops::Deref;
+complete -F _brewer_names -o bashdefault -o default brewer"#;
 
-    use clap::Args;
-    use colored::Colorize;
-    use inquire::{Confirm, InquireError};
-    use skim::{ItemPreview, PreviewContext, SkimItem};
+    const ZSH: &str = r#"
+_brewer_names() {
+    if (( CURRENT > 2 )) && [[ " __COMMANDS__ " == *" ${words[2]} "* && ${words[CURRENT]} != -* ]]; then
+        local -a names
+        names=(${(f)"$(brewer __complete --command ${words[2]} -- ${words[CURRENT]} 2>/dev/null)"})
+        compadd -a names && return
+    fi
 
-    use brewer_core::models;
-    use brewer_engine::{Engine, State};
+    _brewer "$@"
+}
 
-    use crate::cli::{info_cask, info_formula, select_skim};
-    use crate::pretty::header;
+compdef _brewer_names brewer"#;
+
+    const FISH: &str = r#"
+complete -c brewer -n "__fish_seen_subcommand_from __COMMANDS__" -f -a "(brewer __complete --command (commandline -opc)[2] -- (commandline -ct) 2>/dev/null)""#;
 
     #[derive(Args)]
-    pub struct Install {
-        pub names: Vec<String>,
+    pub struct Completions {
+        /// Shell to generate the script for
+        #[clap(value_enum)]
+        pub shell: Shell,
+    }
 
-        #[clap(short, long, action, group = "type")]
+    impl Completions {
+        pub fn run(&self) {
+            clap_complete::generate(
+                self.shell,
+                &mut Cli::command(),
+                "brewer",
+                &mut std::io::stdout(),
+            );
+
+            // names come from the cache, so they are completed by calling back into brewer
+            let dynamic = match self.shell {
+                Shell::Bash => BASH,
+                Shell::Zsh => ZSH,
+                Shell::Fish => FISH,
+                _ => return,
+            };
+
+            println!("{}", dynamic.replace("__COMMANDS__", KEG_COMMANDS));
+        }
+    }
+
+    /// Prints cached formula and cask names starting with the prefix, used by completion scripts
+    #[derive(Args)]
+    pub struct Complete {
+        /// Only complete formulae
+        #[clap(long, action, group = "type")]
         pub formula: bool,
 
-        #[clap(short, long, action, group = "type")]
+        /// Only complete casks
+        #[clap(long, action, group = "type")]
         pub cask: bool,
 
-        /// Confirm
-        #[clap(short, long, action)]
-        pub yes: bool,
+        /// Subcommand being completed, formula-only ones complete formulae only
+        #[clap(long)]
+        pub command: Option<String>,
+
+        #[clap(default_value = "")]
+        pub prefix: String,
     }
 
-    impl Install {
-        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
-            let state = engine.cache_or_latest()?;
+    impl Complete {
+        pub fn run(&self, engine: Engine) -> anyhow::Result<()> {
+            // completion must be instant, a missing or stale cache is used as is
+            let Some(index) = engine.cached_search_index()? else {
+                return Ok(());
+            };
 
-            let kegs = self.get_kegs(state)?;
+            let formula = self.formula
+                || self
+                    .command
+                    .as_deref()
+                    .is_some_and(|c| FORMULA_COMMANDS.contains(&c));
+
+            let names: BTreeSet<_> = index
+                .entries
+                .iter()
+                .filter(|e| match e.kind {
+                    Kind::Formula => !self.cask,
+                    Kind::Cask => !formula,
+                })
+                .flat_map(|e| std::iter::once(&e.name).chain(&e.aliases))
+                .filter(|name| name.starts_with(&self.prefix))
+                .collect();
 
-            if kegs.is_empty() {
-                Ok(())
-            } else {
-                if self.yes || plan(&kegs)? {
-                    engine.install(kegs)?;
-                }
+            let mut buf = BufWriter::new(std::io::stdout());
 
-                Ok(())
+            for name in names {
+                writeln!(buf, "{name}")?;
             }
-        }
 
-        fn get_kegs(&self, state: State) -> anyhow::Result<Vec<models::Keg>> {
-            if self.names.is_empty() {
-                self.get_kegs_from_skim(state)
-            } else {
-                self.get_kegs_from_args(state)
-            }
+            buf.flush()?;
+
+            Ok(())
         }
+    }
+}
 
-        fn get_kegs_from_args(&self, mut state: State) -> anyhow::Result<Vec<models::Keg>> {
-            let mut kegs = Vec::new();
+pub mod bundle {
+    use std::path::PathBuf;
 
-            for name in &self.names {
-                let keg = if self.formula {
-                    if state.formulae.installed.contains_key(name) {
-                        println!(
-                            "{}",
-                            header::warning!("Formula {name} is already installed, skipping")
-                        );
-                        continue;
-                    }
+    use clap::{Args, Subcommand, ValueHint};
 
-                    state.formulae.all.remove(name).map(models::Keg::Formula)
-                } else if self.cask {
-                    if state.casks.installed.contains_key(name) {
-                        println!(
-                            "{}",
-                            header::warning!("Cask {name} is already installed, skipping")
-                        );
-                        continue;
-                    }
+    use brewer_core::{models, OFFICIAL_TAPS};
+    use brewer_engine::{Engine, Resolution, State};
 
-                    state.casks.all.remove(name).map(models::Keg::Cask)
-                } else {
-                    if state.formulae.installed.contains_key(name) {
-                        println!(
+    use crate::brewfile::{Brewfile, BREWFILE};
+    use crate::cli::install::plan;
+    use crate::cli::{ambiguous, wait_for_brew};
+    use crate::error::NotFound;
+    use crate::packages::Packages;
+    use crate::pretty::header;
+    use crate::project::{Manifest, MANIFEST_FILE};
+
+    #[derive(Args)]
+    pub struct Bundle {
+        #[command(subcommand)]
+        pub command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Write taps, formulae installed on request and casks to a Brewfile, marking pinned formulae
+        Dump {
+            /// Brewfile path
+            #[clap(long, default_value = BREWFILE, value_hint = ValueHint::FilePath)]
+            file: PathBuf,
+
+            /// Overwrite an existing Brewfile
+            #[clap(long, action)]
+            force: bool,
+        },
+
+        /// Tap and install everything listed in a Brewfile, pinning formulae marked with `pin: true`
+        Install {
+            /// Brewfile path
+            #[clap(long, default_value = BREWFILE, value_hint = ValueHint::FilePath)]
+            file: PathBuf,
+
+            /// Confirm
+            #[clap(short, long, action)]
+            yes: bool,
+        },
+    }
+
+    impl Bundle {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            match &self.command {
+                Commands::Dump { file, force } => {
+                    if file.exists() && !force {
+                        eprintln!(
                             "{}",
-                            header::warning!("Formula {name} is already installed, skipping")
+                            header::warning!(
+                                "{} already exists, use --force to overwrite",
+                                file.to_string_lossy()
+                            )
                         );
-                        continue;
+
+                        return Ok(false);
                     }
 
-                    if state.casks.installed.contains_key(name) {
-                        println!(
-                            "{}",
-                            header::warning!("Cask {name} is already installed, skipping")
+                    let state = engine.cache_or_latest()?;
+
+                    std::fs::write(file, dump(&state).render())?;
+
+                    Ok(true)
+                }
+                Commands::Install { file, yes } => {
+                    if !file.is_file() {
+                        return Err(
+                            NotFound(format!("{} not found", file.to_string_lossy())).into()
                         );
-                        continue;
                     }
 
-                    state
-                        .formulae
-                        .all
-                        .remove(name)
-                        .map(models::Keg::Formula)
-                        .or_else(|| state.casks.all.remove(name).map(models::Keg::Cask))
-                };
+                    let brewfile = Brewfile::load(file)?;
 
-                let Some(keg) = keg else {
-                    println!(
-                        "{}",
-                        header::warning!("Unknown formula or cask {name}, skipping")
-                    );
-                    continue;
-                };
+                    install(&mut engine, &brewfile, *yes)?;
 
-                kegs.push(keg);
+                    Ok(true)
+                }
             }
-
-            Ok(kegs)
         }
+    }
 
-        fn get_kegs_from_skim(&self, state: State) -> anyhow::Result<Vec<models::Keg>> {
-            let mut non_installed: Vec<Keg> =
-                Vec::with_capacity(state.formulae.all.len() + state.casks.all.len());
+    #[derive(Args)]
+    pub struct ImportBrewfile {
+        #[clap(value_hint = ValueHint::FilePath)]
+        pub path: PathBuf,
 
-            for formula in state.formulae.all.into_values() {
-                if !state.formulae.installed.contains_key(&formula.base.name) {
-                    non_installed.push(formula.into());
-                }
+        /// Add entries to the project manifest instead of installing them
+        #[clap(short, long, action)]
+        pub manifest: bool,
+
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+    }
+
+    impl ImportBrewfile {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
+            let brewfile = Brewfile::load(&self.path)?;
+
+            if self.manifest {
+                to_manifest(&brewfile)
+            } else {
+                install(&mut engine, &brewfile, self.yes)
             }
+        }
+    }
 
-            for cask in state.casks.all.into_values() {
-                if !state.casks.installed.contains_key(&cask.base.token) {
-                    non_installed.push(cask.into());
-                }
+    #[derive(Args)]
+    pub struct Export {}
+
+    impl Export {
+        pub fn run(&self, engine: &mut Engine) -> anyhow::Result<()> {
+            let state = engine.cache_or_latest()?;
+
+            print!("{}", Packages::from(dump(&state)).render());
+
+            Ok(())
+        }
+    }
+
+    #[derive(Args)]
+    pub struct Import {
+        #[clap(value_hint = ValueHint::FilePath)]
+        pub path: PathBuf,
+
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+    }
+
+    impl Import {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
+            if !self.path.is_file() {
+                return Err(NotFound(format!("{} not found", self.path.to_string_lossy())).into());
             }
 
-            let selected = select_skim(non_installed, "Install", true)?
-                .into_iter()
-                .map(|k| k.0)
-                .collect();
+            let brewfile: Brewfile = Packages::load(&self.path)?.into();
 
-            Ok(selected)
+            install(&mut engine, &brewfile, self.yes)
         }
     }
 
-    fn plan(kegs: &Vec<models::Keg>) -> anyhow::Result<bool> {
-        let mut w = BufWriter::new(std::io::stderr());
+    #[derive(Args)]
+    pub struct ImportLeaves {
+        /// File with `brew leaves` output, stdin if omitted
+        #[clap(value_hint = ValueHint::FilePath)]
+        pub path: Option<PathBuf>,
 
-        writeln!(
-            w,
-            "{}",
-            header::primary!("The following kegs will be installed")
-        )?;
+        /// Add formulae to the project manifest instead of installing them
+        #[clap(short, long, action)]
+        pub manifest: bool,
 
-        for keg in kegs {
-            match &keg {
-                models::Keg::Formula(f) => writeln!(
-                    w,
-                    "{} {} (Formula)",
-                    f.base.name.cyan(),
-                    f.base.versions.stable
-                )?,
-                models::Keg::Cask(c) => {
-                    writeln!(w, "{} {} (Cask)", c.base.token.cyan(), c.base.version)?
-                }
+        /// Confirm
+        #[clap(short, long, action)]
+        pub yes: bool,
+    }
+
+    impl ImportLeaves {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
+            let text = match &self.path {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => std::io::read_to_string(std::io::stdin())?,
+            };
+
+            let brewfile = Brewfile {
+                formulae: text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(String::from)
+                    .collect(),
+                ..Brewfile::default()
+            };
+
+            if self.manifest {
+                to_manifest(&brewfile)
+            } else {
+                install(&mut engine, &brewfile, self.yes)
             }
         }
+    }
 
-        writeln!(w)?;
+    /// Adds formulae and casks to the manifest in the current directory, creating it if needed
+    fn to_manifest(brewfile: &Brewfile) -> anyhow::Result<()> {
+        let path = std::env::current_dir()?.join(MANIFEST_FILE);
 
-        let mut executables: Vec<String> = Vec::new();
+        let mut manifest = if path.is_file() {
+            Manifest::load(&path)?
+        } else {
+            Manifest::default()
+        };
 
-        for k in kegs {
-            if let models::Keg::Formula(f) = &k {
-                for e in &f.executables {
-                    executables.push(e.purple().to_string());
-                }
+        manifest.merge(&brewfile.formulae, &brewfile.casks);
+
+        std::fs::write(&path, manifest.render())?;
+
+        for tap in &brewfile.taps {
+            eprintln!(
+                "{}",
+                header::warning!("The manifest does not track taps, make sure {tap} is tapped")
+            );
+        }
+
+        println!(
+            "{}",
+            header::primary!(
+                "Added {} formulae and {} casks to {}",
+                brewfile.formulae.len(),
+                brewfile.casks.len(),
+                path.to_string_lossy()
+            )
+        );
+
+        Ok(())
+    }
+
+    fn dump(state: &State) -> Brewfile {
+        let official = |tap: &str| OFFICIAL_TAPS.contains(&tap);
+
+        let mut brewfile = Brewfile::default();
+
+        for formula in state.formulae.installed.values() {
+            if !formula.receipt.installed_on_request {
+                continue;
+            }
+
+            let base = &formula.upstream.base;
+
+            if !official(&base.tap) {
+                brewfile.taps.push(base.tap.clone());
             }
-        }
 
-        if !executables.is_empty() {
-            writeln!(
-                w,
-                "{}",
-                header::primary!("The following executables will be provided")
-            )?;
-            writeln!(w, "{}", executables.join(" "))?;
-            writeln!(w)?;
+            if formula.pinned {
+                brewfile.pinned.push(base.qualified_name().to_string());
+            }
+
+            brewfile.formulae.push(base.qualified_name().to_string());
         }
 
-        w.flush()?;
+        for cask in state.casks.installed.values() {
+            let base = &cask.upstream.base;
 
-        let result = Confirm::new("Proceed?").with_default(false).prompt();
+            if !official(&base.tap) {
+                brewfile.taps.push(base.tap.clone());
+            }
 
-        match result {
-            Ok(value) => Ok(value),
-            Err(e) => match e {
-                InquireError::OperationCanceled => Ok(false),
-                e => Err(e.into()),
-            },
+            brewfile.casks.push(base.qualified_token().to_string());
         }
-    }
 
-    #[derive(Clone)]
-    struct Keg(models::Keg);
+        brewfile.taps.retain(|t| !t.is_empty());
 
-    impl From<models::formula::Formula> for Keg {
-        fn from(value: models::formula::Formula) -> Self {
-            Keg(value.into())
+        for names in [
+            &mut brewfile.taps,
+            &mut brewfile.formulae,
+            &mut brewfile.casks,
+        ] {
+            names.sort_unstable();
+            names.dedup();
         }
+
+        brewfile
     }
 
-    impl From<models::cask::Cask> for Keg {
-        fn from(value: models::cask::Cask) -> Self {
-            Keg(value.into())
+    fn install(engine: &mut Engine, brewfile: &Brewfile, yes: bool) -> anyhow::Result<()> {
+        let tapped = engine.taps()?;
+
+        let untapped: Vec<_> = brewfile
+            .taps
+            .iter()
+            .filter(|t| !tapped.contains(*t))
+            .collect();
+
+        for tap in &untapped {
+            engine.tap(tap)?;
         }
-    }
 
-    impl Deref for Keg {
-        type Target = models::Keg;
+        // entries of new taps are only known after the cache is rebuilt
+        let mut state = if untapped.is_empty() {
+            engine.cache_or_latest()?
+        } else {
+            let latest = engine.fetch_latest()?;
+            engine.update_cache(&latest)?;
 
-        fn deref(&self) -> &Self::Target {
-            &self.0
+            engine.cache()?.unwrap_or(latest)
+        };
+
+        let mut kegs: Vec<models::Keg> = Vec::new();
+
+        // formulae to pin and whether they are installed already
+        let mut pins: Vec<(String, bool)> = Vec::new();
+
+        for name in &brewfile.formulae {
+            match brewer_engine::resolve_formula(&state, name) {
+                Resolution::Found(key) => {
+                    let Some(formula) = state.formulae.all.remove(&key) else {
+                        continue;
+                    };
+
+                    let installed = state.formulae.installed.get(&formula.base.name);
+
+                    if brewfile.pinned.contains(name) && !installed.is_some_and(|f| f.pinned) {
+                        pins.push((formula.base.name.clone(), installed.is_some()));
+                    }
+
+                    if installed.is_none() {
+                        kegs.push(formula.into());
+                    }
+                }
+                Resolution::Ambiguous(candidates) => ambiguous(name, &candidates),
+                Resolution::Missing => {
+                    println!("{}", header::warning!("Unknown formula {name}, skipping"))
+                }
+            }
         }
-    }
 
-    impl SkimItem for Keg {
-        fn text(&self) -> Cow<str> {
-            match &self.0 {
-                models::Keg::Formula(formula) => Cow::Borrowed(&formula.base.name),
-                models::Keg::Cask(cask) => Cow::Borrowed(&cask.base.token),
+        for name in &brewfile.casks {
+            match brewer_engine::resolve_cask(&state, name) {
+                Resolution::Found(key) => {
+                    let Some(cask) = state.casks.all.remove(&key) else {
+                        continue;
+                    };
+
+                    if !state.casks.installed.contains_key(&cask.base.token) {
+                        kegs.push(cask.into());
+                    }
+                }
+                Resolution::Ambiguous(candidates) => ambiguous(name, &candidates),
+                Resolution::Missing => {
+                    println!("{}", header::warning!("Unknown cask {name}, skipping"))
+                }
             }
         }
 
-        fn preview(&self, _context: PreviewContext) -> ItemPreview {
-            let mut buf = Vec::new();
+        let installed = if kegs.is_empty() {
+            println!("{}", header::primary!("Everything is installed"));
+            true
+        } else if yes || plan(&kegs, "installed")? {
+            wait_for_brew(engine)?;
 
-            match &self.0 {
-                models::Keg::Formula(formula) => info_formula(&mut buf, formula, None).unwrap(),
-                models::Keg::Cask(cask) => info_cask(&mut buf, cask, None).unwrap(),
-            };
+            engine.install(kegs)?;
+            true
+        } else {
+            false
+        };
 
-            let preview = String::from_utf8(buf).unwrap();
+        let pins: Vec<_> = pins
+            .into_iter()
+            .filter(|(_, present)| installed || *present)
+            .map(|(name, _)| name)
+            .collect();
 
-            ItemPreview::AnsiText(preview)
+        if !pins.is_empty() {
+            engine.pin(&pins)?;
+
+            println!("{}", header::primary!("Pinned {}", pins.join(", ")));
         }
+
+        Ok(())
     }
 }
 
-pub mod uninstall {
-    use std::borrow::Cow;
+pub mod project {
     use std::io::{BufWriter, Write};
 
-    use clap::Args;
+    use anyhow::anyhow;
+    use clap::{Args, Subcommand};
     use colored::Colorize;
-    use inquire::{Confirm, InquireError};
-    use skim::{ItemPreview, PreviewContext, SkimItem};
 
     use brewer_core::models;
     use brewer_engine::{Engine, State};
 
-    use crate::cli::{info_cask, info_formula, select_skim};
+    use crate::cli::install::plan;
+    use crate::cli::wait_for_brew;
+    use crate::error::NotFound;
+    use crate::pretty;
     use crate::pretty::header;
+    use crate::project::{Manifest, MANIFEST_FILE};
 
     #[derive(Args)]
-    pub struct Uninstall {
-        pub names: Vec<String>,
-
-        #[clap(short, long, action, group = "type")]
-        pub formula: bool,
-
-        #[clap(short, long, action, group = "type")]
-        pub cask: bool,
-
-        /// Confirm
-        #[clap(short, long, action)]
-        pub yes: bool,
+    pub struct Project {
+        #[command(subcommand)]
+        pub command: Commands,
     }
 
-    impl Uninstall {
-        pub fn run(&self, mut engine: Engine) -> anyhow::Result<()> {
-            let state = engine.cache_or_latest()?;
-
-            let kegs = self.get_kegs(state)?;
+    #[derive(Subcommand)]
+    pub enum Commands {
+        /// Verify that required formulae and casks are installed
+        Check {
+            /// Use the cache only, never refresh it
+            #[clap(long, action)]
+            cached: bool,
+
+            /// Print missing formulae and casks only
+            #[clap(long, action)]
+            missing: bool,
+        },
+
+        /// Install missing formulae and casks
+        Install {
+            /// Confirm
+            #[clap(short, long, action)]
+            yes: bool,
+        },
+    }
 
-            if kegs.is_empty() {
-                Ok(())
-            } else {
-                let kegs = kegs
-                    .into_iter()
-                    .map(|k| match k {
-                        Keg::Formula(formula) => formula.upstream.into(),
-                        Keg::Cask(cask) => cask.upstream.into(),
-                    })
-                    .collect();
+    impl Project {
+        pub fn run(&self, mut engine: Engine) -> anyhow::Result<bool> {
+            let cwd = std::env::current_dir()?;
 
-                if self.yes || plan(&kegs)? {
-                    engine.uninstall(kegs)?;
-                }
+            let Some(path) = Manifest::find(&cwd) else {
+                return Err(NotFound(format!("{MANIFEST_FILE} not found")).into());
+            };
 
-                Ok(())
-            }
-        }
+            let manifest = Manifest::load(&path)?;
 
-        fn get_kegs(&self, state: State) -> anyhow::Result<Vec<Keg>> {
-            if self.names.is_empty() {
-                self.get_kegs_from_skim(state)
-            } else {
-                self.get_kegs_from_args(state)
-            }
-        }
+            match self.command {
+                Commands::Check { cached, missing } => {
+                    let state = if cached {
+                        let Some(state) = engine.cache()? else {
+                            return Err(anyhow!("cache is empty, run `brewer update`"));
+                        };
 
-        fn get_kegs_from_args(&self, mut state: State) -> anyhow::Result<Vec<Keg>> {
-            let mut kegs = Vec::new();
+                        state
+                    } else {
+                        engine.cache_or_latest()?
+                    };
 
-            for name in &self.names {
-                let keg = if self.formula {
-                    if !state.formulae.installed.contains_key(name) {
-                        println!(
-                            "{}",
-                            header::warning!("Formula {name} is not installed, skipping")
-                        );
-                        continue;
-                    }
+                    check(&manifest, &state, missing)
+                }
+                Commands::Install { yes } => {
+                    let state = engine.cache_or_latest()?;
+                    let missing = missing(&manifest, state);
 
-                    state.formulae.installed.remove(name).map(Keg::Formula)
-                } else if self.cask {
-                    if !state.casks.installed.contains_key(name) {
-                        println!(
-                            "{}",
-                            header::warning!("Cask {name} is not installed, skipping")
-                        );
-                        continue;
+                    if missing.is_empty() {
+                        return Ok(true);
                     }
 
-                    state.casks.installed.remove(name).map(Keg::Cask)
-                } else {
-                    state
-                        .formulae
-                        .installed
-                        .remove(name)
-                        .map(Keg::Formula)
-                        .or_else(|| state.casks.installed.remove(name).map(Keg::Cask))
-                };
-
-                let Some(keg) = keg else {
-                    println!(
-                        "{}",
-                        header::warning!("Formula or cask {name} is not installed skipping")
-                    );
-                    continue;
-                };
-
-                kegs.push(keg);
-            }
-
-            Ok(kegs)
-        }
+                    if yes || plan(&missing, "installed")? {
+                        wait_for_brew(&engine)?;
 
-        fn get_kegs_from_skim(&self, state: State) -> anyhow::Result<Vec<Keg>> {
-            let mut installed: Vec<Keg> =
-                Vec::with_capacity(state.formulae.installed.len() + state.casks.installed.len());
-
-            for formula in state
-                .formulae
-                .installed
-                .into_values()
-                .filter(|f| f.receipt.installed_on_request)
-            {
-                installed.push(formula.into());
-            }
+                        engine.install(missing)?;
+                    }
 
-            for cask in state.casks.installed.into_values() {
-                installed.push(cask.into());
+                    Ok(true)
+                }
             }
-
-            let selected = select_skim(installed, "Uninstall", true)?
-                .into_iter()
-                .collect();
-
-            Ok(selected)
         }
     }
 
-    fn plan(kegs: &Vec<models::Keg>) -> anyhow::Result<bool> {
-        let mut w = BufWriter::new(std::io::stderr());
+    fn check(manifest: &Manifest, state: &State, missing_only: bool) -> anyhow::Result<bool> {
+        let mut w = BufWriter::new(std::io::stdout());
 
-        writeln!(
-            w,
-            "{}",
-            header::primary!("The following kegs will be uninstalled")
-        )?;
+        let mut success = true;
 
-        for keg in kegs {
-            match &keg {
-                models::Keg::Formula(f) => writeln!(
-                    w,
-                    "{} {} (Formula)",
-                    f.base.name.cyan(),
-                    f.base.versions.stable
-                )?,
-                models::Keg::Cask(c) => {
-                    writeln!(w, "{} {} (Cask)", c.base.token.cyan(), c.base.version)?
-                }
-            }
-        }
+        let formulae = manifest
+            .formulae
+            .iter()
+            .map(|n| (n, "Formula", state.formulae.installed.contains_key(n)));
 
-        writeln!(w)?;
+        let casks = manifest
+            .casks
+            .iter()
+            .map(|n| (n, "Cask", state.casks.installed.contains_key(n)));
 
-        let mut executables: Vec<String> = Vec::new();
+        for (name, kind, installed) in formulae.chain(casks) {
+            success &= installed;
 
-        for k in kegs {
-            if let models::Keg::Formula(f) = &k {
-                for e in &f.executables {
-                    executables.push(e.purple().to_string());
-                }
+            if missing_only && installed {
+                continue;
             }
-        }
 
-        if !executables.is_empty() {
-            writeln!(
-                w,
-                "{}",
-                header::primary!("The following executables will be removed")
-            )?;
-            writeln!(w, "{}", executables.join(" "))?;
-            writeln!(w)?;
+            writeln!(w, "{} {} ({kind})", pretty::bool(installed), name.cyan())?;
         }
 
         w.flush()?;
 
-        let result = Confirm::new("Proceed?").with_default(false).prompt();
-
-        match result {
-            Ok(value) => Ok(value),
-            Err(e) => match e {
-                InquireError::OperationCanceled => Ok(false),
-                e => Err(e.into()),
-            },
-        }
-    }
-
-    #[derive(Clone)]
-    pub enum Keg {
-        Formula(models::formula::installed::Formula),
-        Cask(models::cask::installed::Cask),
+        Ok(success)
     }
 
-    impl From<models::formula::installed::Formula> for Keg {
-        fn from(value: models::formula::installed::Formula) -> Self {
-            Keg::Formula(value)
-        }
-    }
+    fn missing(manifest: &Manifest, mut state: State) -> Vec<models::Keg> {
+        let mut kegs = Vec::new();
 
-    impl From<models::cask::installed::Cask> for Keg {
-        fn from(value: models::cask::installed::Cask) -> Self {
-            Keg::Cask(value)
-        }
-    }
+        for name in &manifest.formulae {
+            if state.formulae.installed.contains_key(name) {
+                continue;
+            }
 
-    impl SkimItem for Keg {
-        fn text(&self) -> Cow<str> {
-            match &self {
-                Keg::Formula(formula) => Cow::Borrowed(&formula.upstream.base.name),
-                Keg::Cask(cask) => Cow::Borrowed(&cask.upstream.base.token),
+            match state.formulae.all.remove(name) {
+                Some(formula) => kegs.push(formula.into()),
+                None => println!("{}", header::warning!("Unknown formula {name}, skipping")),
             }
         }
 
-        fn preview(&self, _context: PreviewContext) -> ItemPreview {
-            let mut buf = Vec::new();
-
-            match &self {
-                Keg::Formula(formula) => {
-                    info_formula(&mut buf, &formula.upstream, Some(formula)).unwrap()
-                }
-                Keg::Cask(cask) => info_cask(&mut buf, &cask.upstream, Some(cask)).unwrap(),
-            };
-
-            let preview = String::from_utf8(buf).unwrap();
+        for name in &manifest.casks {
+            if state.casks.installed.contains_key(name) {
+                continue;
+            }
 
-            ItemPreview::AnsiText(preview)
+            match state.casks.all.remove(name) {
+                Some(cask) => kegs.push(cask.into()),
+                None => println!("{}", header::warning!("Unknown cask {name}, skipping")),
+            }
         }
+
+        kegs
     }
 }
 
 fn select_skim<T, I>(items: I, header: &str, multi: bool) -> anyhow::Result<Vec<T>>
+where
+    T: SkimItem + Clone,
+    I: IntoIterator<Item = T>,
+{
+    select_skim_with(items, header, multi, None).map(|(selected, _)| selected)
+}
+
+/// Like `select_skim`, the selection can also be accepted with the `expect` key.
+/// The key which accepted it is returned along with the selection
+fn select_skim_with<T, I>(
+    items: I,
+    header: &str,
+    multi: bool,
+    expect: Option<&str>,
+) -> anyhow::Result<(Vec<T>, Option<Key>)>
 where
     T: SkimItem + Clone,
     I: IntoIterator<Item = T>,
@@ -1275,11 +6742,16 @@ where
         .preview(Some("")) // preview should be specified to enable preview window
         .preview_window(Some("60%"))
         .header(Some(header))
+        .expect(expect.map(String::from))
         .build()?;
 
     let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
 
-    for item in items.into_iter() {
+    // stores are hash maps, sorting keeps the list in the same order between runs
+    let mut items: Vec<T> = items.into_iter().collect();
+    items.sort_by(|a, b| a.text().cmp(&b.text()));
+
+    for item in items {
         tx.send(Arc::new(item))?;
     }
 
@@ -1288,7 +6760,7 @@ where
     match Skim::run_with(&options, Some(rx)) {
         Some(output) => {
             if output.is_abort {
-                return Ok(Vec::new());
+                return Err(Cancelled.into());
             }
 
             let mut selected = Vec::new();
@@ -1299,9 +6771,9 @@ where
                 selected.push(item);
             }
 
-            Ok(selected)
+            Ok((selected, Some(output.final_key)))
         }
-        None => Ok(Vec::new()),
+        None => Ok((Vec::new(), None)),
     }
 }
 
@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use config::ConfigError;
+use log::warn;
+
+use brewer_core::transcript::Transcript;
+use brewer_core::Brew;
+use brewer_engine::Engine;
+
+use crate::cli::Cli;
+use crate::settings::{AutoUpdate, Settings};
+use crate::{user, watchdog};
+
+/// Settings, brew and engine constructed on first use, so that cheap commands stay instant.
+/// Global flags are applied here
+pub struct Context {
+    config: Option<PathBuf>,
+    db: Option<PathBuf>,
+    offline: bool,
+    settings: Option<Settings>,
+
+    /// Shared by every brew constructed, present when the session is recorded
+    transcript: Option<Arc<Transcript>>,
+}
+
+impl Context {
+    pub fn new(cli: &Cli) -> Context {
+        Context {
+            config: cli.config.clone(),
+            db: cli.db.clone(),
+            offline: cli.offline,
+            settings: None,
+            transcript: cli.record.as_ref().map(|_| Arc::default()),
+        }
+    }
+
+    pub fn transcript(&self) -> Option<Arc<Transcript>> {
+        self.transcript.clone()
+    }
+
+    pub fn settings(&mut self) -> anyhow::Result<&Settings> {
+        if self.settings.is_none() {
+            // parsing is skipped when there is nothing to parse
+            let settings = if self.config.is_none() && !Settings::configured() {
+                Settings::default()
+            } else {
+                Settings::load(self.config.as_deref()).map_err(|e| self.explain(e))?
+            };
+
+            self.settings = Some(settings);
+        }
+
+        Ok(self.settings.as_ref().unwrap())
+    }
+
+    /// Points at the offending key when a value in the config file is why loading failed
+    fn explain(&self, e: ConfigError) -> anyhow::Error {
+        let path = self.config_path();
+
+        let problem = Settings::validate(&path, false)
+            .ok()
+            .and_then(|problems| problems.into_iter().next());
+
+        match problem {
+            Some(problem) => anyhow::anyhow!("{}", problem.located(&path)),
+            None => e.into(),
+        }
+    }
+
+    /// Config file in use, its extension is resolved by the config loader unless given explicitly
+    pub fn config_file(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(Settings::config_file)
+    }
+
+    /// Config file in use with the extension resolved
+    pub fn config_path(&self) -> PathBuf {
+        let config = self.config_file();
+
+        if config.extension().is_some() {
+            config
+        } else {
+            config.with_extension("toml")
+        }
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        if let Some(db) = &self.db {
+            return db.clone();
+        }
+
+        // a database created by root in a user's cache would be read-only for the user
+        let name = user::file_name("brewer.db");
+
+        if let Some(dir) = dirs::cache_dir() {
+            dir.join(name)
+        } else {
+            name.into()
+        }
+    }
+
+    pub fn brew(&mut self) -> anyhow::Result<Brew> {
+        let settings = &self.settings()?.homebrew;
+
+        let brew = Brew::default();
+
+        let brew = brewer_core::BrewBuilder::default()
+            .path(settings.path.clone().unwrap_or(brew.path))
+            .prefix(settings.prefix.clone().unwrap_or(brew.prefix))
+            .json_version(settings.json_version.clone().unwrap_or(brew.json_version))
+            .analytics(settings.analytics.unwrap_or(brew.analytics))
+            .auto_update(settings.auto_update)
+            .cache(settings.cache.clone())
+            .temp(settings.temp.clone())
+            .tap_priority(settings.tap_priority.clone())
+            .wsl(settings.wsl.clone().or(brew.wsl))
+            .timeout(settings.timeout.unwrap_or(brew.timeout))
+            .jobs(settings.jobs)
+            .nice(settings.nice)
+            .ionice(settings.ionice)
+            .watchdog(Some(Arc::new(watchdog::Prompt)))
+            .transcript(self.transcript.clone())
+            .build()?;
+
+        Ok(brew)
+    }
+
+    pub fn engine(&mut self) -> anyhow::Result<Engine> {
+        let db = user::writable(self.db_path())?;
+        let store = brewer_engine::store::Store::open(db.as_path())?;
+        let brew = self.brew()?;
+        let offline = self.offline;
+
+        let settings = self.settings()?;
+
+        let mut engine_builder = brewer_engine::EngineBuilder::default();
+
+        engine_builder.store(store);
+
+        if let AutoUpdate::Every(duration) = settings.cache.auto_update {
+            engine_builder.cache_duration(Some(duration));
+        } else {
+            engine_builder.cache_duration(None);
+        }
+
+        engine_builder.verify_installed(settings.cache.verify_installed);
+        engine_builder.offline(offline);
+
+        if settings.github.enabled {
+            let token = settings
+                .github
+                .token
+                .clone()
+                .or_else(|| std::env::var("GITHUB_TOKEN").ok());
+
+            match token {
+                Some(token) => {
+                    engine_builder.github(Some(brewer_engine::GitHub {
+                        token,
+                        ttl: settings.github.ttl,
+                    }));
+                }
+                None => warn!("GitHub metadata is enabled, but no token is set"),
+            }
+        }
+
+        engine_builder.brew(brew);
+
+        let engine = engine_builder.build()?;
+
+        Ok(engine)
+    }
+}
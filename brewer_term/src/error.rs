@@ -0,0 +1,212 @@
+use serde::Serialize;
+
+use brewer_core::BrewFailure;
+
+use crate::pretty::header;
+
+/// Failure kinds scripts can branch on
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    NotFound,
+    Network,
+    Brew,
+    Cache,
+    Cancelled,
+    Other,
+}
+
+/// Requested formula, cask, tap or file does not exist
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The user declined a confirmation or backed out of a picker
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled by the user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl Kind {
+    /// Classifies the error by the first recognized cause in its chain
+    pub fn of(e: &anyhow::Error) -> Kind {
+        for cause in e.chain() {
+            if cause.is::<NotFound>() {
+                return Kind::NotFound;
+            }
+
+            if cause.is::<Cancelled>() {
+                return Kind::Cancelled;
+            }
+
+            if cause.is::<reqwest::Error>() {
+                return Kind::Network;
+            }
+
+            if cause.is::<BrewFailure>() {
+                return Kind::Brew;
+            }
+
+            if cause.is::<jammdb::Error>() {
+                return Kind::Cache;
+            }
+
+            if let Some(
+                inquire::InquireError::OperationCanceled
+                | inquire::InquireError::OperationInterrupted,
+            ) = cause.downcast_ref()
+            {
+                return Kind::Cancelled;
+            }
+        }
+
+        Kind::Other
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Kind::NotFound => Some("check the name or run `brewer update` to refresh the index"),
+            Kind::Network => Some("check your connection and try again"),
+            Kind::Brew => Some("see the brew output above, `brew doctor` may help"),
+            Kind::Cache => {
+                Some("brewer.db in the user cache directory may be corrupt, remove it and retry")
+            }
+            Kind::Cancelled | Kind::Other => None,
+        }
+    }
+}
+
+/// Process exit codes, shown by `--explain-exit-codes`
+#[derive(Clone, Copy)]
+pub enum Exit {
+    Success,
+    Failure,
+    NotFound,
+    Brew,
+    Network,
+    Cache,
+    Cancelled,
+}
+
+impl Exit {
+    const ALL: [Exit; 7] = [
+        Exit::Success,
+        Exit::Failure,
+        Exit::NotFound,
+        Exit::Brew,
+        Exit::Network,
+        Exit::Cache,
+        Exit::Cancelled,
+    ];
+
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Success => 0,
+            Exit::Failure => 1,
+            Exit::NotFound => 2,
+            Exit::Brew => 3,
+            Exit::Network => 4,
+            Exit::Cache => 5,
+            Exit::Cancelled => 130,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Exit::Success => "success",
+            Exit::Failure => "failure not covered by other codes, e.g. missing project packages",
+            Exit::NotFound => "formula, cask, tap or file not found",
+            Exit::Brew => "brew command failed",
+            Exit::Network => "network request failed",
+            Exit::Cache => "local database is corrupt or unreadable",
+            Exit::Cancelled => "cancelled by the user",
+        }
+    }
+
+    /// Success if the lookup succeeded, NotFound otherwise
+    pub fn found(found: bool) -> Exit {
+        if found {
+            Exit::Success
+        } else {
+            Exit::NotFound
+        }
+    }
+
+    pub fn success(success: bool) -> Exit {
+        if success {
+            Exit::Success
+        } else {
+            Exit::Failure
+        }
+    }
+
+    pub fn explain() {
+        for exit in Exit::ALL {
+            println!("{:>3} {}", exit.code(), exit.description());
+        }
+    }
+}
+
+impl From<Kind> for Exit {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::NotFound => Exit::NotFound,
+            Kind::Network => Exit::Network,
+            Kind::Brew => Exit::Brew,
+            Kind::Cache => Exit::Cache,
+            Kind::Cancelled => Exit::Cancelled,
+            Kind::Other => Exit::Failure,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    code: Kind,
+    message: String,
+    hint: Option<&'static str>,
+}
+
+/// Prints the error to stderr, as a JSON object if requested
+pub fn report(e: &anyhow::Error, json: bool) {
+    let kind = Kind::of(e);
+
+    if json {
+        let report = Report {
+            code: kind,
+            message: format!("{e:#}"),
+            hint: kind.hint(),
+        };
+
+        match serde_json::to_string(&report) {
+            Ok(report) => eprintln!("{report}"),
+            Err(_) => eprintln!("{}", header::error!("{e}")),
+        }
+
+        return;
+    }
+
+    // the user knows they backed out, only the exit code tells scripts
+    if kind == Kind::Cancelled {
+        return;
+    }
+
+    eprintln!("{}", header::error!("{e}"));
+
+    if let Some(hint) = kind.hint() {
+        eprintln!("{hint}");
+    }
+}
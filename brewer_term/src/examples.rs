@@ -0,0 +1,249 @@
+use clap::Command;
+use serde::Serialize;
+
+/// Runnable invocation of a subcommand with what it does
+#[derive(Serialize, Clone, Copy)]
+pub struct Example {
+    pub description: &'static str,
+    pub args: &'static str,
+}
+
+/// Examples of a subcommand, keyed by its name as typed on the command line
+#[derive(Serialize, Clone, Copy)]
+pub struct Entry {
+    pub command: &'static str,
+    pub examples: &'static [Example],
+}
+
+const fn example(description: &'static str, args: &'static str) -> Example {
+    Example { description, args }
+}
+
+pub const REGISTRY: &[Entry] = &[
+    Entry {
+        command: "which",
+        examples: &[
+            example("Find the formula providing rg", "which rg"),
+            example("Look up several executables at once", "which rg fd jq"),
+            example(
+                "Fall back to the closest executable name",
+                "which --fuzzy ripgre",
+            ),
+            example(
+                "Install the formula providing an executable",
+                "which --install rg",
+            ),
+        ],
+    },
+    Entry {
+        command: "update",
+        examples: &[example("Refresh the local index", "update")],
+    },
+    Entry {
+        command: "list",
+        examples: &[
+            example("List formulae installed on request", "list --formulae -r"),
+            example("List starred formulae and casks", "list --starred"),
+        ],
+    },
+    Entry {
+        command: "info",
+        examples: &[
+            example("Show information about a formula", "info wget"),
+            example("Show several formulae and casks", "info wget curl firefox"),
+            example(
+                "Leave out caveats and analytics",
+                "info wget --hide caveats,analytics",
+            ),
+            example("One line per formula", "info --short wget curl"),
+        ],
+    },
+    Entry {
+        command: "search",
+        examples: &[
+            example("Search formulae and casks by name", "search ripgrep"),
+            example("Search descriptions too", "search --desc json"),
+            example(
+                "Only installed kegs from a tap",
+                "search --installed --tap homebrew/core git",
+            ),
+        ],
+    },
+    Entry {
+        command: "install",
+        examples: &[
+            example("Install a formula", "install wget"),
+            example(
+                "Install a cask without confirming",
+                "install --cask --yes firefox",
+            ),
+            example("Pick formulae and casks interactively", "install"),
+        ],
+    },
+    Entry {
+        command: "uninstall",
+        examples: &[
+            example("Uninstall a formula", "uninstall wget"),
+            example("Pick installed kegs interactively", "uninstall"),
+        ],
+    },
+    Entry {
+        command: "upgrade",
+        examples: &[
+            example("Pick outdated kegs interactively", "upgrade"),
+            example("Upgrade a formula without confirming", "upgrade --yes wget"),
+            example(
+                "Only apply patch and minor upgrades",
+                "upgrade --only patch,minor",
+            ),
+        ],
+    },
+    Entry {
+        command: "outdated",
+        examples: &[
+            example("List outdated formulae and casks", "outdated"),
+            example(
+                "Only formulae from homebrew/core",
+                "outdated --formulae --tap homebrew/core",
+            ),
+        ],
+    },
+    Entry {
+        command: "explain",
+        examples: &[
+            example(
+                "Print the brew commands an install would run",
+                "explain install wget",
+            ),
+            example(
+                "Same for an upgrade, as JSON",
+                "explain --json upgrade wget",
+            ),
+        ],
+    },
+    Entry {
+        command: "owns",
+        examples: &[example(
+            "Find the keg owning a file",
+            "owns /opt/homebrew/bin/rg",
+        )],
+    },
+    Entry {
+        command: "provides",
+        examples: &[
+            example("List executables of a formula", "provides ripgrep"),
+            example(
+                "Find formulae providing pkg-config files",
+                "provides '*.pc'",
+            ),
+        ],
+    },
+    Entry {
+        command: "deps",
+        examples: &[
+            example("Show dependencies as a tree", "deps --tree ffmpeg"),
+            example("Include build dependencies", "deps --build ffmpeg"),
+        ],
+    },
+    Entry {
+        command: "uses",
+        examples: &[example(
+            "Installed formulae depending on openssl@3, recursively",
+            "uses --installed --recursive openssl@3",
+        )],
+    },
+    Entry {
+        command: "cleanup",
+        examples: &[
+            example("Show what would be removed", "cleanup --dry-run"),
+            example("Clean up without confirming", "cleanup --yes"),
+        ],
+    },
+    Entry {
+        command: "config",
+        examples: &[
+            example("Print effective settings", "config show"),
+            example("Check the config file", "config validate"),
+        ],
+    },
+    Entry {
+        command: "history",
+        examples: &[
+            example("Show the last 50 events", "history --limit 50"),
+            example("Events of a formula", "history --name wget"),
+            example(
+                "Export events since a date",
+                "history export --since 2024-05-01",
+            ),
+        ],
+    },
+    Entry {
+        command: "snapshot",
+        examples: &[
+            example("Save installed kegs", "snapshot create before-upgrade"),
+            example("Restore them later", "snapshot restore before-upgrade"),
+        ],
+    },
+    Entry {
+        command: "bundle",
+        examples: &[
+            example("Write a Brewfile", "bundle dump"),
+            example(
+                "Install from a Brewfile",
+                "bundle install --file ~/Brewfile",
+            ),
+        ],
+    },
+    Entry {
+        command: "size",
+        examples: &[example(
+            "Disk usage of installed kegs, largest first",
+            "size",
+        )],
+    },
+    Entry {
+        command: "examples",
+        examples: &[
+            example("Examples of install", "examples install"),
+            example("All examples as JSON", "examples --json"),
+        ],
+    },
+];
+
+/// Examples of the subcommand, None if it has none
+pub fn of(command: &str) -> Option<&'static Entry> {
+    REGISTRY.iter().find(|entry| entry.command == command)
+}
+
+/// Copy-pasteable form, a comment with the description followed by the invocation
+pub fn render(examples: &[Example]) -> String {
+    examples
+        .iter()
+        .map(|e| format!("# {}\nbrewer {}", e.description, e.args))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Appends the examples to the long help of each subcommand having them
+pub fn attach(mut command: Command) -> Command {
+    for entry in REGISTRY {
+        let help = format!("Examples:\n\n{}", indent(&render(entry.examples)));
+
+        command = command.mut_subcommand(entry.command, |c| c.after_long_help(help));
+    }
+
+    command
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("  {line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,156 +1,563 @@
+use std::collections::HashMap;
+use std::io::IsTerminal;
 use std::process::exit;
+use std::time::{Duration, SystemTime};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 
-use brewer_core::Brew;
-use brewer_engine::Engine;
+use brewer_core::models;
+use brewer_engine::store::Store;
+use brewer_engine::{Engine, Operation, State};
 use log::LevelFilter;
 
 use crate::cli::{Cli, Commands};
-use crate::settings::AutoUpdate;
+use crate::context::Context;
+use crate::error::{Exit, Kind};
+use crate::settings::{AutoUpdate, Settings, StaleWarning};
 
+mod brewfile;
 mod cli;
+mod context;
+mod error;
+mod examples;
+mod packages;
 mod pretty;
+mod progress;
+mod project;
+mod record;
 mod settings;
+mod user;
+mod watchdog;
 
 fn setup_logger(level: LevelFilter) {
     env_logger::builder().filter_level(level).init();
 }
 
-fn run() -> anyhow::Result<bool> {
-    let c = Cli::parse();
-
+fn run(c: Cli, ctx: &mut Context) -> anyhow::Result<Exit> {
     setup_logger(c.verbose.log_level_filter());
 
-    match c.command {
-        Commands::Which(cmd) => {
-            let settings = settings::Settings::new()?;
+    if c.explain_exit_codes {
+        Exit::explain();
 
-            let mut engine = get_engine(settings)?;
-            let state = engine.cache_or_latest()?;
+        return Ok(Exit::Success);
+    }
 
-            Ok(cmd.run(state)?)
-        }
-        Commands::Update(cmd) => {
-            let settings = settings::Settings::new()?;
+    let command = match c.command {
+        Some(command) => command,
+        None => match default_command(ctx)? {
+            Some(command) => command,
+            None => {
+                Cli::command().print_help()?;
 
-            let engine = get_engine(settings)?;
+                return Ok(Exit::Success);
+            }
+        },
+    };
 
-            cmd.run(engine)?;
+    match command {
+        Commands::Which(mut cmd) => {
+            if c.json {
+                cmd.output.get_or_insert(cli::which::Output::Json);
+            }
+
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+            let cache_age = engine.cache_age()?;
+
+            let success = cmd.run(engine, state, cache_age)?;
+
+            stale_warning(cache_age, &ctx.settings()?.cache.stale_warning);
 
-            Ok(true)
+            Ok(Exit::found(success))
+        }
+        Commands::Update(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
         }
         Commands::List(cmd) => {
-            let settings = settings::Settings::new()?;
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
 
-            let mut engine = get_engine(settings)?;
-            let state = engine.cache_or_latest()?;
+            cmd.run(&engine, state)?;
 
-            cmd.run(state)?;
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
 
-            Ok(true)
+            Ok(Exit::Success)
         }
         Commands::Info(cmd) => {
-            let settings = settings::Settings::new()?;
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+            let cache_age = engine.cache_age()?;
+
+            let success = cmd.run(&engine, state, cache_age)?;
+
+            stale_warning(cache_age, &ctx.settings()?.cache.stale_warning);
 
-            let mut engine = get_engine(settings)?;
-            let state = engine.cache_or_latest()?;
+            Ok(Exit::found(success))
+        }
+        Commands::Home(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Cat(cmd) => {
+            let engine = ctx.engine()?;
 
-            Ok(cmd.run(state)?)
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Desc(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
         }
         Commands::Search(cmd) => {
-            let settings = settings::Settings::new()?;
+            let mut engine = ctx.engine()?;
+
+            let success = cmd.run(&mut engine)?;
 
-            let mut engine = get_engine(settings)?;
-            let state = engine.cache_or_latest()?;
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
 
-            Ok(cmd.run(state)?)
+            Ok(Exit::found(success))
         }
         Commands::Paths(cmd) => {
+            cmd.run(ctx)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Config(cmd) => Ok(Exit::success(cmd.run(ctx, c.json)?)),
+        Commands::Hook(cmd) => {
             cmd.run();
 
-            Ok(true)
+            Ok(Exit::Success)
+        }
+        Commands::Completions(cmd) => {
+            cmd.run();
+
+            Ok(Exit::Success)
+        }
+        Commands::Complete(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Collisions(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Outdated(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::Success)
+        }
+        Commands::Deps(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Uses(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Leaves(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::Success)
+        }
+        Commands::Missing(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::success(success))
+        }
+        Commands::Bundle(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
+        }
+        Commands::ImportBrewfile(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Export(cmd) => {
+            let mut engine = ctx.engine()?;
+
+            cmd.run(&mut engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Import(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::ImportLeaves(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Provides(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(&engine, state)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Owns(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            Ok(Exit::found(cmd.run(&engine, state)?))
+        }
+        Commands::Examples(cmd) => {
+            cmd.run(c.json)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Size(cmd) => {
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(&mut engine, state, c.json)?;
+
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
+
+            Ok(Exit::found(success))
+        }
+        Commands::Xref(cmd) => Ok(Exit::found(cmd.run(c.offline)?)),
+        Commands::Cleanup(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Doctor(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
         }
         Commands::Exists(cmd) => {
-            let settings = settings::Settings::new()?;
+            let mut engine = ctx.engine()?;
+            let state = load_state(&mut engine)?;
+
+            let success = cmd.run(state);
 
-            let mut engine = get_engine(settings)?;
-            let state = engine.cache_or_latest()?;
+            stale_warning(engine.cache_age()?, &ctx.settings()?.cache.stale_warning);
 
-            Ok(cmd.run(state))
+            Ok(Exit::found(success))
         }
         Commands::Install(cmd) => {
-            let settings = settings::Settings::new()?;
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Uninstall(cmd) => {
+            let engine = ctx.engine()?;
 
-            let engine = get_engine(settings)?;
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Autoremove(cmd) => {
+            let engine = ctx.engine()?;
 
             cmd.run(engine)?;
 
-            Ok(true)
+            Ok(Exit::Success)
         }
-        Commands::Uninstall(cmd) => {
-            let settings = settings::Settings::new()?;
+        Commands::Link(cmd) => {
+            let engine = ctx.engine()?;
 
-            let engine = get_engine(settings)?;
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Unlink(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Shellenv(cmd) => {
+            let engine = ctx.engine()?;
 
             cmd.run(engine)?;
 
-            Ok(true)
+            Ok(Exit::Success)
+        }
+        Commands::Upgrade(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Explain(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine, c.json)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Changes(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::History(cmd) => {
+            let engine = ctx.engine()?;
+
+            cmd.run(engine)?;
+
+            Ok(Exit::Success)
+        }
+        Commands::Rollback(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
+        }
+        Commands::Snapshot(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
+        }
+        Commands::Diff(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
+        }
+        Commands::Tag(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Note(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Star(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Unstar(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
+        }
+        Commands::Project(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::success(cmd.run(engine)?))
+        }
+        Commands::Tap(cmd) => {
+            let engine = ctx.engine()?;
+
+            Ok(Exit::found(cmd.run(engine)?))
         }
     }
 }
 
-fn get_brew(settings: settings::Homebrew) -> anyhow::Result<Brew> {
-    let brew = Brew::default();
+/// Answers `which` and `exists` from a fresh cache, as shell hooks run them on every prompt.
+/// Only the cache TTL is read from the settings and the cached formulae and casks from the db,
+/// without initializing logging, scanning installed kegs or touching the network.
+/// None if the regular path has to run, e.g. when the cache is missing or expired
+fn fast_path(c: &Cli) -> Option<anyhow::Result<Exit>> {
+    // installing, picking interactively and --output, which marks installed kegs,
+    // need the regular brew setup
+    let fast = match &c.command {
+        Some(Commands::Which(cmd)) => {
+            !cmd.install && !cmd.names.is_empty() && cmd.output.is_none() && !c.json
+        }
+        Some(Commands::Exists(_)) => true,
+        _ => false,
+    };
+
+    if !fast || c.explain_exit_codes || c.verbose.log_level_filter() > LevelFilter::Error {
+        return None;
+    }
+
+    let db = Context::new(c).db_path();
+
+    // opening creates a missing db, which is left for the regular path to fill
+    if !db.is_file() {
+        return None;
+    }
+
+    let store = Store::open(&db).ok()?;
+
+    let cache_duration = match Settings::auto_update(c.config.as_deref()).ok()? {
+        AutoUpdate::Every(duration) => Some(duration),
+        AutoUpdate::Never => None,
+    };
+
+    if !c.offline && store.expired(cache_duration).ok()? {
+        return None;
+    }
+
+    let cached = store.get_state().ok()??;
+
+    // neither command shows installed kegs on this path
+    let state = State {
+        formulae: models::formula::State {
+            all: cached.formulae,
+            installed: HashMap::new(),
+        },
+        casks: models::cask::State {
+            all: cached.casks,
+            installed: HashMap::new(),
+        },
+    };
 
-    let brew = brewer_core::BrewBuilder::default()
-        .path(settings.path.unwrap_or(brew.path))
-        .prefix(settings.prefix.unwrap_or(brew.prefix))
-        .build()?;
+    match &c.command {
+        Some(Commands::Which(cmd)) => {
+            let cache_age = store.age().ok()?;
 
-    Ok(brew)
+            Some(
+                cmd.show(&state, &cmd.names, cache_age)
+                    .map(|(_, found)| Exit::found(found)),
+            )
+        }
+        Some(Commands::Exists(cmd)) => Some(Ok(Exit::found(cmd.run(state)))),
+        _ => None,
+    }
 }
 
-fn get_engine(settings: settings::Settings) -> anyhow::Result<Engine> {
-    let db_path = if let Some(dir) = dirs::cache_dir() {
-        dir.join("brewer.db")
-    } else {
-        "brewer.db".into()
+/// Parses the command configured to run when none is given
+fn default_command(ctx: &mut Context) -> anyhow::Result<Option<Commands>> {
+    let Some(default) = ctx.settings()?.default_command.clone() else {
+        return Ok(None);
     };
 
-    let store = brewer_engine::store::Store::open(db_path.as_path())?;
+    let args = std::iter::once("brewer").chain(default.split_whitespace());
 
-    let mut engine_builder = brewer_engine::EngineBuilder::default();
+    let cli = Cli::try_parse_from(args)
+        .map_err(|e| anyhow::anyhow!("invalid default_command {default:?}: {e}"))?;
 
-    engine_builder.store(store);
+    Ok(cli.command)
+}
 
-    if let AutoUpdate::Every(duration) = settings.cache.auto_update {
-        engine_builder.cache_duration(Some(duration));
+/// Loads the state recording how long it took, updating the cache when needed
+fn load_state(engine: &mut Engine) -> anyhow::Result<State> {
+    let operation = if engine.cache_expired()? || engine.cache_age()?.is_none() {
+        Operation::Update
     } else {
-        engine_builder.cache_duration(None);
-    }
+        Operation::Load
+    };
 
-    let brew = get_brew(settings.homebrew)?;
+    cli::timed(engine, operation, |engine| engine.cache_or_latest())
+}
 
-    engine_builder.brew(brew);
+/// Prints a warning to stderr when the cache is older than configured
+fn stale_warning(cache_age: Option<Duration>, setting: &StaleWarning) {
+    let StaleWarning::After(threshold) = setting else {
+        return;
+    };
 
-    let engine = engine_builder.build()?;
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
 
-    Ok(engine)
+    if let Some(age) = cache_age {
+        if age >= *threshold {
+            eprintln!(
+                "{}",
+                pretty::header::warning!(
+                    "Index is {} old, run `brewer update`",
+                    pretty::duration(age)
+                )
+            );
+        }
+    }
 }
 
 fn main() {
-    match run() {
-        Ok(success) => {
-            if success {
-                exit(0)
-            } else {
-                exit(1)
-            }
-        }
+    let c = Cli::parse_with_examples();
+    let json = c.json;
+    let record = c.record.clone();
+    let started = SystemTime::now();
+    let capture = record.as_ref().and_then(|_| record::Capture::start());
+
+    let mut ctx = Context::new(&c);
+
+    let result = match fast_path(&c) {
+        Some(result) => result,
+        None => run(c, &mut ctx),
+    };
+
+    let code = match &result {
+        Ok(code) => code.code(),
         Err(e) => {
-            eprintln!("{}", pretty::header::error!("{e}"));
-            exit(1)
+            error::report(e, json);
+            Exit::from(Kind::of(e)).code()
+        }
+    };
+
+    if let Some(path) = record {
+        let transcript = ctx.transcript();
+
+        if let Err(e) = record::write(
+            &path,
+            started,
+            transcript.as_deref(),
+            capture.map(record::Capture::finish),
+            code,
+            result.as_ref().err(),
+        ) {
+            eprintln!(
+                "{}",
+                pretty::header::warning!("Failed to write {}: {e}", path.display())
+            );
         }
     }
+
+    exit(code)
 }
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use config::{Config, File, FileFormat};
+use serde::Deserialize;
+
+use crate::brewfile::Brewfile;
+
+/// Schema version written by `brewer export`, newer files are rejected
+pub const VERSION: u32 = 1;
+
+/// Installed package set written by `brewer export` and read by `brewer import`
+#[derive(Deserialize, Default)]
+pub struct Packages {
+    pub version: u32,
+
+    #[serde(default)]
+    pub taps: Vec<String>,
+
+    #[serde(default)]
+    pub formulae: Vec<String>,
+
+    #[serde(default)]
+    pub casks: Vec<String>,
+
+    /// Formulae held at their installed versions
+    #[serde(default)]
+    pub pins: Vec<String>,
+}
+
+impl Packages {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let packages: Packages = Config::builder()
+            .add_source(File::from(path).format(FileFormat::Toml))
+            .build()?
+            .try_deserialize()?;
+
+        if packages.version > VERSION {
+            return Err(anyhow!(
+                "{} has version {}, only {VERSION} is supported",
+                path.to_string_lossy(),
+                packages.version
+            ));
+        }
+
+        Ok(packages)
+    }
+
+    pub fn render(&self) -> String {
+        // JSON strings are valid TOML basic strings
+        let list = |names: &[String]| serde_json::to_string(names).unwrap_or_default();
+
+        format!(
+            "version = {}\ntaps = {}\nformulae = {}\ncasks = {}\npins = {}\n",
+            self.version,
+            list(&self.taps),
+            list(&self.formulae),
+            list(&self.casks),
+            list(&self.pins)
+        )
+    }
+}
+
+impl From<Brewfile> for Packages {
+    fn from(value: Brewfile) -> Self {
+        Packages {
+            version: VERSION,
+            taps: value.taps,
+            formulae: value.formulae,
+            casks: value.casks,
+            pins: value.pinned,
+        }
+    }
+}
+
+impl From<Packages> for Brewfile {
+    fn from(value: Packages) -> Self {
+        Brewfile {
+            taps: value.taps,
+            formulae: value.formulae,
+            casks: value.casks,
+            pinned: value.pins,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use colored::Colorize;
 use prettytable::{cell, Row, Table};
 use prettytable::format::consts::FORMAT_CLEAN;
@@ -46,6 +48,82 @@ pub fn bool(b: bool) -> String {
     }
 }
 
+pub fn star() -> String {
+    "★".yellow().to_string()
+}
+
+/// Marker of a keg with a newer upstream version
+pub fn outdated() -> String {
+    "↑".yellow().to_string()
+}
+
+/// Marker of a cask which app updated itself past the installed version
+pub fn locally_newer() -> String {
+    "↥".blue().to_string()
+}
+
+/// Number with thousands separators, e.g. "1,234,567"
+pub fn count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+/// Human-readable size, e.g. "12.3 MB"
+pub fn bytes(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = n as f64;
+    let mut unit = 0;
+
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{n} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Rough human-readable duration, e.g. "9 days"
+pub fn duration(d: Duration) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    let secs = d.as_secs();
+
+    let (value, unit) = if secs >= DAY {
+        (secs / DAY, "day")
+    } else if secs >= HOUR {
+        (secs / HOUR, "hour")
+    } else if secs >= MINUTE {
+        (secs / MINUTE, "minute")
+    } else if secs >= 1 {
+        (secs, "second")
+    } else {
+        return "less than a second".to_string();
+    };
+
+    if value == 1 {
+        format!("{value} {unit}")
+    } else {
+        format!("{value} {unit}s")
+    }
+}
+
 pub fn table(values: &[String], max_width: u16) -> Table {
     const RIGHT_PADDING: usize = 2;
 
@@ -84,4 +162,101 @@ fn calculate_chunk_size(values: &[String], padding: usize, max_width: u16) -> us
     }
 
     chunk_size
-}
\ No newline at end of file
+}
+/// Highlights Ruby source of formulae and casks. A heuristic tokenizer, heredocs and
+/// string interpolation are not understood
+pub fn ruby(source: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "begin", "class", "def", "do", "else", "elsif", "end", "ensure", "false", "if", "in",
+        "module", "next", "nil", "raise", "require", "rescue", "return", "self", "then", "true",
+        "unless", "until", "when", "while", "yield", "case",
+    ];
+
+    let mut out = String::with_capacity(source.len() * 2);
+
+    for line in source.split_inclusive('\n') {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if c == '#' {
+                let rest: String = chars[i..].iter().collect();
+                out.push_str(&rest.trim_end_matches('\n').bright_black().to_string());
+
+                if rest.ends_with('\n') {
+                    out.push('\n');
+                }
+
+                break;
+            }
+
+            if c == '"' || c == '\'' {
+                let start = i;
+                i += 1;
+
+                while i < chars.len() && chars[i] != c {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+
+                    i += 1;
+                }
+
+                i = (i + 1).min(chars.len());
+
+                let literal: String = chars[start..i].iter().collect();
+                out.push_str(&literal.green().to_string());
+
+                continue;
+            }
+
+            let word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+            if c == ':' && chars.get(i + 1).is_some_and(|&n| word_char(n)) {
+                let prev = i.checked_sub(1).map(|p| chars[p]);
+
+                if !prev.is_some_and(|p| p == ':' || word_char(p)) {
+                    let start = i;
+                    i += 1;
+
+                    while i < chars.len() && word_char(chars[i]) {
+                        i += 1;
+                    }
+
+                    let symbol: String = chars[start..i].iter().collect();
+                    out.push_str(&symbol.yellow().to_string());
+
+                    continue;
+                }
+            }
+
+            if word_char(c) {
+                let start = i;
+
+                while i < chars.len() && (word_char(chars[i]) || chars[i] == '?' || chars[i] == '!')
+                {
+                    i += 1;
+                }
+
+                let word: String = chars[start..i].iter().collect();
+
+                if KEYWORDS.contains(&word.as_str()) {
+                    out.push_str(&word.magenta().to_string());
+                } else if word.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    out.push_str(&word.cyan().to_string());
+                } else {
+                    out.push_str(&word);
+                }
+
+                continue;
+            }
+
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    out
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use brewer_core::progress::{Phase, Progress};
+
+/// Spinner with the current phase of a cache update, hidden when stderr is not a terminal
+pub struct Bar(ProgressBar);
+
+impl Bar {
+    pub fn new() -> anyhow::Result<Bar> {
+        let bar = ProgressBar::new(Phase::ALL.len() as u64);
+
+        bar.set_style(ProgressStyle::with_template(
+            "{spinner:.cyan} [{pos}/{len}] {msg} {elapsed:.dim}",
+        )?);
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        Ok(Bar(bar))
+    }
+
+    pub fn finish(&self) {
+        self.0.finish_and_clear();
+    }
+}
+
+impl Progress for Bar {
+    fn phase(&self, phase: Phase) {
+        let step = Phase::ALL
+            .iter()
+            .position(|p| *p == phase)
+            .unwrap_or_default()
+            + 1;
+
+        self.0.set_position(step as u64);
+        self.0.set_message(phase.describe());
+    }
+}
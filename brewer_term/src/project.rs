@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+use config::{Config, ConfigError, File, FileFormat};
+use serde::Deserialize;
+
+pub const MANIFEST_FILE: &str = ".brewer.toml";
+
+/// Project-local list of required formulae and casks
+#[derive(Deserialize, Default)]
+pub struct Manifest {
+    #[serde(default)]
+    pub formulae: Vec<String>,
+
+    #[serde(default)]
+    pub casks: Vec<String>,
+}
+
+impl Manifest {
+    /// Looks for the manifest in the given directory and its ancestors
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(MANIFEST_FILE))
+            .find(|p| p.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let manifest = Config::builder()
+            .add_source(File::from(path).format(FileFormat::Toml))
+            .build()?;
+
+        manifest.try_deserialize()
+    }
+
+    pub fn render(&self) -> String {
+        // JSON strings are valid TOML basic strings
+        let list = |names: &[String]| serde_json::to_string(names).unwrap_or_default();
+
+        format!(
+            "formulae = {}\ncasks = {}\n",
+            list(&self.formulae),
+            list(&self.casks)
+        )
+    }
+
+    /// Adds entries which are not listed yet
+    pub fn merge(&mut self, formulae: &[String], casks: &[String]) {
+        for (names, new) in [(&mut self.formulae, formulae), (&mut self.casks, casks)] {
+            for name in new {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use brewer_core::transcript::{Entry, Transcript};
+
+/// Session written by `--record`, a reproduction trace to attach to bug reports
+#[derive(Serialize)]
+struct Session {
+    version: &'static str,
+    args: Vec<String>,
+
+    /// Seconds since the unix epoch
+    started_at: u64,
+    duration_ms: u64,
+    exit_code: i32,
+    error: Option<String>,
+
+    /// Everything written to stdout, by brewer and the brew commands it ran.
+    /// None if it could not be captured
+    output: Option<String>,
+    commands: Vec<Entry>,
+}
+
+pub fn write(
+    path: &Path,
+    started: SystemTime,
+    transcript: Option<&Transcript>,
+    output: Option<String>,
+    exit_code: i32,
+    error: Option<&anyhow::Error>,
+) -> anyhow::Result<()> {
+    let session = Session {
+        version: env!("CARGO_PKG_VERSION"),
+        args: std::env::args().collect(),
+        started_at: started
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        duration_ms: started.elapsed().unwrap_or_default().as_millis() as u64,
+        exit_code,
+        error: error.map(|e| format!("{e:#}")),
+        output,
+        commands: transcript.map(Transcript::entries).unwrap_or_default(),
+    };
+
+    let mut w = BufWriter::new(File::create(path)?);
+
+    serde_json::to_writer_pretty(&mut w, &session)?;
+    writeln!(w)?;
+    w.flush()?;
+
+    Ok(())
+}
+
+/// Copies everything written to stdout until finished, the output of child processes included.
+/// A terminal is swapped for a pseudo-terminal of the same size, so that brewer and brew
+/// still see a terminal and keep their colors and progress bars
+#[cfg(unix)]
+pub struct Capture {
+    /// Duplicate of the original stdout, restored when finished
+    original: libc::c_int,
+    reader: std::thread::JoinHandle<Vec<u8>>,
+}
+
+#[cfg(unix)]
+impl Capture {
+    /// None if stdout could not be redirected, the session is then recorded without it
+    pub fn start() -> Option<Capture> {
+        use std::io::Read;
+        use std::os::fd::FromRawFd;
+
+        let stdout = libc::STDOUT_FILENO;
+        let (mut read, mut write) = (-1, -1);
+
+        unsafe {
+            if libc::isatty(stdout) == 1 {
+                let mut size: libc::winsize = std::mem::zeroed();
+                libc::ioctl(stdout, libc::TIOCGWINSZ, &mut size);
+
+                let opened = libc::openpty(
+                    &mut read,
+                    &mut write,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &mut size,
+                );
+
+                if opened != 0 {
+                    return None;
+                }
+            } else {
+                let mut fds = [-1; 2];
+
+                if libc::pipe(fds.as_mut_ptr()) != 0 {
+                    return None;
+                }
+
+                [read, write] = fds;
+            }
+
+            // brew commands only inherit stdout itself, not the ends kept here
+            libc::fcntl(read, libc::F_SETFD, libc::FD_CLOEXEC);
+
+            let original = libc::fcntl(stdout, libc::F_DUPFD_CLOEXEC, 0);
+            let terminal = libc::fcntl(stdout, libc::F_DUPFD_CLOEXEC, 0);
+
+            if original < 0 || terminal < 0 || libc::dup2(write, stdout) < 0 {
+                for fd in [read, write, original, terminal] {
+                    libc::close(fd);
+                }
+
+                return None;
+            }
+
+            libc::close(write);
+
+            let mut source = File::from_raw_fd(read);
+            let mut terminal = File::from_raw_fd(terminal);
+
+            let reader = std::thread::spawn(move || {
+                let mut buf = Vec::new();
+                let mut chunk = [0; 8192];
+
+                // a pseudo-terminal reports an error rather than the end once every writer is gone
+                while let Ok(read) = source.read(&mut chunk) {
+                    if read == 0 {
+                        break;
+                    }
+
+                    let _ = terminal.write_all(&chunk[..read]);
+                    let _ = terminal.flush();
+
+                    buf.extend_from_slice(&chunk[..read]);
+                }
+
+                buf
+            });
+
+            Some(Capture { original, reader })
+        }
+    }
+
+    /// Restores stdout, returning what was written to it
+    pub fn finish(self) -> String {
+        let _ = std::io::stdout().flush();
+
+        unsafe {
+            libc::dup2(self.original, libc::STDOUT_FILENO);
+            libc::close(self.original);
+        }
+
+        let output = self.reader.join().unwrap_or_default();
+
+        // pseudo-terminals translate newlines to the terminal form
+        String::from_utf8_lossy(&output).replace("\r\n", "\n")
+    }
+}
+
+#[cfg(not(unix))]
+pub struct Capture;
+
+#[cfg(not(unix))]
+impl Capture {
+    /// Redirecting stdout is only supported on unix, sessions are recorded without it
+    pub fn start() -> Option<Capture> {
+        None
+    }
+
+    pub fn finish(self) -> String {
+        String::new()
+    }
+}
@@ -1,10 +1,13 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use config::{Config, ConfigError, Environment, File};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-#[derive(Deserialize, Debug)]
+use crate::user;
+
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum AutoUpdate {
     Never,
@@ -19,27 +22,191 @@ impl Default for AutoUpdate {
     }
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleWarning {
+    Never,
+
+    #[serde(untagged)]
+    After(Duration),
+}
+
+impl Default for StaleWarning {
+    fn default() -> Self {
+        StaleWarning::After(Duration::from_secs(60 * 60 * 24 * 7))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct Cache {
     #[serde(default)]
     pub auto_update: AutoUpdate,
+
+    /// Warn when the cache is older than this
+    #[serde(default)]
+    pub stale_warning: StaleWarning,
+
+    /// Reconcile installed kegs with brew's own view, slower but catches stale opt symlinks
+    #[serde(default)]
+    pub verify_installed: bool,
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Homebrew {
     pub path: Option<PathBuf>,
     pub prefix: Option<PathBuf>,
+
+    /// Version of the `brew info` JSON output to request, e.g. "v2"
+    pub json_version: Option<String>,
+
+    /// Fetch analytics from formulae.brew.sh, enabled unless `HOMEBREW_NO_ANALYTICS` is set
+    pub analytics: Option<bool>,
+
+    /// Let brew auto-update before installing, which may make the confirmed plan stale
+    #[serde(default)]
+    pub auto_update: bool,
+
+    /// Where brew stores downloads, passed as `HOMEBREW_CACHE`
+    pub cache: Option<PathBuf>,
+
+    /// Where brew stores temporary files, passed as `HOMEBREW_TEMP`
+    pub temp: Option<PathBuf>,
+
+    /// Taps in order of preference when several of them provide the same name
+    #[serde(default)]
+    pub tap_priority: Vec<String>,
+
+    /// WSL distribution running brew on Windows, the default one is detected if unset
+    pub wsl: Option<String>,
+
+    /// How long a brew command may run before asking whether to keep waiting
+    pub timeout: Option<Duration>,
+
+    /// Parallel build jobs for source builds, brew uses all cores by default
+    pub jobs: Option<usize>,
+
+    /// Niceness brew runs with when installing or upgrading, e.g. 10
+    pub nice: Option<i32>,
+
+    /// Run brew in the idle IO class when installing or upgrading, Linux only
+    #[serde(default)]
+    pub ionice: bool,
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct GitHub {
+    /// Show stars and archived status of upstream GitHub repositories
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Token for the GraphQL API, `GITHUB_TOKEN` is used if unset
+    pub token: Option<String>,
+
+    /// How long fetched metadata stays fresh
+    #[serde(default = "GitHub::default_ttl")]
+    pub ttl: Duration,
+}
+
+impl GitHub {
+    fn default_ttl() -> Duration {
+        Duration::from_secs(60 * 60 * 24 * 30)
+    }
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        GitHub {
+            enabled: false,
+            token: None,
+            ttl: GitHub::default_ttl(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct Settings {
     #[serde(default)]
     pub homebrew: Homebrew,
 
     #[serde(default)]
     pub cache: Cache,
+
+    #[serde(default)]
+    pub github: GitHub,
+
+    /// Command with arguments to run when none is given, e.g. "search" or "list -s".
+    /// Help is printed if unset
+    pub default_command: Option<String>,
+}
+
+/// Key of a config file which can't be used as is
+#[derive(Serialize)]
+pub struct Problem {
+    /// Dotted path, e.g. cache.stale_warning
+    pub key: String,
+
+    /// Line of the key, only known for TOML files
+    pub line: Option<usize>,
+
+    pub message: String,
+}
+
+impl Problem {
+    /// Prefixes the message with the file, line and key, like compilers do
+    pub fn located(&self, path: &Path) -> String {
+        match self.line {
+            Some(line) => format!("{}:{line}: {}: {}", path.display(), self.key, self.message),
+            None => format!("{}: {}: {}", path.display(), self.key, self.message),
+        }
+    }
 }
 
+/// Keys holding durations, which are easy to get wrong, and the values they accept
+const DURATIONS: &[(&str, &str)] = &[
+    ("cache.auto_update", "\"never\" or a duration"),
+    ("cache.stale_warning", "\"never\" or a duration"),
+    ("github.ttl", "a duration"),
+    ("homebrew.timeout", "a duration"),
+];
+
+/// Written by `brewer config edit` when there is no config file yet
+pub const TEMPLATE: &str = r#"# brewer settings, every key is optional.
+# Keys can also be set with BREWER_* environment variables.
+
+# Command to run when none is given, e.g. "search" or "list -s"
+# default_command = "search"
+
+[homebrew]
+# path = "brew"
+# prefix = "/opt/homebrew"
+# json_version = "v2"
+# analytics = true
+# Let brew auto-update before installing
+# auto_update = false
+# cache = "/path/to/downloads"
+# temp = "/path/to/tmp"
+# tap_priority = ["user/tap"]
+# WSL distribution running brew on Windows
+# wsl = "Ubuntu"
+# How long a brew command may run before asking whether to keep waiting
+# timeout = { secs = 1800, nanos = 0 }
+# Keep big installs and upgrades from hogging the machine
+# jobs = 2
+# nice = 10
+# ionice = false
+
+[cache]
+# "never" or a duration
+# auto_update = { secs = 86400, nanos = 0 }
+# stale_warning = { secs = 604800, nanos = 0 }
+# verify_installed = false
+
+[github]
+# enabled = false
+# token = "..."
+# ttl = { secs = 2592000, nanos = 0 }
+"#;
+
 impl Settings {
     fn config_dir() -> PathBuf {
         #[cfg(target_os = "macos")]
@@ -51,17 +218,231 @@ impl Settings {
         base.map(|p| p.join("brewer")).unwrap_or(".".into())
     }
 
+    /// Separate per user when running as someone other than the owner of the home directory
     pub fn config_file() -> PathBuf {
-        Self::config_dir().join("brewer")
+        Self::config_dir().join(user::file_name("brewer"))
     }
 
-    pub fn new() -> Result<Self, ConfigError> {
-        let settings = Config::builder()
-            .add_source(File::with_name(Self::config_file().to_str().unwrap()).required(false))
+    /// Whether a config file or `BREWER_*` variables may override the defaults
+    pub fn configured() -> bool {
+        let file = Self::config_file();
+
+        // extensions the config loader resolves
+        let exists = ["toml", "json", "yaml", "yml", "ini", "ron", "json5"]
+            .iter()
+            .any(|ext| file.with_extension(ext).is_file());
+
+        exists
+            || std::env::vars_os()
+                .any(|(key, _)| key.to_string_lossy().to_uppercase().starts_with("BREWER_"))
+    }
+
+    /// Loads settings from the given config file, or the default one if it exists
+    pub fn load(config: Option<&Path>) -> Result<Self, ConfigError> {
+        Self::source(config)?.try_deserialize()
+    }
+
+    /// `cache.auto_update` alone, the rest of the settings is neither deserialized nor validated
+    pub fn auto_update(config: Option<&Path>) -> Result<AutoUpdate, ConfigError> {
+        if config.is_none() && !Self::configured() {
+            return Ok(AutoUpdate::default());
+        }
+
+        match Self::source(config)?.get("cache.auto_update") {
+            Ok(auto_update) => Ok(auto_update),
+            Err(ConfigError::NotFound(_)) => Ok(AutoUpdate::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn source(config: Option<&Path>) -> Result<Config, ConfigError> {
+        let file = match config {
+            Some(path) => File::from(path).required(true),
+            None => File::with_name(Self::config_file().to_str().unwrap()).required(false),
+        };
+
+        Config::builder()
+            .add_source(file)
             .add_source(Environment::with_prefix("brewer"))
+            .build()
+    }
+
+    /// Settings as JSON, secrets are redacted
+    pub fn to_json(&self) -> anyhow::Result<Value> {
+        let mut value = serde_json::to_value(self)?;
+
+        if let Some(token) = value.pointer_mut("/github/token") {
+            if token.is_string() {
+                *token = Value::String("<redacted>".into());
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Settings as TOML, secrets are redacted. Unset keys are omitted
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let Value::Object(root) = self.to_json()? else {
+            return Ok(String::new());
+        };
+
+        let mut out = String::new();
+
+        for (key, value) in &root {
+            if !matches!(value, Value::Object(_) | Value::Null) {
+                out.push_str(&format!("{key} = {}\n", toml_value(value)));
+            }
+        }
+
+        for (table, value) in &root {
+            let Value::Object(entries) = value else {
+                continue;
+            };
+
+            out.push_str(&format!("\n[{table}]\n"));
+
+            for (key, value) in entries {
+                if !value.is_null() {
+                    out.push_str(&format!("{key} = {}\n", toml_value(value)));
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Checks each key of the config file on its own, so that a wrong value is reported
+    /// with its key. Unknown keys are ignored by loading, strict mode reports them too
+    pub fn validate(path: &Path, strict: bool) -> anyhow::Result<Vec<Problem>> {
+        let file = Config::builder()
+            .add_source(File::from(path).required(true))
             .build()?;
 
-        settings.try_deserialize()
+        let Value::Object(root) = file.try_deserialize::<Value>()? else {
+            return Ok(Vec::new());
+        };
+
+        let Value::Object(known) = serde_json::to_value(Settings::default())? else {
+            return Ok(Vec::new());
+        };
+
+        let mut problems = Vec::new();
+
+        for (key, value) in &root {
+            let Some(expected) = known.get(key) else {
+                if strict {
+                    problems.push(unknown(key.clone(), known.keys()));
+                }
+
+                continue;
+            };
+
+            match (expected, value) {
+                (Value::Object(fields), Value::Object(entries)) => {
+                    for (field, value) in entries {
+                        let dotted = format!("{key}.{field}");
+
+                        if fields.contains_key(field) {
+                            problems.extend(check(dotted, json!({ key: { field: value } })));
+                        } else if strict {
+                            problems.push(unknown(dotted, fields.keys()));
+                        }
+                    }
+                }
+                _ => problems.extend(check(key.clone(), json!({ key: value }))),
+            }
+        }
+
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            let text = std::fs::read_to_string(path)?;
+
+            for problem in &mut problems {
+                problem.line = line_of(&text, &problem.key);
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+fn unknown<'a>(key: String, expected: impl Iterator<Item = &'a String>) -> Problem {
+    let expected: Vec<_> = expected.map(|k| k.as_str()).collect();
+
+    Problem {
+        key,
+        line: None,
+        message: format!("unknown key, expected one of {}", expected.join(", ")),
+    }
+}
+
+/// Deserializes settings with the single key set, the error is about that key only
+fn check(key: String, probe: Value) -> Option<Problem> {
+    let e = serde_json::from_value::<Settings>(probe).err()?;
+
+    let message = match DURATIONS.iter().find(|(k, _)| *k == key) {
+        Some((_, expected)) => {
+            format!("{e}, expected {expected}, e.g. {{ secs = 3600, nanos = 0 }}")
+        }
+        None => e.to_string(),
+    };
+
+    Some(Problem {
+        key,
+        line: None,
+        message,
+    })
+}
+
+/// Line of the dotted key in a TOML file, following table headers
+fn line_of(text: &str, key: &str) -> Option<usize> {
+    let (table, name) = match key.split_once('.') {
+        Some((table, name)) => (Some(table), name),
+        None => (None, key),
+    };
+
+    let mut current = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(header.trim());
+            continue;
+        }
+
+        let Some((lhs, _)) = line.split_once('=') else {
+            continue;
+        };
+
+        let lhs = lhs.trim();
+
+        // dotted keys may set table entries from the root, e.g. cache.verify_installed = true
+        if (current == table && lhs == name) || (current.is_none() && lhs == key) {
+            return Some(i + 1);
+        }
+    }
+
+    None
+}
+
+/// Strings and arrays of JSON are valid TOML, objects become inline tables
+fn toml_value(value: &Value) -> String {
+    match value {
+        Value::Object(entries) => {
+            let entries: Vec<_> = entries
+                .iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| format!("{k} = {}", toml_value(v)))
+                .collect();
+
+            format!("{{ {} }}", entries.join(", "))
+        }
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(toml_value).collect();
+
+            format!("[{}]", values.join(", "))
+        }
+        value => value.to_string(),
     }
 }
 
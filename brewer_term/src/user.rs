@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use crate::pretty::header;
+
+/// Effective user id when it differs from the owner of the home directory,
+/// e.g. under sudo with HOME preserved. Always None outside of Unix
+#[cfg(unix)]
+pub fn foreign() -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    // SAFETY: geteuid has no preconditions and never fails
+    let euid = unsafe { libc::geteuid() };
+    let owner = dirs::home_dir()?.metadata().ok()?.uid();
+
+    (euid != owner).then_some(euid)
+}
+
+#[cfg(not(unix))]
+pub fn foreign() -> Option<u32> {
+    None
+}
+
+/// File name with the effective user id appended to the stem when running as a foreign user,
+/// so that files in someone else's home directory are never shared, e.g. brewer-0.db
+pub fn file_name(name: &str) -> String {
+    let Some(uid) = foreign() else {
+        return name.to_string();
+    };
+
+    match name.split_once('.') {
+        Some((stem, extension)) => format!("{stem}-{uid}.{extension}"),
+        None => format!("{name}-{uid}"),
+    }
+}
+
+/// The given file, or a private copy of it when it belongs to another user and can't be written.
+/// The copy is made once and reused afterwards, changes to it are not seen by the owner
+pub fn writable(path: PathBuf) -> anyhow::Result<PathBuf> {
+    if !path.is_file() || is_writable(&path) {
+        return Ok(path);
+    }
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "brewer.db".into());
+
+    let copy = private_dir()?.join(name);
+
+    if !copy.is_file() {
+        std::fs::copy(&path, &copy)?;
+    }
+
+    eprintln!(
+        "{}",
+        header::warning!(
+            "{} belongs to another user, using a copy at {}",
+            path.to_string_lossy(),
+            copy.to_string_lossy()
+        )
+    );
+
+    Ok(copy)
+}
+
+fn is_writable(path: &Path) -> bool {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .is_ok()
+}
+
+/// Directory in the temp dir only the effective user can access, e.g. /tmp/brewer-0
+#[cfg(unix)]
+fn private_dir() -> anyhow::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    let dir = std::env::temp_dir().join(format!("brewer-{}", owner_tag()));
+
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        result => result?,
+    }
+
+    // the temp dir is shared, a directory or link planted there by someone else is refused
+    let metadata = std::fs::symlink_metadata(&dir)?;
+    // SAFETY: geteuid has no preconditions and never fails
+    let euid = unsafe { libc::geteuid() };
+
+    if !metadata.is_dir() || metadata.uid() != euid || metadata.permissions().mode() & 0o077 != 0 {
+        anyhow::bail!(
+            "{} is not private to the current user",
+            dir.to_string_lossy()
+        );
+    }
+
+    Ok(dir)
+}
+
+#[cfg(not(unix))]
+fn private_dir() -> anyhow::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("brewer-{}", owner_tag()));
+
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+#[cfg(unix)]
+fn owner_tag() -> String {
+    // SAFETY: geteuid has no preconditions and never fails
+    unsafe { libc::geteuid() }.to_string()
+}
+
+#[cfg(not(unix))]
+fn owner_tag() -> String {
+    std::env::var("USERNAME").unwrap_or_else(|_| "user".into())
+}
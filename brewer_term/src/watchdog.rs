@@ -0,0 +1,39 @@
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use inquire::{Confirm, InquireError};
+
+use brewer_core::watchdog::Watchdog;
+
+use crate::pretty;
+use crate::pretty::header;
+
+/// Asks whether to keep waiting for a stalled brew command, keeps waiting when not interactive
+pub struct Prompt;
+
+impl Watchdog for Prompt {
+    fn keep_waiting(&self, command: &str, elapsed: Duration) -> bool {
+        eprintln!(
+            "{}",
+            header::warning!(
+                "Waiting for `{command}` for {}, brew may be stalled on a lock or network",
+                pretty::duration(elapsed)
+            )
+        );
+
+        if !std::io::stdin().is_terminal() {
+            return true;
+        }
+
+        let answer = Confirm::new("Keep waiting?")
+            .with_default(true)
+            .with_help_message("answering no kills the command")
+            .prompt();
+
+        match answer {
+            Ok(keep_waiting) => keep_waiting,
+            Err(InquireError::OperationInterrupted) => false,
+            Err(_) => true,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use brewer_core::models;
+use brewer_engine::store::{State, Store};
+
+/// Scratch directory used as the home, config and cache directory of brewer
+struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Sandbox {
+        let dir = std::env::temp_dir().join(format!("brewer-{name}-{}", std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        Sandbox { dir }
+    }
+
+    fn db(&self) -> PathBuf {
+        self.dir.join("brewer.db")
+    }
+
+    fn brewer(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_brewer"))
+            .args(args)
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .env("XDG_CACHE_HOME", &self.dir)
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "brewer {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        output
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn formula(name: &str) -> models::formula::Formula {
+    serde_json::from_value(serde_json::json!({
+        "base": {
+            "name": name,
+            "tap": "homebrew/core",
+            "versions": { "stable": "1.0" },
+        },
+        "executables": [],
+    }))
+    .unwrap()
+}
+
+fn cask(token: &str) -> models::cask::Cask {
+    serde_json::from_value(serde_json::json!({
+        "base": {
+            "token": token,
+            "tap": "homebrew/cask",
+            "version": "1.0",
+        },
+    }))
+    .unwrap()
+}
+
+/// Cache with the formulae libpng and libxml2 and the cask libreoffice
+fn fixture(db: &Path) {
+    let formulae: HashMap<_, _> = ["libpng", "libxml2"]
+        .into_iter()
+        .map(|name| (name.to_string(), formula(name)))
+        .collect();
+
+    let casks = HashMap::from([("libreoffice".to_string(), cask("libreoffice"))]);
+
+    let mut store = Store::open(db).unwrap();
+
+    store.set_state(State { formulae, casks }).unwrap();
+}
+
+fn lines(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn scripts_call_back_into_brewer() {
+    let sandbox = Sandbox::new("completions");
+
+    for shell in ["bash", "zsh", "fish"] {
+        let output = sandbox.brewer(&["completions", shell]);
+        let script = String::from_utf8_lossy(&output.stdout);
+
+        assert!(
+            script.contains("brewer __complete --command"),
+            "{shell} script has no dynamic name completion"
+        );
+    }
+}
+
+#[test]
+fn formula_only_commands_exclude_casks() {
+    let sandbox = Sandbox::new("complete");
+    let db = sandbox.db();
+
+    fixture(&db);
+
+    let db = db.to_str().unwrap();
+
+    let link = sandbox.brewer(&["--db", db, "__complete", "--command", "link", "--", "lib"]);
+    assert_eq!(lines(&link), ["libpng", "libxml2"]);
+
+    let info = sandbox.brewer(&["--db", db, "__complete", "--command", "info", "--", "lib"]);
+    assert_eq!(lines(&info), ["libpng", "libreoffice", "libxml2"]);
+
+    let png = sandbox.brewer(&["--db", db, "__complete", "--command", "info", "--", "libp"]);
+    assert_eq!(lines(&png), ["libpng"]);
+}